@@ -3,18 +3,63 @@
 
 char LICENSE[] SEC("license") = "GPL";
 
+// bpf_get_current_pid_tgid() returns the tgid (the userspace "pid") in the
+// upper 32 bits and the kernel task id (the thread id) in the lower 32. Only
+// the tgid names a /proc/<pid> entry that is guaranteed to list the process.
 struct event {
-    __u32 pid;
+    __u32 tgid;
+    __u32 tid;
+    __u32 uid;
+    __s32 dfd;
+    __u32 kind;     // EVENT_OPEN or EVENT_UNLINK
     char comm[16];
     char filename[256];
 };
 
+#define EVENT_OPEN   0
+#define EVENT_UNLINK 1
+#define EVENT_RENAME 2
+
+#define AT_FDCWD -100
+
+// A rename carries both paths: `base.dfd`/`base.filename` is the old one.
+struct rename_event {
+    struct event base;
+    __s32 newdfd;
+    char newname[256];
+};
+
 struct {
     __uint(type, BPF_MAP_TYPE_PERF_EVENT_ARRAY);
     __uint(key_size, sizeof(__u32));
     __uint(value_size, sizeof(__u32));
 } events SEC(".maps");
 
+// Matched opens and unlinks waiting for their sys_exit, keyed by pid_tgid
+// (one in-flight syscall per thread). Only calls that succeed are reported.
+struct {
+    __uint(type, BPF_MAP_TYPE_HASH);
+    __uint(max_entries, 10240);
+    __type(key, __u64);
+    __type(value, struct event);
+} pending SEC(".maps");
+
+// Two paths don't fit on the 512-byte BPF stack; build renames here instead.
+struct {
+    __uint(type, BPF_MAP_TYPE_PERCPU_ARRAY);
+    __uint(max_entries, 1);
+    __type(key, __u32);
+    __type(value, struct rename_event);
+} rename_scratch SEC(".maps");
+
+// Matched renames waiting for their sys_exit, like `pending`.
+struct {
+    __uint(type, BPF_MAP_TYPE_HASH);
+    __uint(max_entries, 10240);
+    __type(key, __u64);
+    __type(value, struct rename_event);
+} pending_renames SEC(".maps");
+
 static __always_inline int match_etc(const char *p) {
     return p[0] == '/' && p[1] == 'e' && p[2] == 't' && p[3] == 'c' && p[4] == '/';
 }
@@ -55,28 +100,176 @@ static __always_inline int is_hdas(const char *p) {
     return 0;
 }
 
-SEC("tracepoint/syscalls/sys_enter_openat")
-int trace_openat(void *ctx) {
+// /etc/... or anything with a .cache, .local or .config component. Relative
+// names always pass: where they point depends on the dirfd or cwd, which only
+// userspace can resolve (e.g. "nvim/init.lua" from a cwd of ~/.config).
+static __always_inline int path_matches(const char *p) {
+    if (p[0] != '/') return 1;
+    if (match_etc(p)) return 1;
+    for (int i = 0; i < 200; i++) {
+        if (p[i] == '\0') break;
+        if (match_dot_dir(&p[i])) return 1;
+    }
+    return 0;
+}
+
+// Read the common (dfd, path) prefix of openat/unlinkat and stash the event
+// until sys_exit if the path is one we care about.
+static __always_inline int stash_matched(void *ctx, __u32 kind) {
     struct event e = {};
+    __u64 pid_tgid = bpf_get_current_pid_tgid();
 
-    e.pid = bpf_get_current_pid_tgid() >> 32;
+    e.tgid = pid_tgid >> 32;
+    e.tid = (__u32)pid_tgid;
+    e.uid = bpf_get_current_uid_gid() & 0xffffffff;
+    e.kind = kind;
     bpf_get_current_comm(&e.comm, sizeof(e.comm));
 
+    // sys_enter_openat / sys_enter_unlinkat args: dfd at +16, filename at +24
+    long dfd;
+    bpf_probe_read(&dfd, sizeof(dfd), ctx + 16);
+    e.dfd = (__s32)dfd;
+
     void *fname;
     bpf_probe_read(&fname, sizeof(fname), ctx + 24);
     bpf_probe_read_user_str(&e.filename, sizeof(e.filename), fname);
 
-    int matched = match_etc(e.filename);
-    if (!matched) {
-        for (int i = 0; i < 200; i++) {
-            if (e.filename[i] == '\0') break;
-            if (match_dot_dir(&e.filename[i])) { matched = 1; break; }
-        }
+    if (path_matches(e.filename) && !is_hdas(e.filename)) {
+        bpf_map_update_elem(&pending, &pid_tgid, &e, BPF_ANY);
     }
 
-    if (matched && !is_hdas(e.filename)) {
-        bpf_perf_event_output(ctx, &events, BPF_F_CURRENT_CPU, &e, sizeof(e));
+    return 0;
+}
+
+// Emit the stashed event if the syscall succeeded.
+static __always_inline int emit_on_success(void *ctx) {
+    __u64 pid_tgid = bpf_get_current_pid_tgid();
+    struct event *e = bpf_map_lookup_elem(&pending, &pid_tgid);
+    if (!e) return 0;
+
+    // sys_exit_* args: ret at +16
+    long ret;
+    bpf_probe_read(&ret, sizeof(ret), ctx + 16);
+
+    // Failed calls (ENOENT, EACCES, ...) never touched the file
+    if (ret >= 0) {
+        bpf_perf_event_output(ctx, &events, BPF_F_CURRENT_CPU, e, sizeof(*e));
     }
+    bpf_map_delete_elem(&pending, &pid_tgid);
 
     return 0;
 }
+
+SEC("tracepoint/syscalls/sys_enter_openat")
+int trace_openat(void *ctx) {
+    return stash_matched(ctx, EVENT_OPEN);
+}
+
+SEC("tracepoint/syscalls/sys_exit_openat")
+int trace_openat_exit(void *ctx) {
+    return emit_on_success(ctx);
+}
+
+// rm, find -delete and most programs using *at() calls remove files through
+// unlinkat; the older unlink/rmdir syscalls are left to `hdas prune`.
+SEC("tracepoint/syscalls/sys_enter_unlinkat")
+int trace_unlinkat(void *ctx) {
+    return stash_matched(ctx, EVENT_UNLINK);
+}
+
+SEC("tracepoint/syscalls/sys_exit_unlinkat")
+int trace_unlinkat_exit(void *ctx) {
+    return emit_on_success(ctx);
+}
+
+// Stash a rename if either side is a path we care about: moving a file out of
+// a monitored dir matters as much as moving one in. `at` says whether the
+// syscall takes directory fds (renameat, renameat2) or not (rename).
+static __always_inline int stash_rename(void *ctx, int at) {
+    __u32 zero = 0;
+    struct rename_event *e = bpf_map_lookup_elem(&rename_scratch, &zero);
+    if (!e) return 0;
+    __u64 pid_tgid = bpf_get_current_pid_tgid();
+
+    e->base.tgid = pid_tgid >> 32;
+    e->base.tid = (__u32)pid_tgid;
+    e->base.uid = bpf_get_current_uid_gid() & 0xffffffff;
+    e->base.kind = EVENT_RENAME;
+    bpf_get_current_comm(&e->base.comm, sizeof(e->base.comm));
+
+    // renameat/renameat2 args: olddfd at +16, oldname at +24, newdfd at +32,
+    // newname at +40. rename args: oldname at +16, newname at +24.
+    long olddfd = AT_FDCWD, newdfd = AT_FDCWD;
+    void *oldname, *newname;
+    if (at) {
+        bpf_probe_read(&olddfd, sizeof(olddfd), ctx + 16);
+        bpf_probe_read(&oldname, sizeof(oldname), ctx + 24);
+        bpf_probe_read(&newdfd, sizeof(newdfd), ctx + 32);
+        bpf_probe_read(&newname, sizeof(newname), ctx + 40);
+    } else {
+        bpf_probe_read(&oldname, sizeof(oldname), ctx + 16);
+        bpf_probe_read(&newname, sizeof(newname), ctx + 24);
+    }
+    e->base.dfd = (__s32)olddfd;
+    e->newdfd = (__s32)newdfd;
+    // The scratch entry is reused, so bytes past each NUL are stale; userspace
+    // reads both names only up to their terminator.
+    bpf_probe_read_user_str(&e->base.filename, sizeof(e->base.filename), oldname);
+    bpf_probe_read_user_str(&e->newname, sizeof(e->newname), newname);
+
+    if ((path_matches(e->base.filename) || path_matches(e->newname))
+        && !is_hdas(e->base.filename) && !is_hdas(e->newname)) {
+        bpf_map_update_elem(&pending_renames, &pid_tgid, e, BPF_ANY);
+    }
+
+    return 0;
+}
+
+static __always_inline int emit_rename_on_success(void *ctx) {
+    __u64 pid_tgid = bpf_get_current_pid_tgid();
+    struct rename_event *e = bpf_map_lookup_elem(&pending_renames, &pid_tgid);
+    if (!e) return 0;
+
+    long ret;
+    bpf_probe_read(&ret, sizeof(ret), ctx + 16);
+
+    if (ret == 0) {
+        bpf_perf_event_output(ctx, &events, BPF_F_CURRENT_CPU, e, sizeof(*e));
+    }
+    bpf_map_delete_elem(&pending_renames, &pid_tgid);
+
+    return 0;
+}
+
+SEC("tracepoint/syscalls/sys_enter_renameat2")
+int trace_renameat2(void *ctx) {
+    return stash_rename(ctx, 1);
+}
+
+SEC("tracepoint/syscalls/sys_exit_renameat2")
+int trace_renameat2_exit(void *ctx) {
+    return emit_rename_on_success(ctx);
+}
+
+// glibc's rename() and renameat() still use these older syscalls where the
+// architecture has them (x86_64 does, arm64 and riscv don't), and that's how
+// most atomic config writes land. Userspace attaches them when present.
+SEC("tracepoint/syscalls/sys_enter_renameat")
+int trace_renameat(void *ctx) {
+    return stash_rename(ctx, 1);
+}
+
+SEC("tracepoint/syscalls/sys_exit_renameat")
+int trace_renameat_exit(void *ctx) {
+    return emit_rename_on_success(ctx);
+}
+
+SEC("tracepoint/syscalls/sys_enter_rename")
+int trace_rename(void *ctx) {
+    return stash_rename(ctx, 0);
+}
+
+SEC("tracepoint/syscalls/sys_exit_rename")
+int trace_rename_exit(void *ctx) {
+    return emit_rename_on_success(ctx);
+}
@@ -0,0 +1,403 @@
+//! `clean --archive` and `restore-archive`: deleted targets are streamed into
+//! a zstd-compressed tarball under the data directory, with a JSON manifest
+//! of their records alongside, so a cautious cleanup can be undone later.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+
+use crate::db::{Database, FileRecord};
+use crate::query::{format_size, print_json};
+
+/// zstd level: a good ratio without making large cleans crawl.
+const ZSTD_LEVEL: i32 = 10;
+
+/// Directory holding archives and their manifests.
+pub fn archive_dir() -> PathBuf {
+    crate::db::data_dir().join("archive")
+}
+
+/// One path handed to [`write_archive`].
+pub(crate) struct ArchiveItem<'a> {
+    pub record: &'a FileRecord,
+    /// Paths inside a directory target that are being kept, so not archived
+    pub protected: &'a [PathBuf],
+}
+
+/// Sidecar `<name>.json` written next to `<name>.tar.zst`.
+#[derive(Serialize, Deserialize)]
+struct ArchiveManifest {
+    /// Unix seconds when the archive was written
+    archived_at: i64,
+    package: Option<String>,
+    /// Records of the archived targets, re-added on restore
+    files: Vec<FileRecord>,
+}
+
+/// Where [`write_archive`] put things.
+#[derive(Serialize)]
+pub(crate) struct ArchiveInfo {
+    pub path: PathBuf,
+    pub manifest: PathBuf,
+    /// Compressed size in bytes
+    pub size: u64,
+    /// Entries written, counting everything inside directories
+    pub entries: usize,
+}
+
+/// Archive name for a clean of `package` (or "stdin"), e.g. `firefox-20250218-221100`.
+pub(crate) fn archive_name(package: Option<&str>) -> String {
+    let label: String = package
+        .unwrap_or("stdin")
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || "._+-".contains(c) { c } else { '_' })
+        .collect();
+    format!("{}-{}", label, chrono::Local::now().format("%Y%m%d-%H%M%S"))
+}
+
+/// Tar member name for an absolute path: the path without its leading `/`,
+/// so a restore can put it back exactly where it was.
+fn member_name(path: &Path) -> &Path {
+    path.strip_prefix("/").unwrap_or(path)
+}
+
+/// Give a file written as root (under sudo) to the user whose home it's in.
+/// A symlink itself is changed, not its target.
+fn chown_to_user(path: &Path) {
+    if let (_, Some(uid), Some(gid)) = crate::db::get_user_info() {
+        let _ = std::os::unix::fs::lchown(path, Some(uid), Some(gid));
+    }
+}
+
+/// Stream `items` into `<archive_dir>/<name>.tar.zst` and write the manifest.
+/// Symlinks are stored as links, and directories are walked without
+/// following links. Nothing is deleted here; on error the partial archive is
+/// removed so the caller can abort before touching the originals.
+pub(crate) fn write_archive(name: &str, package: Option<&str>, items: &[ArchiveItem]) -> Result<ArchiveInfo> {
+    let dir = archive_dir();
+    let (_, uid, gid) = crate::db::get_user_info();
+    crate::db::create_dir_all_with_owner(&dir, uid, gid)?;
+
+    let path = dir.join(format!("{}.tar.zst", name));
+    let manifest_path = dir.join(format!("{}.json", name));
+
+    let result = (|| -> Result<usize> {
+        let file = std::fs::File::create_new(&path)?;
+        let encoder = zstd::stream::write::Encoder::new(file, ZSTD_LEVEL)?;
+        let mut builder = tar::Builder::new(encoder);
+        builder.follow_symlinks(false);
+
+        let mut entries = 0;
+        for item in items {
+            let root = Path::new(&item.record.path);
+            for entry in walkdir::WalkDir::new(root).follow_links(false) {
+                let entry = entry?;
+                let path = entry.path();
+                if item.protected.iter().any(|k| path.starts_with(k)) {
+                    continue;
+                }
+                builder
+                    .append_path_with_name(path, member_name(path))
+                    .map_err(|e| anyhow::anyhow!("Could not archive {}: {}", path.display(), e))?;
+                entries += 1;
+            }
+        }
+
+        builder.into_inner()?.finish()?;
+        Ok(entries)
+    })();
+
+    let entries = match result {
+        Ok(entries) => entries,
+        Err(e) => {
+            let _ = std::fs::remove_file(&path);
+            return Err(e);
+        }
+    };
+
+    let manifest = ArchiveManifest {
+        archived_at: chrono::Utc::now().timestamp(),
+        package: package.map(str::to_string),
+        files: items.iter().map(|i| i.record.clone()).collect(),
+    };
+    std::fs::write(&manifest_path, serde_json::to_string_pretty(&manifest)? + "\n")?;
+    chown_to_user(&path);
+    chown_to_user(&manifest_path);
+
+    let size = path.metadata().map(|m| m.len()).unwrap_or(0);
+    Ok(ArchiveInfo { path, manifest: manifest_path, size, entries })
+}
+
+/// Accept either a path to an archive or a bare name in [`archive_dir`],
+/// with or without the `.tar.zst` extension. An archive anywhere else is
+/// refused unless `allow_external`, since a restore may run as root.
+fn resolve_archive(name: &str, allow_external: bool) -> Result<PathBuf> {
+    let candidates = [
+        PathBuf::from(name),
+        archive_dir().join(name),
+        archive_dir().join(format!("{}.tar.zst", name)),
+    ];
+    let path = candidates
+        .into_iter()
+        .find(|p| p.is_file())
+        .ok_or_else(|| anyhow::anyhow!("No archive named {} (see `hdas restore-archive` for the list)", name))?;
+
+    let inside = match (path.canonicalize(), archive_dir().canonicalize()) {
+        (Ok(path), Ok(dir)) => path.starts_with(dir),
+        _ => false,
+    };
+    if !inside && !allow_external {
+        anyhow::bail!(
+            "{} is not in {}; pass --allow-external to restore it anyway",
+            path.display(),
+            archive_dir().display()
+        );
+    }
+    Ok(path)
+}
+
+/// Directories a restore may write into: the user's home and the monitored
+/// dirs, canonicalized so symlinked ones compare by their targets.
+fn restore_roots() -> Result<Vec<PathBuf>> {
+    let home = crate::db::get_user_home();
+    let config = crate::config::Config::load()?;
+    let mut roots = vec![home.clone()];
+    roots.extend(config.monitored_dir_paths(&home));
+    Ok(roots.iter().filter_map(|r| r.canonicalize().ok()).collect())
+}
+
+/// Whether `dest` lands under one of `roots` once its deepest existing
+/// ancestor is resolved, so a symlink already on disk (or planted by an
+/// earlier member) can't carry a write outside them.
+fn resolves_under(dest: &Path, roots: &[PathBuf]) -> bool {
+    let (Some(mut ancestor), Some(name)) = (dest.parent(), dest.file_name()) else {
+        return false;
+    };
+    let mut tail = PathBuf::from(name);
+    while ancestor.symlink_metadata().is_err() {
+        let (Some(parent), Some(name)) = (ancestor.parent(), ancestor.file_name()) else {
+            return false;
+        };
+        tail = Path::new(name).join(tail);
+        ancestor = parent;
+    }
+    let Ok(real) = ancestor.canonicalize() else {
+        return false;
+    };
+    let real = real.join(tail);
+    roots.iter().any(|r| real.starts_with(r))
+}
+
+/// Create the missing parents of `dest`, handing the ones under `home` to
+/// the user as [`chown_to_user`] does for the restored files.
+fn create_parents(dest: &Path, home: &Path) -> std::io::Result<()> {
+    let Some(parent) = dest.parent() else {
+        return Ok(());
+    };
+    let missing: Vec<&Path> = parent.ancestors().take_while(|a| a.symlink_metadata().is_err()).collect();
+    std::fs::create_dir_all(parent)?;
+    for dir in missing.into_iter().filter(|d| d.starts_with(home)) {
+        chown_to_user(dir);
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct ArchiveListing {
+    name: String,
+    path: PathBuf,
+    size: u64,
+    package: Option<String>,
+    archived_at: Option<i64>,
+    files: Option<usize>,
+}
+
+/// `restore-archive` with no argument: the archives available to restore.
+fn list_archives(json: bool) -> Result<()> {
+    let mut archives = Vec::new();
+    if let Ok(entries) = std::fs::read_dir(archive_dir()) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(name) = path.file_name().and_then(|n| n.to_str()).and_then(|n| n.strip_suffix(".tar.zst")) else {
+                continue;
+            };
+            let manifest: Option<ArchiveManifest> = std::fs::read_to_string(path.with_file_name(format!("{}.json", name)))
+                .ok()
+                .and_then(|c| serde_json::from_str(&c).ok());
+            archives.push(ArchiveListing {
+                name: name.to_string(),
+                size: entry.metadata().map(|m| m.len()).unwrap_or(0),
+                package: manifest.as_ref().and_then(|m| m.package.clone()),
+                archived_at: manifest.as_ref().map(|m| m.archived_at),
+                files: manifest.as_ref().map(|m| m.files.len()),
+                path,
+            });
+        }
+    }
+    archives.sort_by(|a, b| a.name.cmp(&b.name));
+
+    if json {
+        return print_json(&archives);
+    }
+    if archives.is_empty() {
+        println!("No archives in {} (create one with `hdas clean PKG --archive`)", archive_dir().display());
+        return Ok(());
+    }
+    println!("Archives in {}:\n", archive_dir().display());
+    for a in &archives {
+        let files = a.files.map_or_else(|| "no manifest".to_string(), |n| format!("{} path(s)", n));
+        println!("  {:<40} {:>9}  {}", a.name, format_size(a.size), files);
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct RestoreResult {
+    archive: PathBuf,
+    restored: Vec<String>,
+    /// Paths that already exist and were left alone (without --overwrite)
+    skipped: Vec<String>,
+    /// Members outside the home and monitored dirs, or of a type `clean
+    /// --archive` never writes (hard links, devices)
+    refused: Vec<String>,
+    records_restored: usize,
+    dry_run: bool,
+}
+
+/// Extract an archive written by `clean --archive` back to the original
+/// locations and re-add the database records of the restored targets.
+/// Existing paths are skipped unless `overwrite`. Only members under the
+/// user's home or a monitored dir are extracted, and only from archives in
+/// [`archive_dir`] unless `allow_external`.
+pub fn restore_archive(
+    name: Option<&str>,
+    dry_run: bool,
+    overwrite: bool,
+    allow_external: bool,
+    json: bool,
+) -> Result<()> {
+    let Some(name) = name else {
+        return list_archives(json);
+    };
+    let path = resolve_archive(name, allow_external)?;
+    let roots = restore_roots()?;
+    let home = crate::db::get_user_home();
+
+    let mut restored = Vec::new();
+    let mut skipped = Vec::new();
+    let mut refused = Vec::new();
+    let file = std::fs::File::open(&path)?;
+    let mut archive = tar::Archive::new(zstd::stream::read::Decoder::new(file)?);
+    // Mode bits are kept but setuid/setgid aren't, and owners from the
+    // archive are ignored; files under home are given to the user instead
+    archive.set_preserve_permissions(false);
+    archive.set_preserve_mtime(true);
+    archive.set_preserve_ownerships(false);
+    archive.set_overwrite(overwrite);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let member = entry.path()?.into_owned();
+        let dest = Path::new("/").join(&member);
+        let kind = entry.header().entry_type();
+        let is_dir = kind.is_dir();
+
+        let plain = member.components().all(|c| matches!(c, Component::Normal(_)));
+        let supported = kind.is_file() || is_dir || kind.is_symlink();
+        if !plain || !supported || !resolves_under(&dest, &roots) {
+            refused.push(dest.to_string_lossy().into_owned());
+            continue;
+        }
+
+        let exists = dest.symlink_metadata().is_ok();
+
+        // Existing directories are merged into, not conflicts
+        if exists && is_dir {
+            continue;
+        }
+        if exists && !overwrite {
+            skipped.push(dest.to_string_lossy().into_owned());
+            continue;
+        }
+        if !dry_run {
+            create_parents(&dest, &home)?;
+            entry
+                .unpack(&dest)
+                .map_err(|e| anyhow::anyhow!("Could not restore {}: {}", dest.display(), e))?;
+            if dest.starts_with(&home) {
+                chown_to_user(&dest);
+            }
+        }
+        restored.push(dest.to_string_lossy().into_owned());
+    }
+
+    let manifest: Option<ArchiveManifest> = std::fs::read_to_string(path.with_extension("").with_extension("json"))
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok());
+    let mut records_restored = 0;
+    if let Some(manifest) = &manifest {
+        let restored_set: std::collections::HashSet<&str> = restored.iter().map(String::as_str).collect();
+        let db = (!dry_run).then(Database::new).transpose()?;
+        for record in manifest.files.iter().filter(|r| restored_set.contains(r.path.as_str())) {
+            match &db {
+                Some(db) => records_restored += db.restore_record(record)? as usize,
+                None => records_restored += 1,
+            }
+        }
+    }
+
+    let result = RestoreResult { archive: path, restored, skipped, refused, records_restored, dry_run };
+    if json {
+        return print_json(&result);
+    }
+
+    let verb = if dry_run { "Would restore" } else { "Restored" };
+    for p in &result.restored {
+        println!("{}: {}", verb, p);
+    }
+    for p in &result.skipped {
+        println!("Skipped (already exists): {}", p);
+    }
+    for p in &result.refused {
+        println!("Refused (outside home and monitored dirs): {}", p);
+    }
+    println!();
+    println!(
+        "{} {} path(s) from {}, skipped {}, refused {}; {} {} database record(s)",
+        verb,
+        result.restored.len(),
+        result.archive.display(),
+        result.skipped.len(),
+        result.refused.len(),
+        if dry_run { "would re-add" } else { "re-added" },
+        result.records_restored
+    );
+    if manifest.is_none() {
+        println!("(no manifest found next to the archive, so no records were re-added)");
+    }
+    if dry_run {
+        println!("(dry run - nothing was extracted)");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restore_stays_under_its_roots() {
+        let tmp = tempfile::tempdir().unwrap();
+        let home = tmp.path().join("home");
+        let outside = tmp.path().join("outside");
+        std::fs::create_dir_all(home.join(".config")).unwrap();
+        std::fs::create_dir(&outside).unwrap();
+        std::os::unix::fs::symlink(&outside, home.join(".config/link")).unwrap();
+        let roots = vec![home.canonicalize().unwrap()];
+
+        assert!(resolves_under(&home.join(".config/app/new/file"), &roots));
+        assert!(resolves_under(&home.join(".config/link"), &roots));
+        assert!(!resolves_under(&home.join(".config/link/file"), &roots));
+        assert!(!resolves_under(&outside.join("file"), &roots));
+    }
+}
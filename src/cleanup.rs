@@ -1,17 +1,14 @@
 use anyhow::Result;
 use owo_colors::OwoColorize;
-use serde::Serialize;
-use std::io::IsTerminal;
-use std::path::Path;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
 
 use crate::db::{Database, FileRecord};
-use crate::query::format_size;
+use crate::query::{format_size, print_json, use_color, PhaseTimer};
 
-fn use_color() -> bool {
-    std::io::stdout().is_terminal()
-}
-
-fn get_path_size(path: &Path) -> u64 {
+pub(crate) fn get_path_size(path: &Path) -> u64 {
     if path.is_file() {
         path.metadata().map(|m| m.len()).unwrap_or(0)
     } else if path.is_dir() {
@@ -32,6 +29,10 @@ struct CleanTarget {
     size: u64,
     is_dir: bool,
     is_symlink: bool,
+    /// Paths inside a directory target that match `--exclude` and must survive
+    protected: Vec<PathBuf>,
+    /// (dev, inode, nlink) for regular files with more than one hard link
+    hardlink: Option<(u64, u64, u64)>,
 }
 
 impl CleanTarget {
@@ -44,11 +45,176 @@ impl CleanTarget {
         let is_symlink = meta.file_type().is_symlink();
         let is_dir = !is_symlink && path.is_dir();
         let size = if is_symlink { 0 } else { get_path_size(path) };
-        Some(CleanTarget { record, size, is_dir, is_symlink })
+        let hardlink = (meta.is_file() && meta.nlink() > 1).then(|| (meta.dev(), meta.ino(), meta.nlink()));
+        Some(CleanTarget { record, size, is_dir, is_symlink, protected: vec![], hardlink })
+    }
+}
+
+/// How many clean targets point at each hardlinked (dev, inode).
+type LinkGroups = HashMap<(u64, u64), u64>;
+
+fn hardlink_groups<'a>(targets: impl Iterator<Item = &'a CleanTarget>) -> LinkGroups {
+    let mut groups = LinkGroups::new();
+    for (dev, ino, _) in targets.filter_map(|t| t.hardlink) {
+        *groups.entry((dev, ino)).or_default() += 1;
+    }
+    groups
+}
+
+/// Space actually freed by deleting `targets`: each hardlinked inode is
+/// counted once, and not at all if links outside the targets keep it alive.
+fn reclaimable_size<'a>(targets: impl Iterator<Item = &'a CleanTarget>, groups: &LinkGroups) -> u64 {
+    let mut seen = std::collections::HashSet::new();
+    targets
+        .filter(|t| match t.hardlink {
+            None => true,
+            Some((dev, ino, nlink)) => seen.insert((dev, ino)) && groups[&(dev, ino)] >= nlink,
+        })
+        .map(|t| t.size)
+        .sum()
+}
+
+/// `[total]`, or `[total, reclaimable reclaimable]` when hard links make them differ.
+fn format_totals(total: u64, reclaimable: u64) -> String {
+    if total == reclaimable {
+        format_size(total)
+    } else {
+        format!("{}, {} reclaimable", format_size(total), format_size(reclaimable))
+    }
+}
+
+/// Name of the per-directory keep file honored by `clean`.
+const KEEP_FILE: &str = ".hdaskeep";
+
+/// Parsed `.hdaskeep` files, keyed by the directory containing them.
+type KeepCache = HashMap<PathBuf, Vec<glob::Pattern>>;
+
+/// Globs from `dir/.hdaskeep`: one per line, blank lines and `#` comments
+/// ignored. Empty if there is no keep file.
+fn keep_patterns<'a>(dir: &Path, cache: &'a mut KeepCache) -> &'a [glob::Pattern] {
+    cache.entry(dir.to_path_buf()).or_insert_with(|| {
+        let keep_file = dir.join(KEEP_FILE);
+        let Ok(content) = std::fs::read_to_string(&keep_file) else {
+            return vec![];
+        };
+        content
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .filter_map(|l| match glob::Pattern::new(l.trim_start_matches('/')) {
+                Ok(p) => Some(p),
+                Err(e) => {
+                    eprintln!("Warning: {}: ignoring invalid pattern '{}': {}", keep_file.display(), l, e);
+                    None
+                }
+            })
+            .collect()
+    })
+}
+
+/// True if a `.hdaskeep` in one of `path`'s ancestors, up to and including
+/// `root`, lists it. Patterns containing `/` match the path relative to the
+/// keep file's directory; others match the file name at any depth. Keep
+/// files themselves are always kept.
+fn kept_by_keep_file(path: &Path, root: &Path, cache: &mut KeepCache) -> bool {
+    if path.file_name().is_some_and(|n| n == KEEP_FILE) {
+        return true;
+    }
+    for dir in path.ancestors().skip(1) {
+        if let Ok(rel) = path.strip_prefix(dir) {
+            let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+            let kept = keep_patterns(dir, cache).iter().any(|p| {
+                if p.as_str().contains('/') {
+                    p.matches_path(rel)
+                } else {
+                    p.matches(&name)
+                }
+            });
+            if kept {
+                return true;
+            }
+        }
+        if dir == root {
+            break;
+        }
+    }
+    false
+}
+
+/// Compiled `--exclude` globs, matched against full paths, plus any
+/// `.hdaskeep` files found next to or inside the targets.
+struct ExcludeSet {
+    patterns: Vec<glob::Pattern>,
+}
+
+impl ExcludeSet {
+    fn new(globs: &[String]) -> Result<Self> {
+        let patterns = globs
+            .iter()
+            .map(|g| {
+                let expanded = if g.starts_with('~') {
+                    crate::db::expand_user_path(g)?
+                } else {
+                    g.clone()
+                };
+                glob::Pattern::new(&expanded)
+                    .map_err(|e| anyhow::anyhow!("Invalid --exclude pattern '{}': {}", g, e))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { patterns })
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        self.patterns.iter().any(|p| p.matches_path(path))
+    }
+
+    /// Apply the exclude patterns and keep files to one target: a matching
+    /// target is dropped, and matching paths inside a directory target are
+    /// marked protected. Returns the surviving target and the number of
+    /// preserved paths.
+    fn apply(&self, mut target: CleanTarget) -> (Option<CleanTarget>, usize) {
+        let path = PathBuf::from(&target.record.path);
+        let root = path.parent().unwrap_or(&path).to_path_buf();
+        let mut keep_cache = KeepCache::new();
+
+        if self.matches(&path) || kept_by_keep_file(&path, &root, &mut keep_cache) {
+            return (None, 1);
+        }
+        if target.is_dir {
+            let mut walker = walkdir::WalkDir::new(&path).min_depth(1).into_iter();
+            while let Some(Ok(entry)) = walker.next() {
+                if self.matches(entry.path()) || kept_by_keep_file(entry.path(), &root, &mut keep_cache) {
+                    if entry.file_type().is_dir() {
+                        walker.skip_current_dir();
+                    }
+                    target.size = target.size.saturating_sub(get_path_size(entry.path()));
+                    target.protected.push(entry.into_path());
+                }
+            }
+        }
+        let preserved = target.protected.len();
+        (Some(target), preserved)
+    }
+}
+
+/// Remove everything under `dir` except the protected paths and their ancestors.
+fn remove_dir_except(dir: &Path, protected: &[PathBuf]) -> std::io::Result<()> {
+    for entry in walkdir::WalkDir::new(dir).min_depth(1).contents_first(true) {
+        let entry = entry?;
+        let path = entry.path();
+        if protected.iter().any(|k| path.starts_with(k) || k.starts_with(path)) {
+            continue;
+        }
+        if entry.file_type().is_dir() {
+            std::fs::remove_dir(path)?;
+        } else {
+            std::fs::remove_file(path)?;
+        }
     }
+    Ok(())
 }
 
-fn display_target(target: &CleanTarget) {
+fn display_target(target: &CleanTarget, groups: &LinkGroups) {
     let color = use_color();
     let type_indicator = if target.is_symlink {
         "link"
@@ -58,6 +224,20 @@ fn display_target(target: &CleanTarget) {
         "file"
     };
     let size = format_size(target.size);
+    let mut keeping = if target.protected.is_empty() {
+        String::new()
+    } else {
+        format!(" (keeping {} excluded)", target.protected.len())
+    };
+    if let Some((dev, ino, nlink)) = target.hardlink {
+        let others = groups.get(&(dev, ino)).copied().unwrap_or(1) - 1;
+        if others > 0 {
+            keeping.push_str(&format!(" (shares data with {} other tracked file(s))", others));
+        }
+        if nlink > others + 1 {
+            keeping.push_str(&format!(" (hard link; {} other link(s) keep the data)", nlink - others - 1));
+        }
+    }
 
     if color {
         let type_colored = if target.is_symlink {
@@ -67,38 +247,85 @@ fn display_target(target: &CleanTarget) {
         } else {
             type_indicator.to_string()
         };
-        println!("  [{:>6}] [{}] {}", size.dimmed(), type_colored, target.record.path);
+        println!("  [{:>8}] [{}] {}{}", size.dimmed(), type_colored, target.record.path, keeping.dimmed());
     } else {
-        println!("  [{:>6}] [{}] {}", size, type_indicator, target.record.path);
+        println!("  [{:>8}] [{}] {}{}", size, type_indicator, target.record.path, keeping);
     }
 }
 
-fn confirm_prompt() -> Result<bool> {
+/// Ask for confirmation. When stdin is already consumed (e.g. a piped path
+/// list), `from_tty` reads the answer from the controlling terminal instead.
+/// With `word`, only that exact word confirms (large cleans, see
+/// `confirm_word` in the config).
+pub(crate) fn confirm_prompt(from_tty: bool, word: Option<&str>) -> Result<bool> {
     let color = use_color();
+    let prompt = match word {
+        Some(word) => format!("This is a large deletion. Type '{}' to proceed: ", word),
+        None => "Proceed? [y/N]: ".to_string(),
+    };
     if color {
-        print!("{}", "Proceed? [y/N]: ".bold());
+        print!("{}", prompt.bold());
     } else {
-        print!("Proceed? [y/N]: ");
+        print!("{}", prompt);
     }
     use std::io::{self, BufRead, Write};
     io::stdout().flush()?;
     let mut line = String::new();
-    io::stdin().lock().read_line(&mut line)?;
+    if from_tty {
+        let tty = std::fs::File::open("/dev/tty")?;
+        io::BufReader::new(tty).read_line(&mut line)?;
+    } else {
+        io::stdin().lock().read_line(&mut line)?;
+    }
+    if let Some(word) = word {
+        return Ok(line.trim() == word);
+    }
     let response = line.trim().to_lowercase();
     Ok(response == "y" || response == "yes")
 }
 
+fn kind_name(is_symlink: bool, is_dir: bool) -> &'static str {
+    if is_symlink {
+        "symlink"
+    } else if is_dir {
+        "directory"
+    } else {
+        "file"
+    }
+}
+
+/// Re-stat a target right before deleting it. The preview (and any prompt)
+/// may be long past, and acting on a stale type could `remove_dir_all` what
+/// is now a directory where a file was previewed, or the other way round.
+fn verify_type_unchanged(target: &CleanTarget) -> std::io::Result<()> {
+    let file_type = Path::new(&target.record.path).symlink_metadata()?.file_type();
+    let (is_symlink, is_dir) = (file_type.is_symlink(), file_type.is_dir());
+    if is_symlink != target.is_symlink || is_dir != target.is_dir {
+        return Err(std::io::Error::other(format!(
+            "changed from {} to {} since the preview; skipped",
+            kind_name(target.is_symlink, target.is_dir),
+            kind_name(is_symlink, is_dir)
+        )));
+    }
+    Ok(())
+}
+
 fn run_deletions<'a>(
     targets: impl Iterator<Item = &'a CleanTarget>,
     json: bool,
 ) -> (Vec<String>, Vec<CleanError>) {
+    let _t = PhaseTimer::start("deletion");
     let mut deleted_paths = Vec::new();
     let mut errors = Vec::new();
 
     for target in targets {
         let path = Path::new(&target.record.path);
-        let result = if target.is_symlink {
+        let result = if let Err(e) = verify_type_unchanged(target) {
+            Err(e)
+        } else if target.is_symlink {
             std::fs::remove_file(path)
+        } else if target.is_dir && !target.protected.is_empty() {
+            remove_dir_except(path, &target.protected)
         } else if target.is_dir {
             std::fs::remove_dir_all(path)
         } else {
@@ -106,6 +333,12 @@ fn run_deletions<'a>(
         };
 
         match result {
+            Ok(_) if !target.protected.is_empty() => {
+                // The directory itself survives, so its record stays too
+                if !json {
+                    println!("Cleaned (kept {} excluded): {}", target.protected.len(), target.record.path);
+                }
+            }
             Ok(_) => {
                 if !json {
                     if target.is_symlink {
@@ -131,6 +364,12 @@ fn run_deletions<'a>(
     (deleted_paths, errors)
 }
 
+fn print_preserved(preserved: usize) {
+    if preserved > 0 {
+        println!("\nPreserving {} path(s) matching --exclude or .hdaskeep", preserved);
+    }
+}
+
 fn print_summary(deleted_count: usize, error_count: usize, records_removed: usize) {
     let color = use_color();
     println!();
@@ -154,9 +393,12 @@ struct CleanPreview {
     package: Option<String>,
     targets: Vec<CleanTargetInfo>,
     total_size: u64,
+    /// `total_size` with shared hardlinked data counted once
+    reclaimable_size: u64,
     file_count: usize,
     dir_count: usize,
     symlink_count: usize,
+    preserved: usize,
 }
 
 #[derive(Serialize)]
@@ -165,6 +407,29 @@ struct CleanTargetInfo {
     size: u64,
     is_dir: bool,
     is_symlink: bool,
+    /// Other targets that are hard links to the same data
+    #[serde(skip_serializing_if = "is_zero")]
+    shared_with: u64,
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+impl CleanTargetInfo {
+    fn new(target: &CleanTarget, groups: &LinkGroups) -> Self {
+        let shared_with = target
+            .hardlink
+            .and_then(|(dev, ino, _)| groups.get(&(dev, ino)))
+            .map_or(0, |n| n - 1);
+        Self {
+            path: target.record.path.clone(),
+            size: target.size,
+            is_dir: target.is_dir,
+            is_symlink: target.is_symlink,
+            shared_with,
+        }
+    }
 }
 
 #[derive(Serialize)]
@@ -172,6 +437,9 @@ struct CleanResult {
     deleted: Vec<String>,
     errors: Vec<CleanError>,
     records_removed: usize,
+    /// `--archive`: where the deleted targets were saved
+    #[serde(skip_serializing_if = "Option::is_none")]
+    archive: Option<crate::archive::ArchiveInfo>,
 }
 
 #[derive(Serialize)]
@@ -180,66 +448,386 @@ struct CleanError {
     error: String,
 }
 
-pub fn clean_package(package: &str, force: bool, dry_run: bool, json: bool) -> Result<()> {
+/// The part of a `package --export-manifest` file that `clean` reads back.
+#[derive(Deserialize)]
+struct ExportedManifest {
+    files: Vec<ExportedFile>,
+}
+
+#[derive(Deserialize)]
+struct ExportedFile {
+    path: String,
+}
+
+/// `clean --report` file: the `CleanResult` plus what each deleted path was.
+#[derive(Serialize)]
+struct CleanManifest<'a> {
+    /// Unix seconds when the deletion ran
+    cleaned_at: i64,
+    package: Option<&'a str>,
+    #[serde(flatten)]
+    result: &'a CleanResult,
+    items: Vec<ManifestItem<'a>>,
+}
+
+#[derive(Serialize)]
+struct ManifestItem<'a> {
+    path: &'a str,
+    size: u64,
+    is_dir: bool,
+    is_symlink: bool,
+    created_by_package: &'a str,
+    created_by_process: &'a str,
+    created_at: i64,
+    last_accessed_at: i64,
+}
+
+fn write_report(
+    path: &Path,
+    package: Option<&str>,
+    result: &CleanResult,
+    targets: &[CleanTarget],
+) -> Result<()> {
+    let deleted: std::collections::HashSet<&str> = result.deleted.iter().map(String::as_str).collect();
+    let manifest = CleanManifest {
+        cleaned_at: chrono::Utc::now().timestamp(),
+        package,
+        result,
+        items: targets
+            .iter()
+            .filter(|t| deleted.contains(t.record.path.as_str()))
+            .map(|t| ManifestItem {
+                path: &t.record.path,
+                size: t.size,
+                is_dir: t.is_dir,
+                is_symlink: t.is_symlink,
+                created_by_package: &t.record.created_by_package,
+                created_by_process: &t.record.created_by_process,
+                created_at: t.record.created_at,
+                last_accessed_at: t.record.last_accessed_at,
+            })
+            .collect(),
+    };
+    let content = serde_json::to_string_pretty(&manifest)?;
+    std::fs::write(path, content + "\n")
+        .map_err(|e| anyhow::anyhow!("Could not write report {}: {}", path.display(), e))
+}
+
+/// Flags shared by the preview / confirm / delete flow.
+struct CleanFlags<'a> {
+    force: bool,
+    dry_run: bool,
+    json: bool,
+    /// Read the confirmation from /dev/tty because stdin carries input
+    confirm_from_tty: bool,
+    /// Write a JSON manifest of the deletion here
+    report: Option<&'a Path>,
+    /// Refuse to run if a target sits on a missing or read-only mount
+    check_mounts: bool,
+    /// `package --purge`: also forget every record of the package, including
+    /// files that failed to delete or were already gone
+    purge: bool,
+    /// VACUUM the database afterwards (with `purge`)
+    vacuum: bool,
+    /// Stream the targets into a compressed archive before deleting them
+    archive: bool,
+}
+
+/// Mount points listed in /etc/fstab (swap and pseudo entries skipped).
+fn fstab_mount_points() -> Vec<String> {
+    std::fs::read_to_string("/etc/fstab")
+        .unwrap_or_default()
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .map(crate::monitor::unescape_mountinfo)
+        .filter(|mount_point| mount_point.starts_with('/'))
+        .collect()
+}
+
+/// Why deleting `paths` would be unsafe given the current mounts: a path
+/// under an fstab mount point that isn't mounted (an offline disk's empty
+/// placeholder would be cleaned instead), on a read-only mount, or itself a
+/// mount point (removing it would empty the mounted filesystem).
+fn mount_problems<'a>(paths: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mounts = match crate::monitor::read_mountinfo("self") {
+        Some(mounts) => mounts,
+        None => return vec![],
+    };
+    let fstab = fstab_mount_points();
+    let mut problems = Vec::new();
+
+    for path in paths {
+        let mounted = mounts
+            .iter()
+            .filter(|m| crate::monitor::strip_path_prefix(path, &m.mount_point).is_some())
+            .max_by_key(|m| m.mount_point.len());
+        let mounted_len = mounted.map_or(0, |m| m.mount_point.trim_end_matches('/').len());
+
+        if let Some(missing) = fstab.iter().find(|fp| {
+            fp.trim_end_matches('/').len() > mounted_len
+                && crate::monitor::strip_path_prefix(path, fp).is_some()
+        }) {
+            problems.push(format!("{}: {} is in /etc/fstab but not mounted", path, missing));
+        } else if let Some(mount) = mounted {
+            if mount.mount_point.trim_end_matches('/') == path.trim_end_matches('/') && path != "/" {
+                problems.push(format!("{}: is a mount point", path));
+            } else if mount.read_only {
+                problems.push(format!("{}: {} is mounted read-only", path, mount.mount_point));
+            }
+        }
+    }
+    problems
+}
+
+/// Abort the clean when [`mount_problems`] finds anything.
+fn ensure_mounts_safe<'a>(paths: impl Iterator<Item = &'a str>) -> Result<()> {
+    let problems = mount_problems(paths);
+    if problems.is_empty() {
+        return Ok(());
+    }
+    const SHOWN: usize = 10;
+    let mut msg = format!("Refusing to clean: {} path(s) on missing or unsafe mounts:", problems.len());
+    for problem in problems.iter().take(SHOWN) {
+        msg.push_str(&format!("\n  {}", problem));
+    }
+    if problems.len() > SHOWN {
+        msg.push_str(&format!("\n  ... and {} more", problems.len() - SHOWN));
+    }
+    msg.push_str("\nFix the mounts or --exclude those paths, then try again.");
+    Err(anyhow::anyhow!(msg))
+}
+
+/// Command-line options of `clean`, for a package or paths from stdin.
+pub struct CleanOptions<'a> {
+    pub force: bool,
+    pub dry_run: bool,
+    /// `--report FILE`
+    pub report: Option<&'a Path>,
+    /// `--check-mounts`
+    pub check_mounts: bool,
+    /// `--archive`
+    pub archive: bool,
+}
+
+impl<'a> CleanFlags<'a> {
+    fn from_options(opts: &CleanOptions<'a>, confirm_from_tty: bool, json: bool) -> Self {
+        CleanFlags {
+            force: opts.force,
+            dry_run: opts.dry_run,
+            json,
+            confirm_from_tty,
+            report: opts.report,
+            check_mounts: opts.check_mounts,
+            purge: false,
+            vacuum: false,
+            archive: opts.archive,
+        }
+    }
+}
+
+pub fn clean_package(package: &str, exclude: &[String], opts: &CleanOptions, json: bool) -> Result<()> {
+    clean_package_with(package, exclude, &CleanFlags::from_options(opts, false, json))
+}
+
+/// `package --purge`: delete the package's files, then forget all of its
+/// records (even for files that couldn't be deleted) and optionally vacuum,
+/// as if it had never been installed.
+pub fn purge_package(package: &str, force: bool, dry_run: bool, vacuum: bool, json: bool) -> Result<()> {
+    let flags = CleanFlags {
+        force,
+        dry_run,
+        json,
+        confirm_from_tty: false,
+        report: None,
+        check_mounts: false,
+        purge: true,
+        vacuum,
+        archive: false,
+    };
+    clean_package_with(package, &[], &flags)
+}
+
+fn clean_package_with(package: &str, exclude: &[String], flags: &CleanFlags) -> Result<()> {
+    let json = flags.json;
+    let excludes = ExcludeSet::new(exclude)?;
     let db = Database::new()?;
-    let records = db.query_package(package)?;
+    let records = {
+        let _t = PhaseTimer::start("db query");
+        db.query_package(package)?
+    };
+    let record_count = records.len();
 
+    let t = PhaseTimer::start("existence checks + sizes");
+    let bar = crate::progress::bar(records.len() as u64, "Computing sizes");
+    let mut preserved = 0;
     let targets: Vec<_> = records
         .into_iter()
-        .filter_map(CleanTarget::from_record)
+        .filter_map(|record| {
+            bar.inc(1);
+            CleanTarget::from_record(record)
+        })
+        .filter_map(|t| {
+            let (target, kept) = excludes.apply(t);
+            preserved += kept;
+            target
+        })
         .collect();
+    bar.finish_and_clear();
+    drop(t);
 
-    if targets.is_empty() {
+    // A purge still has records to forget when every file is already gone
+    if targets.is_empty() && !(flags.purge && record_count > 0) {
         if json {
             let result = CleanPreview {
                 package: Some(package.to_string()),
                 targets: vec![],
                 total_size: 0,
+                reclaimable_size: 0,
                 file_count: 0,
                 dir_count: 0,
                 symlink_count: 0,
+                preserved,
             };
-            println!("{}", serde_json::to_string_pretty(&result)?);
+            print_json(&result)?;
         } else {
             println!("No existing files found for package: {}", package);
         }
         return Ok(());
     }
 
+    clean_targets(&db, &targets, Some(package), preserved, flags)
+}
+
+/// Delete tracked paths read from stdin, one per line. Paths with no
+/// database record are reported and skipped.
+pub fn clean_from_stdin(exclude: &[String], opts: &CleanOptions, json: bool) -> Result<()> {
+    use std::io::Read;
+
+    let excludes = ExcludeSet::new(exclude)?;
+    let db = Database::new()?;
+    let mut targets = Vec::new();
+    let mut untracked = Vec::new();
+    let mut preserved = 0;
+
+    let mut input = String::new();
+    std::io::stdin().lock().read_to_string(&mut input)?;
+    // Tracked paths are absolute, so a leading '{' can only be a manifest
+    let paths: Vec<String> = if input.trim_start().starts_with('{') {
+        let manifest: ExportedManifest = serde_json::from_str(&input)
+            .map_err(|e| anyhow::anyhow!("Invalid package manifest on stdin: {}", e))?;
+        manifest.files.into_iter().map(|f| f.path).collect()
+    } else {
+        input.lines().map(str::to_string).collect()
+    };
+
+    for path in &paths {
+        let path = path.trim();
+        if path.is_empty() {
+            continue;
+        }
+        let path = if path.len() > 1 { path.trim_end_matches('/') } else { path };
+        match db.get_record(path)? {
+            Some(record) => {
+                if let Some(target) = CleanTarget::from_record(record) {
+                    let (target, kept) = excludes.apply(target);
+                    preserved += kept;
+                    targets.extend(target);
+                }
+            }
+            None => untracked.push(path.to_string()),
+        }
+    }
+
+    for path in &untracked {
+        eprintln!("Skipping (not tracked): {}", path);
+    }
+
+    if targets.is_empty() {
+        if json {
+            let result = CleanPreview {
+                package: None,
+                targets: vec![],
+                total_size: 0,
+                reclaimable_size: 0,
+                file_count: 0,
+                dir_count: 0,
+                symlink_count: 0,
+                preserved,
+            };
+            print_json(&result)?;
+        } else {
+            println!("No existing tracked files found in input");
+        }
+        return Ok(());
+    }
+
+    clean_targets(&db, &targets, None, preserved, &CleanFlags::from_options(opts, true, json))
+}
+
+/// Shared preview / confirm / delete flow for a resolved set of targets.
+fn clean_targets(
+    db: &Database,
+    targets: &[CleanTarget],
+    package: Option<&str>,
+    preserved: usize,
+    flags: &CleanFlags,
+) -> Result<()> {
+    let CleanFlags { force, dry_run, json, confirm_from_tty, report, check_mounts, purge, vacuum, archive } = *flags;
+    let archive_name = archive.then(|| crate::archive::archive_name(package));
+    // Records a purge forgets on top of the ones for deleted files
+    let forget = match package {
+        Some(package) if purge => Some((package, db.count_package(package)?)),
+        _ => None,
+    };
+    if check_mounts {
+        ensure_mounts_safe(targets.iter().map(|t| t.record.path.as_str()))?;
+    }
+    let groups = hardlink_groups(targets.iter());
     let total_size: u64 = targets.iter().map(|t| t.size).sum();
+    let reclaimable = reclaimable_size(targets.iter(), &groups);
     let dir_count = targets.iter().filter(|t| t.is_dir).count();
     let symlink_count = targets.iter().filter(|t| t.is_symlink).count();
     let file_count = targets.len() - dir_count - symlink_count;
 
     if json && dry_run {
         let preview = CleanPreview {
-            package: Some(package.to_string()),
-            targets: targets.iter().map(|t| CleanTargetInfo {
-                path: t.record.path.clone(),
-                size: t.size,
-                is_dir: t.is_dir,
-                is_symlink: t.is_symlink,
-            }).collect(),
+            package: package.map(|p| p.to_string()),
+            targets: targets.iter().map(|t| CleanTargetInfo::new(t, &groups)).collect(),
             total_size,
+            reclaimable_size: reclaimable,
             file_count,
             dir_count,
             symlink_count,
+            preserved,
         };
-        println!("{}", serde_json::to_string_pretty(&preview)?);
+        print_json(&preview)?;
         return Ok(());
     }
 
     if !json {
         if dry_run {
             println!("Would delete {} file(s), {} director(ies), {} symlink(s) [{}]:",
-                file_count, dir_count, symlink_count, format_size(total_size));
+                file_count, dir_count, symlink_count, format_totals(total_size, reclaimable));
         } else {
             println!("Will delete {} file(s), {} director(ies), {} symlink(s) [{}]:",
-                file_count, dir_count, symlink_count, format_size(total_size));
+                file_count, dir_count, symlink_count, format_totals(total_size, reclaimable));
+        }
+
+        for target in targets {
+            display_target(target, &groups);
         }
 
-        for target in &targets {
-            display_target(target);
+        print_preserved(preserved);
+        if let Some(name) = &archive_name {
+            println!("\n{} archive everything first to {}",
+                if dry_run { "Would" } else { "Will" },
+                crate::archive::archive_dir().join(format!("{}.tar.zst", name)).display());
+        }
+        if let Some((package, count)) = forget {
+            println!("\nThen forget all {} database record(s) for {}{}", count, package,
+                if vacuum { " and vacuum the database" } else { "" });
         }
 
         if dry_run {
@@ -248,60 +836,107 @@ pub fn clean_package(package: &str, force: bool, dry_run: bool, json: bool) -> R
         }
 
         println!();
-        if !force && !confirm_prompt()? {
+        let word = crate::config::Config::load()?.required_confirm_word(package, targets.len(), total_size);
+        if !force && !confirm_prompt(confirm_from_tty, word.as_deref())? {
             println!("Aborted.");
             return Ok(());
         }
     }
 
+    // Nothing is deleted unless the whole archive was written
+    let archived = match &archive_name {
+        Some(name) => {
+            let _t = PhaseTimer::start("archive");
+            let items: Vec<_> = targets
+                .iter()
+                .map(|t| crate::archive::ArchiveItem { record: &t.record, protected: &t.protected })
+                .collect();
+            let info = crate::archive::write_archive(name, package, &items)
+                .map_err(|e| anyhow::anyhow!("Archiving failed, nothing was deleted: {}", e))?;
+            if !json {
+                println!("Archived {} entries to {} [{}]", info.entries, info.path.display(), format_size(info.size));
+            }
+            Some(info)
+        }
+        None => None,
+    };
+
     let (deleted_paths, errors) = run_deletions(targets.iter(), json);
 
-    let records_removed = if !deleted_paths.is_empty() {
+    let mut records_removed = if !deleted_paths.is_empty() {
         db.delete_file_records(&deleted_paths)?
     } else {
         0
     };
+    if let Some((package, _)) = forget {
+        records_removed += db.forget_package(package)?;
+        if vacuum {
+            let _t = PhaseTimer::start("vacuum");
+            db.vacuum()?;
+        }
+    }
+
+    let result = CleanResult {
+        deleted: deleted_paths,
+        errors,
+        records_removed,
+        archive: archived,
+    };
+    if let Some(report) = report {
+        write_report(report, package, &result, targets)?;
+    }
 
     if json {
-        println!("{}", serde_json::to_string_pretty(&CleanResult {
-            deleted: deleted_paths,
-            errors,
-            records_removed,
-        })?);
+        print_json(&result)?;
     } else {
-        print_summary(deleted_paths.len(), errors.len(), records_removed);
+        print_summary(result.deleted.len(), result.errors.len(), result.records_removed);
+        if let Some(report) = report {
+            println!("Wrote deletion report to {}", report.display());
+        }
     }
 
     Ok(())
 }
 
-pub fn clean_orphans(force: bool, dry_run: bool, json: bool) -> Result<()> {
+pub fn clean_orphans(force: bool, dry_run: bool, exclude: &[String], check_mounts: bool, json: bool) -> Result<()> {
+    let excludes = ExcludeSet::new(exclude)?;
     let db = Database::new()?;
     let orphan_packages = db.get_orphans()?;
 
     if orphan_packages.is_empty() {
         if json {
-            println!("{}", serde_json::to_string_pretty(&CleanResult {
+            print_json(&CleanResult {
                 deleted: vec![],
                 errors: vec![],
                 records_removed: 0,
-            })?);
+                archive: None,
+            })?;
         } else {
             println!("No orphaned packages found!");
         }
         return Ok(());
     }
 
+    let t = PhaseTimer::start("db query + existence checks + sizes");
     let mut all_targets: Vec<(String, CleanTarget)> = Vec::new();
+    let mut preserved = 0;
+    let bar = crate::progress::bar(orphan_packages.len() as u64, "Scanning packages");
 
     for pkg in &orphan_packages {
+        bar.inc(1);
         let records = db.query_package(pkg)?;
         for record in records {
             if let Some(target) = CleanTarget::from_record(record) {
-                all_targets.push((pkg.clone(), target));
+                let (target, kept) = excludes.apply(target);
+                preserved += kept;
+                if let Some(target) = target {
+                    all_targets.push((pkg.clone(), target));
+                }
             }
         }
     }
+    bar.finish_and_clear();
+    drop(t);
 
     if all_targets.is_empty() {
         if json {
@@ -309,11 +944,12 @@ pub fn clean_orphans(force: bool, dry_run: bool, json: bool) -> Result<()> {
             if !dry_run {
                 records_removed = db.prune_deleted()?;
             }
-            println!("{}", serde_json::to_string_pretty(&CleanResult {
+            print_json(&CleanResult {
                 deleted: vec![],
                 errors: vec![],
                 records_removed,
-            })?);
+                archive: None,
+            })?;
         } else {
             println!("No existing files from orphaned packages.");
             if !dry_run {
@@ -326,7 +962,13 @@ pub fn clean_orphans(force: bool, dry_run: bool, json: bool) -> Result<()> {
         return Ok(());
     }
 
+    if check_mounts {
+        ensure_mounts_safe(all_targets.iter().map(|(_, t)| t.record.path.as_str()))?;
+    }
+
+    let groups = hardlink_groups(all_targets.iter().map(|(_, t)| t));
     let total_size: u64 = all_targets.iter().map(|(_, t)| t.size).sum();
+    let reclaimable = reclaimable_size(all_targets.iter().map(|(_, t)| t), &groups);
     let dir_count = all_targets.iter().filter(|(_, t)| t.is_dir).count();
     let symlink_count = all_targets.iter().filter(|(_, t)| t.is_symlink).count();
     let file_count = all_targets.len() - dir_count - symlink_count;
@@ -334,28 +976,25 @@ pub fn clean_orphans(force: bool, dry_run: bool, json: bool) -> Result<()> {
     if json && dry_run {
         let preview = CleanPreview {
             package: None,
-            targets: all_targets.iter().map(|(_, t)| CleanTargetInfo {
-                path: t.record.path.clone(),
-                size: t.size,
-                is_dir: t.is_dir,
-                is_symlink: t.is_symlink,
-            }).collect(),
+            targets: all_targets.iter().map(|(_, t)| CleanTargetInfo::new(t, &groups)).collect(),
             total_size,
+            reclaimable_size: reclaimable,
             file_count,
             dir_count,
             symlink_count,
+            preserved,
         };
-        println!("{}", serde_json::to_string_pretty(&preview)?);
+        print_json(&preview)?;
         return Ok(());
     }
 
     if !json {
         if dry_run {
             println!("Would delete {} file(s), {} director(ies), {} symlink(s) from {} orphaned package(s) [{}]:\n",
-                file_count, dir_count, symlink_count, orphan_packages.len(), format_size(total_size));
+                file_count, dir_count, symlink_count, orphan_packages.len(), format_totals(total_size, reclaimable));
         } else {
             println!("Will delete {} file(s), {} director(ies), {} symlink(s) from {} orphaned package(s) [{}]:\n",
-                file_count, dir_count, symlink_count, orphan_packages.len(), format_size(total_size));
+                file_count, dir_count, symlink_count, orphan_packages.len(), format_totals(total_size, reclaimable));
         }
 
         let mut current_pkg = String::new();
@@ -367,16 +1006,19 @@ pub fn clean_orphans(force: bool, dry_run: bool, json: bool) -> Result<()> {
                 println!("{}:", pkg);
                 current_pkg = pkg.clone();
             }
-            display_target(target);
+            display_target(target, &groups);
         }
 
+        print_preserved(preserved);
+
         if dry_run {
             println!("\n(dry run - no files were deleted)");
             return Ok(());
         }
 
         println!();
-        if !force && !confirm_prompt()? {
+        let word = crate::config::Config::load()?.required_confirm_word(None, all_targets.len(), total_size);
+        if !force && !confirm_prompt(false, word.as_deref())? {
             println!("Aborted.");
             return Ok(());
         }
@@ -391,11 +1033,12 @@ pub fn clean_orphans(force: bool, dry_run: bool, json: bool) -> Result<()> {
     };
 
     if json {
-        println!("{}", serde_json::to_string_pretty(&CleanResult {
+        print_json(&CleanResult {
             deleted: deleted_paths,
             errors,
             records_removed,
-        })?);
+            archive: None,
+        })?;
     } else {
         print_summary(deleted_paths.len(), errors.len(), records_removed);
     }
@@ -403,14 +1046,21 @@ pub fn clean_orphans(force: bool, dry_run: bool, json: bool) -> Result<()> {
     Ok(())
 }
 
-pub fn prune() -> Result<()> {
+pub fn prune(packages: &[String]) -> Result<()> {
     let db = Database::new()?;
+    if !packages.is_empty() {
+        return prune_packages(&db, packages);
+    }
     let config = crate::config::Config::load()?;
 
     let pruned = db.prune_deleted()?;
     let excluded = db.prune_excluded(&config.excluded_paths)?;
     let ignored = db.prune_ignored_packages(&config.ignored_packages)?;
 
+    if pruned > 0 || !excluded.is_empty() || ignored > 0 {
+        db.incremental_vacuum()?;
+    }
+
     let color = use_color();
 
     if pruned > 0 {
@@ -438,3 +1088,143 @@ pub fn prune() -> Result<()> {
 
     Ok(())
 }
+
+/// `prune --packages`: only existence-check the given packages' records.
+fn prune_packages(db: &Database, packages: &[String]) -> Result<()> {
+    let counts = db.prune_deleted_for_packages(packages)?;
+    let total: usize = counts.iter().map(|(_, n)| n).sum();
+    if total > 0 {
+        db.incremental_vacuum()?;
+    }
+
+    let color = use_color();
+    for (package, count) in &counts {
+        if color {
+            println!("{}: pruned {} deleted file(s)", package.bold(), count);
+        } else {
+            println!("{}: pruned {} deleted file(s)", package, count);
+        }
+    }
+    if total == 0 {
+        println!("Nothing to prune");
+    }
+
+    Ok(())
+}
+
+/// Rows that must change before `gc` bothers to VACUUM.
+const GC_VACUUM_THRESHOLD: usize = 1000;
+
+#[derive(Serialize)]
+struct GcSummary {
+    dry_run: bool,
+    orphan_records: usize,
+    deleted_records: usize,
+    excluded_records: usize,
+    ignored_records: usize,
+    total_records: usize,
+    vacuumed: bool,
+    auto_vacuum_changed: bool,
+    db_size_before: u64,
+    db_size_after: u64,
+}
+
+/// One-shot maintenance: drop records of gone files from uninstalled packages,
+/// prune deleted/excluded/ignored records, and VACUUM if enough rows changed.
+pub fn gc(dry_run: bool, json: bool) -> Result<()> {
+    let db = Database::new()?;
+    let config = crate::config::Config::load()?;
+    let (_, _, db_path) = db.get_stats()?;
+    let db_size = || Path::new(&db_path).metadata().map(|m| m.len()).unwrap_or(0);
+    let db_size_before = db_size();
+
+    // Records of uninstalled packages whose files are already gone. Skipped
+    // (with a note) if no package manager is available.
+    let orphan_gone: Vec<String> = match db.get_orphans() {
+        Ok(orphans) => db
+            .get_files_for_packages(&orphans)?
+            .into_iter()
+            .filter(|r| !Path::new(&r.path).exists())
+            .map(|r| r.path)
+            .collect(),
+        Err(e) => {
+            if !json {
+                eprintln!("Skipping orphan cleanup: {}", e);
+            }
+            vec![]
+        }
+    };
+
+    let (orphan_records, deleted_records, excluded_records, ignored_records) = if dry_run {
+        let deleted = db.find_deleted()?.len().saturating_sub(orphan_gone.len());
+        let excluded = db.find_excluded(&config.excluded_paths)?.len();
+        let ignored = db.count_package_records(&config.ignored_packages)?;
+        (orphan_gone.len(), deleted, excluded, ignored)
+    } else {
+        let orphans = db.delete_file_records(&orphan_gone)?;
+        let deleted = db.prune_deleted()?;
+        let excluded = db.prune_excluded(&config.excluded_paths)?.len();
+        let ignored = db.prune_ignored_packages(&config.ignored_packages)?;
+        (orphans, deleted, excluded, ignored)
+    };
+
+    let total_records = orphan_records + deleted_records + excluded_records + ignored_records;
+
+    // Converting auto_vacuum mode rewrites the file, which doubles as the vacuum.
+    let auto_vacuum_changed = !dry_run && db.incremental_vacuum_enabled()? != config.auto_vacuum;
+    let vacuumed = !dry_run && (auto_vacuum_changed || total_records >= GC_VACUUM_THRESHOLD);
+    if auto_vacuum_changed {
+        db.set_auto_vacuum(config.auto_vacuum)?;
+    } else if vacuumed {
+        db.vacuum()?;
+    } else if !dry_run && total_records > 0 {
+        db.incremental_vacuum()?;
+    }
+
+    let summary = GcSummary {
+        dry_run,
+        orphan_records,
+        deleted_records,
+        excluded_records,
+        ignored_records,
+        total_records,
+        vacuumed,
+        auto_vacuum_changed,
+        db_size_before,
+        db_size_after: db_size(),
+    };
+
+    if json {
+        print_json(&summary)?;
+        return Ok(());
+    }
+
+    let verb = if dry_run { "Would remove" } else { "Removed" };
+    println!("{} {} record(s):", verb, total_records);
+    println!("  {} from uninstalled packages (files gone)", orphan_records);
+    println!("  {} for other deleted files", deleted_records);
+    println!("  {} under excluded paths", excluded_records);
+    println!("  {} from ignored packages", ignored_records);
+
+    if auto_vacuum_changed {
+        println!(
+            "Switched database auto_vacuum to {} (one-time rebuild)",
+            if config.auto_vacuum { "incremental" } else { "none" }
+        );
+    }
+    if vacuumed {
+        println!(
+            "Vacuumed database: {} -> {}",
+            format_size(summary.db_size_before),
+            format_size(summary.db_size_after)
+        );
+    } else if !dry_run {
+        println!("Skipped vacuum (fewer than {} rows changed)", GC_VACUUM_THRESHOLD);
+    }
+
+    if dry_run {
+        println!("\n(dry run - database was not modified)");
+    }
+
+    Ok(())
+}
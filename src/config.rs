@@ -2,6 +2,14 @@ use anyhow::Result;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::path::PathBuf;
 
+/// Home dotdirs (without the leading dot) that churn through enough files
+/// that tracking them at depth 0 can balloon the database.
+const HIGH_CHURN_DIRS: &[&str] = &["cache", "local"];
+
+/// Deepest tracking depth that `config validate` accepts without a warning;
+/// `config validate --fix` clamps anything deeper to this.
+pub const MAX_USUAL_DEPTH: u32 = 5;
+
 #[derive(Debug, Clone)]
 pub struct MonitoredDir {
     pub path: String,
@@ -53,7 +61,18 @@ impl<'de> Deserialize<'de> for MonitoredDir {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Unit base for displayed sizes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SizeUnits {
+    /// 1024-based, labeled KiB/MiB/GiB
+    #[default]
+    Binary,
+    /// 1000-based, labeled kB/MB/GB
+    Si,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     #[serde(default = "default_monitored_dirs")]
     pub monitored_dirs: Vec<MonitoredDir>,
@@ -67,11 +86,50 @@ pub struct Config {
     #[serde(default)]
     pub excluded_paths: Vec<String>,
 
+    #[serde(default = "default_ignored_paths")]
+    pub ignored_paths: Vec<String>,
+
     #[serde(default = "default_tracking_depth")]
     pub tracking_depth: u32,
 
     #[serde(default = "default_auto_prune")]
     pub auto_prune: bool,
+
+    #[serde(default)]
+    pub auto_vacuum: bool,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_records: Option<usize>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub db_path: Option<String>,
+
+    #[serde(default = "default_parent_walk")]
+    pub parent_walk: bool,
+
+    #[serde(default = "default_transparent_packages")]
+    pub transparent_packages: Vec<String>,
+
+    #[serde(default = "default_transition_processes")]
+    pub transition_processes: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_format: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+
+    #[serde(default)]
+    pub size_units: SizeUnits,
+
+    #[serde(default = "default_confirm_word")]
+    pub confirm_word: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confirm_word_min_files: Option<usize>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confirm_word_min_mib: Option<u64>,
 }
 
 fn default_monitored_dirs() -> Vec<MonitoredDir> {
@@ -121,6 +179,35 @@ fn default_ignored_packages() -> Vec<String> {
     vec![]
 }
 
+/// Make an `ignored_paths` glob containing a '/' absolute: `~` and `~/…`
+/// are under `home`, as are relative patterns like `.config/*/Cache/**`.
+pub fn expand_home_pattern(pattern: &str, home: &std::path::Path) -> String {
+    if pattern.starts_with('/') {
+        return pattern.to_string();
+    }
+    let rest = match pattern.strip_prefix('~') {
+        Some(rest) => rest.trim_start_matches('/'),
+        None => pattern,
+    };
+    if rest.is_empty() {
+        home.to_string_lossy().into_owned()
+    } else {
+        home.join(rest).to_string_lossy().into_owned()
+    }
+}
+
+/// File-name globs for transient files (temp, lock, editor swap/backup)
+/// that appear and vanish too quickly to be worth a record.
+fn default_ignored_paths() -> Vec<String> {
+    vec![
+        "*.tmp".to_string(),
+        "*.lock".to_string(),
+        "*~".to_string(),
+        ".#*".to_string(),
+        "*.swp".to_string(),
+    ]
+}
+
 fn default_tracking_depth() -> u32 {
     1
 }
@@ -129,6 +216,35 @@ fn default_auto_prune() -> bool {
     true
 }
 
+fn default_parent_walk() -> bool {
+    true
+}
+
+fn default_confirm_word() -> String {
+    "delete".to_string()
+}
+
+fn default_transition_processes() -> Vec<String> {
+    vec![
+        "sudo".to_string(),
+        "doas".to_string(),
+        "pkexec".to_string(),
+        "su".to_string(),
+        "run0".to_string(),
+    ]
+}
+
+fn default_transparent_packages() -> Vec<String> {
+    vec![
+        "bash".to_string(),
+        "dash".to_string(),
+        "zsh".to_string(),
+        "fish".to_string(),
+        "coreutils".to_string(),
+        "busybox".to_string(),
+    ]
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -136,12 +252,55 @@ impl Default for Config {
             ignored_processes: default_ignored_processes(),
             ignored_packages: default_ignored_packages(),
             excluded_paths: vec![],
+            ignored_paths: default_ignored_paths(),
             tracking_depth: default_tracking_depth(),
             auto_prune: default_auto_prune(),
+            auto_vacuum: false,
+            max_records: None,
+            db_path: None,
+            parent_walk: default_parent_walk(),
+            transparent_packages: default_transparent_packages(),
+            transition_processes: default_transition_processes(),
+            time_format: None,
+            timezone: None,
+            size_units: SizeUnits::default(),
+            confirm_word: default_confirm_word(),
+            confirm_word_min_files: None,
+            confirm_word_min_mib: None,
         }
     }
 }
 
+/// Render a TOML parse error with the file path, line/column, the offending
+/// line, and a caret under the error position.
+fn describe_parse_error(path: &std::path::Path, content: &str, err: &toml::de::Error) -> String {
+    let mut msg = format!("Invalid config file {}", path.display());
+    let detail = err.message().trim().replace('\n', "; ");
+    if let Some(span) = err.span() {
+        let start = span.start.min(content.len());
+        let line_start = content[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = content[start..].find('\n').map_or(content.len(), |i| start + i);
+        let line_no = content[..start].matches('\n').count() + 1;
+        let column = content[line_start..start].chars().count() + 1;
+        let gutter = " ".repeat(line_no.to_string().len());
+        msg.push_str(&format!(
+            " at line {}, column {}: {}\n {} |\n {} | {}\n {} | {}^",
+            line_no,
+            column,
+            detail,
+            gutter,
+            line_no,
+            &content[line_start..line_end],
+            gutter,
+            " ".repeat(column - 1),
+        ));
+    } else {
+        msg.push_str(&format!(": {}", detail));
+    }
+    msg.push_str("\nFix the file (or run `hdas config init` to start over), then check it with `hdas config validate`.");
+    msg
+}
+
 impl Config {
     pub fn path() -> PathBuf {
         crate::db::get_user_home()
@@ -155,8 +314,8 @@ impl Config {
 
         if path.exists() {
             let content = std::fs::read_to_string(&path)?;
-            let config: Config = toml::from_str(&content)?;
-            Ok(config)
+            toml::from_str(&content)
+                .map_err(|e| anyhow::anyhow!(describe_parse_error(&path, &content, &e)))
         } else {
             Ok(Config::default())
         }
@@ -183,6 +342,94 @@ impl Config {
         Ok(())
     }
 
+    /// Apply the mechanical fixes for problems `config validate` reports:
+    /// drop duplicate monitored dirs and ignored processes (first one wins)
+    /// and clamp depths above [`MAX_USUAL_DEPTH`]. Returns one line per change.
+    pub fn fix_problems(&mut self) -> Vec<String> {
+        let mut fixes = Vec::new();
+
+        let mut seen = std::collections::HashSet::new();
+        self.monitored_dirs.retain(|d| {
+            let first = seen.insert(d.path.clone());
+            if !first {
+                fixes.push(format!("Removed duplicate monitored directory: {}", d.path));
+            }
+            first
+        });
+
+        let mut seen = std::collections::HashSet::new();
+        self.ignored_processes.retain(|p| {
+            let first = seen.insert(p.clone());
+            if !first {
+                fixes.push(format!("Removed duplicate ignored process: {}", p));
+            }
+            first
+        });
+
+        if self.tracking_depth > MAX_USUAL_DEPTH {
+            fixes.push(format!("Clamped tracking_depth {} to {}", self.tracking_depth, MAX_USUAL_DEPTH));
+            self.tracking_depth = MAX_USUAL_DEPTH;
+        }
+        for dir in &mut self.monitored_dirs {
+            if let Some(depth) = dir.depth.filter(|&d| d > MAX_USUAL_DEPTH) {
+                fixes.push(format!("Clamped depth {} for '{}' to {}", depth, dir.path, MAX_USUAL_DEPTH));
+                dir.depth = Some(MAX_USUAL_DEPTH);
+            }
+        }
+
+        fixes
+    }
+
+    /// Monitored dirs that would be tracked at full depth (effective depth 0)
+    /// despite being high-churn, where every file becomes its own record.
+    pub fn depth_zero_high_churn_dirs(&self) -> Vec<&str> {
+        self.monitored_dirs
+            .iter()
+            .filter(|d| d.depth.unwrap_or(self.tracking_depth) == 0)
+            .filter(|d| HIGH_CHURN_DIRS.contains(&d.path.trim_start_matches('.').trim_end_matches('/')))
+            .map(|d| d.path.as_str())
+            .collect()
+    }
+
+    /// Monitored dirs as absolute paths; relative entries like `config` are
+    /// the dot-directory under `home`.
+    pub fn monitored_dir_paths(&self, home: &std::path::Path) -> Vec<PathBuf> {
+        self.monitored_dirs
+            .iter()
+            .map(|d| {
+                if d.path.starts_with('/') {
+                    PathBuf::from(d.path.trim_end_matches('/'))
+                } else {
+                    home.join(format!(".{}", d.path.trim_start_matches('.').trim_end_matches('/')))
+                }
+            })
+            .collect()
+    }
+
+    /// Monitored dirs that are symlinks (e.g. `~/.config` managed by a dotfile
+    /// tool), as (configured path, canonical target). Dangling links are skipped.
+    pub fn symlinked_monitored_dirs(&self, home: &std::path::Path) -> Vec<(PathBuf, PathBuf)> {
+        self.monitored_dir_paths(home)
+            .into_iter()
+            .filter(|p| p.symlink_metadata().is_ok_and(|m| m.file_type().is_symlink()))
+            .filter_map(|p| std::fs::canonicalize(&p).ok().map(|target| (p, target)))
+            .collect()
+    }
+
+    /// The word a clean of `count` paths totalling `bytes` must be confirmed
+    /// with, or None when it's under both thresholds and `y` will do.
+    /// `{package}` in `confirm_word` becomes the package name, or "delete"
+    /// when the clean isn't for a single package.
+    pub fn required_confirm_word(&self, package: Option<&str>, count: usize, bytes: u64) -> Option<String> {
+        let over_count = self.confirm_word_min_files.is_some_and(|min| count >= min);
+        let over_size = self.confirm_word_min_mib.is_some_and(|min| bytes >= min.saturating_mul(1024 * 1024));
+        if !over_count && !over_size {
+            return None;
+        }
+        let word = self.confirm_word.replace("{package}", package.unwrap_or("delete"));
+        Some(if word.trim().is_empty() { default_confirm_word() } else { word.trim().to_string() })
+    }
+
     pub fn ensure_exists() -> Result<()> {
         let path = Self::path();
         if !path.exists() {
@@ -215,12 +462,69 @@ ignored_packages = []
 # ]
 excluded_paths = []
 
+# Globs the monitor skips. A pattern without '/' is matched against the opened
+# file's name (the defaults cover temp, lock, and editor swap/backup files that
+# vanish right away; set to [] to record them too). A pattern with '/' is
+# matched against the whole path: '~/' and relative patterns are under your
+# home, '*' stays within one directory and '**' spans any number, e.g.
+# ".config/*/Cache/**" skips every app's Cache directory.
+ignored_paths = ["*.tmp", "*.lock", "*~", ".#*", "*.swp"]
+
 # Default depth for monitored dirs without explicit depth (1 = app dir like ~/.cache/mozilla)
 # Note: ~/.local/share, ~/.local/state, and ~/.local/lib automatically add +1 depth
 tracking_depth = 1
 
 auto_prune = true
 
+# Use SQLite incremental auto-vacuum so prune/gc hand freed space back to the
+# filesystem cheaply. Applies to new databases immediately; an existing
+# database is converted by the next `hdas gc` (a one-time full VACUUM).
+auto_vacuum = false
+
+# Cap on tracked records. While monitoring, the least recently accessed
+# records are evicted (checked once a minute) when the cap is exceeded.
+# Unset means unlimited.
+# max_records = 500000
+
+# Where the attribution database lives. "~" and relative paths are under your
+# home; $HDAS_DB_PATH overrides this. The monitor and queries must agree on it.
+# Unset means attributions.db in $XDG_DATA_HOME/hdas (~/.local/share/hdas).
+# db_path = "~/.local/share/hdas/attributions.db"
+
+# Walk up the process tree when the writing process isn't owned by a package.
+# Disabling gives precise-but-sparse attribution: more "unknown", fewer
+# misattributions to shells and terminal emulators.
+parent_walk = true
+
+# Packages that are skipped over during the process-tree walk. When a shell or
+# coreutils binary does the writing, the nearest packaged ancestor outside this
+# list gets the attribution instead.
+transparent_packages = ["bash", "dash", "zsh", "fish", "coreutils", "busybox"]
+
+# Privilege-transition helpers, matched by executable name (or full path if
+# an entry contains '/'). Files written through them are attributed to the
+# process that invoked the helper rather than to the helper's package, and
+# root-owned writes are recorded when you started the helper.
+transition_processes = ["sudo", "doas", "pkexec", "su", "run0"]
+
+# strftime format for displayed timestamps (JSON output always uses epochs).
+# Unset uses "Feb 18 22:11" for this year and "Feb 18  2024" for older dates.
+# time_format = "%Y-%m-%d %H:%M"
+
+# IANA timezone for displayed timestamps (e.g. "UTC", "Europe/Berlin").
+# Unset uses the system local time, which honours $TZ.
+# timezone = "UTC"
+
+# Size units: "binary" (1024-based, KiB/MiB/GiB) or "si" (1000-based, kB/MB/GB)
+size_units = "binary"
+
+# Large cleans must be confirmed by typing confirm_word instead of y. Applies
+# when a clean reaches either threshold (unset disables that threshold);
+# --force still skips the prompt. "{package}" stands for the package name.
+confirm_word = "delete"
+# confirm_word_min_files = 100
+# confirm_word_min_mib = 1024
+
 # Directories to monitor
 # Use [[monitored_dirs]] for per-directory depth, or simple strings for global depth
 #
@@ -246,3 +550,58 @@ path = ".config"
 "#
     .to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symlinked_monitored_dirs_resolve_to_their_targets() {
+        let tmp = tempfile::tempdir().unwrap();
+        let home = tmp.path().join("home");
+        let dotfiles = tmp.path().join("dotfiles/config");
+        std::fs::create_dir_all(home.join(".cache")).unwrap();
+        std::fs::create_dir_all(&dotfiles).unwrap();
+        std::os::unix::fs::symlink(&dotfiles, home.join(".config")).unwrap();
+        // Dangling: skipped rather than reported with a bogus target
+        std::os::unix::fs::symlink(tmp.path().join("gone"), home.join(".local")).unwrap();
+        let absolute = tmp.path().join("etc-link");
+        std::os::unix::fs::symlink(&dotfiles, &absolute).unwrap();
+
+        let config = Config {
+            monitored_dirs: vec![
+                MonitoredDir::new(".cache"),
+                MonitoredDir::new(".config"),
+                MonitoredDir::new(".local"),
+                MonitoredDir::new(&format!("{}/", absolute.display())),
+            ],
+            ..Default::default()
+        };
+        let target = std::fs::canonicalize(&dotfiles).unwrap();
+        assert_eq!(
+            config.symlinked_monitored_dirs(&home),
+            vec![(home.join(".config"), target.clone()), (absolute, target)]
+        );
+    }
+
+    fn parse_error(content: &str) -> String {
+        let err = toml::from_str::<Config>(content).expect_err("config should not parse");
+        describe_parse_error(std::path::Path::new("/home/u/.config/hdas/config.toml"), content, &err)
+    }
+
+    #[test]
+    fn parse_error_points_at_line_and_column() {
+        let msg = parse_error("auto_prune = true\ntracking_depth = = 2\n");
+        assert!(msg.starts_with("Invalid config file /home/u/.config/hdas/config.toml at line 2, column 18: "), "{}", msg);
+        assert!(msg.contains("\n 2 | tracking_depth = = 2\n   |                  ^\n"), "{}", msg);
+        assert!(msg.ends_with("then check it with `hdas config validate`."), "{}", msg);
+    }
+
+    #[test]
+    fn type_error_names_the_offending_value() {
+        let msg = parse_error("auto_prune = true\n\ntracking_depth = \"two\"\n");
+        assert!(msg.contains("at line 3, column 18: "), "{}", msg);
+        assert!(msg.contains(" 3 | tracking_depth = \"two\"\n"), "{}", msg);
+        assert!(msg.contains("`hdas config init`"), "{}", msg);
+    }
+}
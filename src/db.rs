@@ -1,17 +1,42 @@
 use anyhow::Result;
 use nix::unistd::User;
-use rusqlite::{Connection, params};
-use serde::Serialize;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
+use std::sync::OnceLock;
 use std::os::unix::fs::chown;
 
 pub struct Database {
     conn: Connection,
 }
 
+/// Columns read into a [`FileRecord`], in the order [`record_from_row`] expects.
+const SELECT_COLUMNS: &str = "path,
+    created_by_package, created_by_process, created_at,
+    last_accessed_by_package, last_accessed_by_process, last_accessed_at, size_bytes";
+
+/// Map a row selected with [`SELECT_COLUMNS`]. Unknown timestamps read as 0.
+fn record_from_row(row: &rusqlite::Row) -> rusqlite::Result<FileRecord> {
+    Ok(FileRecord {
+        path: row.get(0)?,
+        created_by_package: row.get(1)?,
+        created_by_process: row.get(2)?,
+        created_at: row.get::<_, Option<i64>>(3)?.unwrap_or(0),
+        last_accessed_by_package: row.get(4)?,
+        last_accessed_by_process: row.get(5)?,
+        last_accessed_at: row.get::<_, Option<i64>>(6)?.unwrap_or(0),
+        size_bytes: row.get(7)?,
+    })
+}
+
+/// Look up a user's passwd entry by name.
+fn lookup_user(name: &str) -> Option<User> {
+    User::from_name(name).ok().flatten()
+}
+
 pub fn get_user_info() -> (PathBuf, Option<u32>, Option<u32>) {
     if let Ok(sudo_user) = std::env::var("SUDO_USER") {
-        if let Ok(Some(user)) = User::from_name(&sudo_user) {
+        if let Some(user) = lookup_user(&sudo_user) {
             let uid = user.uid.as_raw();
             let gid = user.gid.as_raw();
             return (user.dir, Some(uid), Some(gid));
@@ -30,6 +55,85 @@ pub fn get_user_home() -> PathBuf {
     get_user_info().0
 }
 
+/// Home directory set with `--home` for analyzing another tree offline.
+static HOME_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Override the home used for path expansion and monitored-dir matching in
+/// read commands. The database and config still live in the real home.
+pub fn set_home_override(home: PathBuf) {
+    let _ = HOME_OVERRIDE.set(home);
+}
+
+/// Home to resolve user-supplied and monitored paths against: the `--home`
+/// override if given, else the monitoring user's home.
+pub fn analysis_home() -> PathBuf {
+    HOME_OVERRIDE.get().cloned().unwrap_or_else(get_user_home)
+}
+
+/// Expand a user-supplied path to an absolute one: `~` and `~/…` resolve to
+/// the monitoring user's home (or `--home`), `~name` and `~name/…` to that
+/// user's home, and relative paths are taken relative to the same home as `~`.
+pub fn expand_user_path(path: &str) -> Result<String> {
+    if path.starts_with('/') {
+        return Ok(path.to_string());
+    }
+    let Some(tilde) = path.strip_prefix('~') else {
+        return Ok(analysis_home().join(path).to_string_lossy().into_owned());
+    };
+    let (name, rest) = tilde.split_once('/').unwrap_or((tilde, ""));
+    let home = if name.is_empty() {
+        analysis_home()
+    } else {
+        lookup_user(name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown user '{}' in path: {}", name, path))?
+            .dir
+    };
+    Ok(if rest.is_empty() {
+        home.to_string_lossy().into_owned()
+    } else {
+        home.join(rest).to_string_lossy().into_owned()
+    })
+}
+
+/// Directory holding the attribution database and hdas's other state:
+/// `$XDG_DATA_HOME/hdas`, else `~/.local/share/hdas`. A relative
+/// `XDG_DATA_HOME` is ignored, as the XDG spec requires.
+pub fn data_dir() -> PathBuf {
+    std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .unwrap_or_else(|| get_user_home().join(".local/share"))
+        .join("hdas")
+}
+
+/// Location of the attributions database: `$HDAS_DB_PATH`, then `db_path`
+/// from the config, then `attributions.db` in [`data_dir`]. `~` and relative
+/// paths resolve against the real user's home, not `--home`. A config that
+/// can't be read is an error rather than a silent fall back to the default,
+/// which could be a different database.
+pub fn db_path() -> Result<PathBuf> {
+    if let Some(path) = std::env::var("HDAS_DB_PATH").ok().filter(|p| !p.is_empty()) {
+        return Ok(expand_db_path(&path));
+    }
+    let config = crate::config::Config::load()
+        .map_err(|e| anyhow::anyhow!("Could not read the config to find the database: {}", e))?;
+    Ok(match config.db_path {
+        Some(path) => expand_db_path(&path),
+        None => data_dir().join("attributions.db"),
+    })
+}
+
+/// Resolve a configured database path: absolute paths are kept, `~/` and
+/// relative paths are taken from the real user's home.
+pub fn expand_db_path(path: &str) -> PathBuf {
+    let home = get_user_home();
+    match path.strip_prefix("~/") {
+        Some(rest) => home.join(rest),
+        None if path == "~" => home,
+        None => home.join(path),
+    }
+}
+
 pub fn create_dir_all_with_owner(path: &std::path::Path, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
     let mut to_create = Vec::new();
     let mut current = path.to_path_buf();
@@ -55,107 +159,348 @@ pub fn create_dir_all_with_owner(path: &std::path::Path, uid: Option<u32>, gid:
     Ok(())
 }
 
+/// Bounds `[low, high)` covering every string that starts with `prefix`, so a
+/// prefix match can use the column's index (LIKE can't, being case-insensitive).
+fn prefix_range(prefix: Option<&str>) -> (String, String) {
+    let prefix = prefix.unwrap_or_default();
+    (prefix.to_string(), format!("{}{}", prefix, char::MAX))
+}
+
+/// The WHERE conditions of a record query and the values bound to them.
+/// Filters that aren't in use add nothing, rather than a `?N IS NULL OR …`
+/// guard, which keeps SQLite from using the index on the filtered column.
+#[derive(Default)]
+struct Filter {
+    conditions: Vec<String>,
+    values: Vec<rusqlite::types::Value>,
+}
+
+impl Filter {
+    /// Add `condition`, whose `?` placeholders take `values` in order.
+    fn push(&mut self, condition: &str, values: impl IntoIterator<Item = rusqlite::types::Value>) {
+        self.conditions.push(condition.to_string());
+        self.values.extend(values);
+    }
+
+    /// Only packages whose name starts with `prefix`, as a range on the name
+    /// so `idx_package` applies.
+    fn package_prefix(&mut self, prefix: Option<&str>) {
+        if prefix.is_some() {
+            let (low, high) = prefix_range(prefix);
+            self.push("created_by_package >= ? AND created_by_package < ?", [low.into(), high.into()]);
+        }
+    }
+
+    /// Only files created by exactly `package`.
+    fn created_by(&mut self, package: &str) {
+        self.push("created_by_package = ?", [package.to_string().into()]);
+    }
+
+    /// Only files created at or after `since`; unknown creation times (0 or
+    /// NULL) never match.
+    fn created_since(&mut self, since: Option<i64>) {
+        if let Some(since) = since {
+            self.push("created_at >= ?", [since.max(1).into()]);
+        }
+    }
+
+    /// Only files created within `range`. Once either end is set, unknown
+    /// creation times never match.
+    fn created_in(&mut self, range: CreatedRange) {
+        if range.is_unbounded() {
+            return;
+        }
+        self.created_since(Some(range.since.unwrap_or(1)));
+        if let Some(until) = range.until {
+            self.push("created_at < ?", [until.into()]);
+        }
+    }
+
+    /// ` WHERE …` joining the conditions, or nothing without any.
+    fn where_clause(&self) -> String {
+        if self.conditions.is_empty() {
+            return String::new();
+        }
+        format!(" WHERE {}", self.conditions.join(" AND "))
+    }
+
+    /// Record columns of the matching rows, followed by `tail` (ORDER BY, LIMIT).
+    fn select_records(&self, tail: &str) -> String {
+        let mut sql = format!("SELECT {} FROM files{}", SELECT_COLUMNS, self.where_clause());
+        if !tail.is_empty() {
+            sql.push(' ');
+            sql.push_str(tail);
+        }
+        sql
+    }
+}
+
+/// Filter for [`Database::query_file`].
+fn file_filter(path: &PathMatch, created_since: Option<i64>, package_prefix: Option<&str>) -> Filter {
+    let mut filter = Filter::default();
+    // A regex is applied to the rows as they're read
+    if let PathMatch::Like(pattern) = path {
+        filter.push("path LIKE ?", [format!("%{}%", pattern).into()]);
+    }
+    filter.created_since(created_since);
+    filter.package_prefix(package_prefix);
+    filter
+}
+
+/// Hand the database file to the monitoring user when running under sudo,
+/// along with the WAL files, which every reader needs to write to.
+fn chown_to_user(db_path: &std::path::Path) {
+    if let (_, Some(uid), Some(gid)) = get_user_info() {
+        if let Err(e) = chown(db_path, Some(uid), Some(gid)) {
+            eprintln!("Warning: failed to chown {}: {}", db_path.display(), e);
+        }
+        for suffix in ["-wal", "-shm"] {
+            let mut side = db_path.as_os_str().to_owned();
+            side.push(suffix);
+            // Absent unless the database is in WAL mode and open
+            let _ = chown(std::path::Path::new(&side), Some(uid), Some(gid));
+        }
+    }
+}
+
+/// Schema version this build of hdas creates and expects.
+pub const SCHEMA_VERSION: i32 = 4;
+
+/// Each schema version with what migrating to it does, oldest first.
+pub const MIGRATIONS: &[(i32, &str)] = &[
+    (1, "Create the files table (converting the pre-versioned schema if present)"),
+    (2, "Add the created_via_parent column"),
+    (3, "Index created_at and last_accessed_at"),
+    (4, "Add the size_bytes column"),
+];
+
+fn check_schema_supported(version: i32) -> Result<()> {
+    if version > SCHEMA_VERSION {
+        return Err(anyhow::anyhow!(
+            "Unknown database schema version: {} (this hdas supports up to {}; was the database written by a newer hdas?)",
+            version, SCHEMA_VERSION
+        ));
+    }
+    Ok(())
+}
+
+/// Outcome of [`Database::run_migrations`].
+#[derive(Debug, Serialize)]
+pub struct MigrationSummary {
+    pub path: PathBuf,
+    pub from_version: i32,
+    pub to_version: i32,
+    /// Steps that were applied (or would be, on a dry run)
+    pub steps: Vec<MigrationStep>,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MigrationStep {
+    pub version: i32,
+    pub description: &'static str,
+}
+
 impl Database {
     pub fn new() -> Result<Self> {
-        let (home, uid, gid) = get_user_info();
-        let mut db_dir = home;
-        db_dir.push(".local/share/hdas");
-        create_dir_all_with_owner(&db_dir, uid, gid)?;
+        let (conn, db_path) = Self::open_unmigrated()?;
 
-        let db_path = db_dir.join("attributions.db");
-        let conn = Connection::open(&db_path)?;
+        // WAL lets queries read while the monitor holds the write connection.
+        // It's only an optimization, so filesystems without the shared memory
+        // it needs keep the rollback journal.
+        let _ = conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0));
         Self::migrate(&conn)?;
+        chown_to_user(&db_path);
 
-        if let (Some(uid), Some(gid)) = (uid, gid) {
-            if let Err(e) = chown(&db_path, Some(uid), Some(gid)) {
-                eprintln!("Warning: failed to chown {}: {}", db_path.display(), e);
+        Ok(Self { conn })
+    }
+
+    fn open_unmigrated() -> Result<(Connection, PathBuf)> {
+        let (_, uid, gid) = get_user_info();
+        let db_path = db_path()?;
+        if let Some(db_dir) = db_path.parent() {
+            create_dir_all_with_owner(db_dir, uid, gid)?;
+        }
+
+        let conn = Connection::open(&db_path)?;
+
+        // auto_vacuum can only be chosen before the first table exists; existing
+        // databases are converted by `hdas gc` (see `set_auto_vacuum`).
+        let auto_vacuum = crate::config::Config::load().map(|c| c.auto_vacuum).unwrap_or(false);
+        if auto_vacuum {
+            let tables: i64 = conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |r| r.get(0))?;
+            if tables == 0 {
+                conn.execute_batch("PRAGMA auto_vacuum = INCREMENTAL")?;
             }
         }
 
-        Ok(Self { conn })
+        Ok((conn, db_path))
     }
 
+    /// Open the database and bring its schema up to [`SCHEMA_VERSION`],
+    /// reporting the versions and steps involved (`hdas migrate-db`). Every
+    /// command already does this silently on open; running it again once
+    /// current is a no-op.
+    pub fn run_migrations(dry_run: bool) -> Result<MigrationSummary> {
+        let (conn, db_path) = Self::open_unmigrated()?;
+        let from_version: i32 = conn.query_row("PRAGMA user_version", [], |r| r.get(0))?;
+        check_schema_supported(from_version)?;
+
+        if !dry_run {
+            Self::migrate(&conn)?;
+            chown_to_user(&db_path);
+        }
+        let to_version = if dry_run {
+            SCHEMA_VERSION
+        } else {
+            conn.query_row("PRAGMA user_version", [], |r| r.get(0))?
+        };
+
+        let steps = MIGRATIONS
+            .iter()
+            .filter(|(version, _)| *version > from_version && *version <= to_version)
+            .map(|&(version, description)| MigrationStep { version, description })
+            .collect();
+
+        Ok(MigrationSummary { path: db_path, from_version, to_version, steps, dry_run })
+    }
+
+    /// Upgrade the schema to [`SCHEMA_VERSION`] in a single transaction, so
+    /// an interrupted upgrade leaves the previous version intact.
     fn migrate(conn: &Connection) -> Result<()> {
         let version: i32 = conn.query_row("PRAGMA user_version", [], |r| r.get(0))?;
 
-        match version {
-            0 => {
-                // May be: (a) fresh DB, (b) old schema, (c) current schema but never stamped
-                let has_new_schema = conn
-                    .prepare("SELECT created_by_package FROM files LIMIT 1")
-                    .is_ok();
-
-                if has_new_schema {
-                    // Already on current schema — just stamp it
-                    conn.execute("PRAGMA user_version = 1", [])?;
-                    return Ok(());
-                }
+        check_schema_supported(version)?;
+        if version == SCHEMA_VERSION {
+            return Ok(());
+        }
 
-                let old_table_exists = conn
-                    .prepare("SELECT path FROM files LIMIT 1")
-                    .is_ok();
-
-                if old_table_exists {
-                    conn.execute_batch(
-                        "CREATE TABLE files_new (
-                            path TEXT PRIMARY KEY,
-                            created_by_package TEXT,
-                            created_by_process TEXT,
-                            created_at INTEGER,
-                            last_accessed_by_package TEXT,
-                            last_accessed_by_process TEXT,
-                            last_accessed_at INTEGER
-                        );
-                        INSERT INTO files_new (
-                            path,
-                            created_by_package, created_by_process, created_at,
-                            last_accessed_by_package, last_accessed_by_process, last_accessed_at
-                        )
-                        SELECT
-                            path,
-                            package, process, first_seen,
-                            package, process, last_seen
-                        FROM files;
-                        DROP TABLE files;
-                        ALTER TABLE files_new RENAME TO files;
-                        CREATE INDEX idx_package ON files(created_by_package);
-                        CREATE INDEX idx_last_package ON files(last_accessed_by_package);"
-                    )?;
-                } else {
-                    conn.execute(
-                        "CREATE TABLE IF NOT EXISTS files (
-                            path TEXT PRIMARY KEY,
-                            created_by_package TEXT,
-                            created_by_process TEXT,
-                            created_at INTEGER,
-                            last_accessed_by_package TEXT,
-                            last_accessed_by_process TEXT,
-                            last_accessed_at INTEGER
-                        )",
-                        [],
-                    )?;
-                    conn.execute(
-                        "CREATE INDEX IF NOT EXISTS idx_package ON files(created_by_package)",
-                        [],
-                    )?;
-                    conn.execute(
-                        "CREATE INDEX IF NOT EXISTS idx_last_package ON files(last_accessed_by_package)",
-                        [],
-                    )?;
-                }
-                conn.execute("PRAGMA user_version = 1", [])?;
+        let tx = conn.unchecked_transaction()?;
+        let conn = &*tx;
+
+        if version == 0 {
+            // May be: (a) fresh DB, (b) old schema, (c) version 1 schema but never stamped
+            let has_new_schema = conn
+                .prepare("SELECT created_by_package FROM files LIMIT 1")
+                .is_ok();
+
+            let old_table_exists = conn
+                .prepare("SELECT path FROM files LIMIT 1")
+                .is_ok();
+
+            if has_new_schema {
+                // Already on the version 1 schema, just never stamped
+            } else if old_table_exists {
+                conn.execute_batch(
+                    "CREATE TABLE files_new (
+                        path TEXT PRIMARY KEY,
+                        created_by_package TEXT,
+                        created_by_process TEXT,
+                        created_at INTEGER,
+                        last_accessed_by_package TEXT,
+                        last_accessed_by_process TEXT,
+                        last_accessed_at INTEGER
+                    );
+                    INSERT INTO files_new (
+                        path,
+                        created_by_package, created_by_process, created_at,
+                        last_accessed_by_package, last_accessed_by_process, last_accessed_at
+                    )
+                    SELECT
+                        path,
+                        package, process, first_seen,
+                        package, process, last_seen
+                    FROM files;
+                    DROP TABLE files;
+                    ALTER TABLE files_new RENAME TO files;
+                    CREATE INDEX idx_package ON files(created_by_package);
+                    CREATE INDEX idx_last_package ON files(last_accessed_by_package);"
+                )?;
+            } else {
+                conn.execute(
+                    "CREATE TABLE IF NOT EXISTS files (
+                        path TEXT PRIMARY KEY,
+                        created_by_package TEXT,
+                        created_by_process TEXT,
+                        created_at INTEGER,
+                        last_accessed_by_package TEXT,
+                        last_accessed_by_process TEXT,
+                        last_accessed_at INTEGER
+                    )",
+                    [],
+                )?;
+                conn.execute(
+                    "CREATE INDEX IF NOT EXISTS idx_package ON files(created_by_package)",
+                    [],
+                )?;
+                conn.execute(
+                    "CREATE INDEX IF NOT EXISTS idx_last_package ON files(last_accessed_by_package)",
+                    [],
+                )?;
             }
-            1 => {} // current version
-            v => return Err(anyhow::anyhow!("Unknown database schema version: {}", v)),
+            conn.execute("PRAGMA user_version = 1", [])?;
+        }
+
+        if version < 2 {
+            // Whether the creator was found by walking up the process tree.
+            // NULL for records made before this was tracked.
+            conn.execute_batch(
+                "ALTER TABLE files ADD COLUMN created_via_parent INTEGER;
+                 PRAGMA user_version = 2;"
+            )?;
+        }
+
+        if version < 3 {
+            // Time-range filters and newest/oldest ordering
+            conn.execute_batch(
+                "CREATE INDEX IF NOT EXISTS idx_created_at ON files(created_at);
+                 CREATE INDEX IF NOT EXISTS idx_last_accessed_at ON files(last_accessed_at);
+                 PRAGMA user_version = 3;"
+            )?;
         }
 
+        if version < 4 {
+            // Size when cataloged, NULL until measured (so an empty file's 0
+            // isn't measured again); existing rows are measured on their next access
+            conn.execute_batch(
+                "ALTER TABLE files ADD COLUMN size_bytes INTEGER;
+                 PRAGMA user_version = 4;"
+            )?;
+        }
+
+        tx.commit()?;
         Ok(())
     }
 
-    pub fn record_access(&self, path: &str, package: &str, process: &str, is_ignored: bool) -> Result<()> {
-        let now = chrono::Utc::now().timestamp();
+    pub fn schema_version(&self) -> Result<i32> {
+        let version: i32 = self.conn.query_row("PRAGMA user_version", [], |r| r.get(0))?;
+        Ok(version)
+    }
 
-        if is_ignored {
+    /// Record accesses, in order, in one transaction. For each, the first
+    /// known accessor of the path becomes its creator, and the event's size
+    /// fills in an unmeasured one. The monitor buffers its writes through
+    /// this so a burst of opens costs one commit instead of one each.
+    pub fn record_access_batch(&self, events: &[AccessEvent]) -> Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        let tx = self.conn.unchecked_transaction()?;
+        for event in events {
+            self.apply_access(event)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn apply_access(&self, event: &AccessEvent) -> Result<()> {
+        let (path, process, via_parent, now, size) =
+            (event.path.as_str(), event.process.as_str(), event.via_parent, event.at, event.size);
+        // Names from the package manager are already normalized; this guards
+        // against stray whitespace from any other source
+        let package = event.package.trim();
+
+        if event.is_ignored {
             let exists: bool = self.conn.query_row(
                 "SELECT 1 FROM files WHERE path = ?1",
                 [path],
@@ -167,18 +512,20 @@ impl Database {
                     "UPDATE files SET
                         last_accessed_by_package = ?2,
                         last_accessed_by_process = ?3,
-                        last_accessed_at = ?4
+                        last_accessed_at = ?4,
+                        size_bytes = COALESCE(size_bytes, ?5)
                      WHERE path = ?1",
-                    params![path, package, process, now],
+                    params![path, package, process, now, size],
                 )?;
             } else {
                 self.conn.execute(
                     "INSERT INTO files (
                         path,
                         created_by_package, created_by_process, created_at,
-                        last_accessed_by_package, last_accessed_by_process, last_accessed_at
-                    ) VALUES (?1, 'unknown', ?3, ?4, ?2, ?3, ?4)",
-                    params![path, package, process, now],
+                        last_accessed_by_package, last_accessed_by_process, last_accessed_at,
+                        size_bytes
+                    ) VALUES (?1, 'unknown', ?3, ?4, ?2, ?3, ?4, ?5)",
+                    params![path, package, process, now, size],
                 )?;
             }
         } else {
@@ -186,12 +533,14 @@ impl Database {
                 "INSERT INTO files (
                     path,
                     created_by_package, created_by_process, created_at,
-                    last_accessed_by_package, last_accessed_by_process, last_accessed_at
-                ) VALUES (?1, ?2, ?3, ?4, ?2, ?3, ?4)
+                    last_accessed_by_package, last_accessed_by_process, last_accessed_at,
+                    created_via_parent, size_bytes
+                ) VALUES (?1, ?2, ?3, ?4, ?2, ?3, ?4, ?5, ?6)
                 ON CONFLICT(path) DO UPDATE SET
                     last_accessed_by_package = ?2,
                     last_accessed_by_process = ?3,
                     last_accessed_at = ?4,
+                    size_bytes = COALESCE(size_bytes, ?6),
                     created_by_package = CASE
                         WHEN created_by_package = 'unknown' THEN ?2
                         ELSE created_by_package
@@ -203,42 +552,107 @@ impl Database {
                     created_at = CASE
                         WHEN created_by_package = 'unknown' THEN ?4
                         ELSE created_at
+                    END,
+                    created_via_parent = CASE
+                        WHEN created_by_package = 'unknown' THEN ?5
+                        ELSE created_via_parent
                     END",
-                params![path, package, process, now],
+                params![path, package, process, now, via_parent, size],
             )?;
         }
+        Ok(())
+    }
 
+    /// Whether the record for `path` has no size yet (NULL, as opposed to a
+    /// measured 0).
+    pub fn size_unmeasured(&self, path: &str) -> bool {
+        self.conn.query_row(
+            "SELECT 1 FROM files WHERE path = ?1 AND size_bytes IS NULL",
+            [path],
+            |_| Ok(true)
+        ).unwrap_or(false)
+    }
+
+    /// Store `size` for `path` unless it was measured already. Sizes are
+    /// taken once, when a path is cataloged; records from before sizes were
+    /// kept get theirs on their next access.
+    pub fn backfill_size(&self, path: &str, size: u64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE files SET size_bytes = ?2 WHERE path = ?1 AND size_bytes IS NULL",
+            params![path, size],
+        )?;
         Ok(())
     }
 
     pub fn prune_deleted(&self) -> Result<usize> {
+        let to_delete = self.find_deleted()?;
+        let count = to_delete.len();
+        self.batch_delete(&to_delete)?;
+        Ok(count)
+    }
+
+    /// Prune records of deleted files, checking only rows created by `packages`.
+    /// Returns the number removed per package, in the order given.
+    pub fn prune_deleted_for_packages(&self, packages: &[String]) -> Result<Vec<(String, usize)>> {
+        let mut stmt = self.conn.prepare("SELECT path FROM files WHERE created_by_package = ?1")?;
+        let mut counts = Vec::with_capacity(packages.len());
+        for package in packages {
+            let to_delete: Vec<String> = stmt
+                .query_map([package], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .filter(|p| !std::path::Path::new(p).exists())
+                .collect();
+            counts.push((package.clone(), self.batch_delete(&to_delete)?));
+        }
+        Ok(counts)
+    }
+
+    /// Paths of records whose files no longer exist on disk.
+    pub fn find_deleted(&self) -> Result<Vec<String>> {
         let mut stmt = self.conn.prepare("SELECT path FROM files")?;
         let paths: Vec<String> = stmt
             .query_map([], |row| row.get(0))?
             .collect::<Result<Vec<_>, _>>()?;
-        drop(stmt);
 
-        let to_delete: Vec<String> = paths
+        let bar = crate::progress::bar(paths.len() as u64, "Checking files");
+        let deleted = paths
             .into_iter()
-            .filter(|p| !std::path::Path::new(p).exists())
+            .filter(|p| {
+                bar.inc(1);
+                !std::path::Path::new(p).exists()
+            })
             .collect();
-
-        let count = to_delete.len();
-        self.batch_delete(&to_delete)?;
-        Ok(count)
+        bar.finish_and_clear();
+        Ok(deleted)
     }
 
-    fn batch_delete(&self, paths: &[String]) -> Result<()> {
+    /// Delete records for `paths` in one transaction, using chunked
+    /// `IN (...)` statements. Returns the number of rows actually removed.
+    fn batch_delete(&self, paths: &[String]) -> Result<usize> {
+        if paths.is_empty() {
+            return Ok(0);
+        }
+        let tx = self.conn.unchecked_transaction()?;
+        let mut total = 0;
         for chunk in paths.chunks(500) {
             let placeholders: Vec<String> = (1..=chunk.len()).map(|i| format!("?{}", i)).collect();
             let sql = format!("DELETE FROM files WHERE path IN ({})", placeholders.join(", "));
             let params: Vec<&dyn rusqlite::ToSql> = chunk.iter().map(|p| p as &dyn rusqlite::ToSql).collect();
-            self.conn.prepare(&sql)?.execute(params.as_slice())?;
+            total += tx.prepare(&sql)?.execute(params.as_slice())?;
         }
-        Ok(())
+        tx.commit()?;
+        Ok(total)
     }
 
     pub fn prune_excluded(&self, excluded_paths: &[String]) -> Result<Vec<String>> {
+        let pruned = self.find_excluded(excluded_paths)?;
+        self.batch_delete(&pruned)?;
+        Ok(pruned)
+    }
+
+    /// Paths of records that fall under any of `excluded_paths`.
+    pub fn find_excluded(&self, excluded_paths: &[String]) -> Result<Vec<String>> {
         if excluded_paths.is_empty() {
             return Ok(vec![]);
         }
@@ -247,9 +661,8 @@ impl Database {
         let paths: Vec<String> = stmt
             .query_map([], |row| row.get(0))?
             .collect::<Result<Vec<_>, _>>()?;
-        drop(stmt);
 
-        let pruned: Vec<String> = paths
+        Ok(paths
             .into_iter()
             .filter(|path| {
                 excluded_paths.iter().any(|ex| {
@@ -258,106 +671,238 @@ impl Database {
                         && (path.len() == base.len() || path[base.len()..].starts_with('/'))
                 })
             })
-            .collect();
+            .collect())
+    }
 
-        self.batch_delete(&pruned)?;
-        Ok(pruned)
+    /// Row count for `query_file` without materializing rows.
+    pub fn count_query_file(&self, pattern: &str, created_since: Option<i64>) -> Result<usize> {
+        let filter = file_filter(&PathMatch::Like(pattern), created_since, None);
+        self.conn
+            .query_row(
+                &format!("SELECT COUNT(*) FROM files{}", filter.where_clause()),
+                rusqlite::params_from_iter(&filter.values),
+                |row| row.get(0),
+            )
+            .map_err(Into::into)
     }
 
-    pub fn query_file(&self, pattern: &str) -> Result<Vec<FileRecord>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT path,
-                    created_by_package, created_by_process, created_at,
-                    last_accessed_by_package, last_accessed_by_process, last_accessed_at
-             FROM files WHERE path LIKE ?1"
-        )?;
+    /// Row count for `query_package` without materializing rows.
+    pub fn count_package(&self, package: &str) -> Result<usize> {
+        self.conn
+            .query_row("SELECT COUNT(*) FROM files WHERE created_by_package = ?1", [package], |row| row.get(0))
+            .map_err(Into::into)
+    }
 
-        let pattern = format!("%{}%", pattern);
-        let records = stmt.query_map([pattern], |row| {
-            Ok(FileRecord {
-                path: row.get(0)?,
-                created_by_package: row.get(1)?,
-                created_by_process: row.get(2)?,
-                created_at: row.get(3)?,
-                last_accessed_by_package: row.get(4)?,
-                last_accessed_by_process: row.get(5)?,
-                last_accessed_at: row.get(6)?,
-            })
-        })?;
+    /// Row count for `query_directory` without materializing rows.
+    pub fn count_directory(&self, dir: &str) -> Result<usize> {
+        let pattern = format!("{}%", dir.trim_end_matches('/'));
+        self.conn
+            .query_row("SELECT COUNT(*) FROM files WHERE path LIKE ?1", [pattern], |row| row.get(0))
+            .map_err(Into::into)
+    }
 
-        records.collect::<Result<Vec<_>, _>>().map_err(Into::into)
+    /// Files whose path matches `path`, optionally only those created at or
+    /// after `created_since` (Unix seconds) and by a package whose name starts
+    /// with `package_prefix`.
+    pub fn query_file(&self, path: PathMatch, created_since: Option<i64>, package_prefix: Option<&str>) -> Result<Vec<FileRecord>> {
+        let filter = file_filter(&path, created_since, package_prefix);
+        let mut stmt = self.conn.prepare(&filter.select_records(""))?;
+        let records = stmt.query_map(rusqlite::params_from_iter(&filter.values), record_from_row)?;
+
+        let PathMatch::Regex(re) = path else {
+            return records.collect::<Result<Vec<_>, _>>().map_err(Into::into);
+        };
+        // Filter while reading so non-matching rows are never kept
+        let mut matched = Vec::new();
+        for record in records {
+            let record = record?;
+            if re.is_match(&record.path) {
+                matched.push(record);
+            }
+        }
+        Ok(matched)
     }
 
     pub fn query_package(&self, package: &str) -> Result<Vec<FileRecord>> {
+        self.query_package_since(package, CreatedRange::default())
+    }
+
+    /// [`query_package`](Self::query_package) limited to files created within `range`.
+    pub fn query_package_since(&self, package: &str, range: CreatedRange) -> Result<Vec<FileRecord>> {
+        let mut filter = Filter::default();
+        filter.created_by(package);
+        filter.created_in(range);
+        let mut stmt = self.conn.prepare(&filter.select_records("ORDER BY last_accessed_at DESC"))?;
+
+        let records = stmt.query_map(rusqlite::params_from_iter(&filter.values), record_from_row)?;
+
+        records.collect::<Result<Vec<_>, _>>().map_err(Into::into)
+    }
+
+    /// Files last accessed by `package`, or with `include_created`, also those it
+    /// created. Uses `idx_last_package` (and `idx_package` for the union).
+    pub fn query_package_accessed(&self, package: &str, include_created: bool) -> Result<Vec<FileRecord>> {
+        let filter = if include_created {
+            "created_by_package = ?1 OR last_accessed_by_package = ?1"
+        } else {
+            "last_accessed_by_package = ?1"
+        };
+        let sql = format!(
+            "SELECT {} FROM files WHERE {} ORDER BY last_accessed_at DESC",
+            SELECT_COLUMNS,
+            filter
+        );
+        let mut stmt = self.conn.prepare(&sql)?;
+
+        let records = stmt.query_map([package], record_from_row)?;
+
+        records.collect::<Result<Vec<_>, _>>().map_err(Into::into)
+    }
+
+    /// How `package`'s records were attributed: (direct, via parent, not
+    /// recorded). Records from before schema version 2 count as not recorded.
+    pub fn package_attribution_counts(&self, package: &str) -> Result<(usize, usize, usize)> {
+        self.conn.query_row(
+            "SELECT COALESCE(SUM(created_via_parent = 0), 0),
+                    COALESCE(SUM(created_via_parent = 1), 0),
+                    COALESCE(SUM(created_via_parent IS NULL), 0)
+             FROM files WHERE created_by_package = ?1",
+            [package],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        ).map_err(Into::into)
+    }
+
+    /// Files `package` was attributed through a parent process, with the
+    /// process that created them.
+    pub fn package_via_parent_files(&self, package: &str) -> Result<Vec<(String, String)>> {
         let mut stmt = self.conn.prepare(
-            "SELECT path,
-                    created_by_package, created_by_process, created_at,
-                    last_accessed_by_package, last_accessed_by_process, last_accessed_at
-             FROM files WHERE created_by_package = ?1 ORDER BY last_accessed_at DESC"
+            "SELECT path, created_by_process FROM files
+             WHERE created_by_package = ?1 AND created_via_parent = 1
+             ORDER BY created_by_process, path"
         )?;
+        let rows = stmt.query_map([package], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(Into::into)
+    }
 
-        let records = stmt.query_map([package], |row| {
-            Ok(FileRecord {
-                path: row.get(0)?,
-                created_by_package: row.get(1)?,
-                created_by_process: row.get(2)?,
-                created_at: row.get(3)?,
-                last_accessed_by_package: row.get(4)?,
-                last_accessed_by_process: row.get(5)?,
-                last_accessed_at: row.get(6)?,
-            })
-        })?;
+    /// Creating processes for `package` with their record counts, most frequent first.
+    pub fn package_process_counts(&self, package: &str) -> Result<Vec<(String, usize)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT created_by_process, COUNT(*) FROM files
+             WHERE created_by_package = ?1
+             GROUP BY created_by_process ORDER BY COUNT(*) DESC, created_by_process"
+        )?;
+        let rows = stmt.query_map([package], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(Into::into)
+    }
+
+    /// Earliest and latest known creation time for `package` (unknown timestamps skipped).
+    pub fn package_time_range(&self, package: &str) -> Result<(Option<i64>, Option<i64>)> {
+        self.conn.query_row(
+            "SELECT MIN(created_at), MAX(created_at) FROM files
+             WHERE created_by_package = ?1 AND created_at > 0",
+            [package],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).map_err(Into::into)
+    }
+
+    /// Files created by `package` within `range`, ordered by creation time and
+    /// capped at `limit`.
+    pub fn query_package_by_age(&self, package: &str, newest_first: bool, limit: usize, range: CreatedRange) -> Result<Vec<FileRecord>> {
+        let order = if newest_first { "DESC" } else { "ASC" };
+        let mut filter = Filter::default();
+        filter.created_by(package);
+        filter.created_in(range);
+        let sql = filter.select_records(&format!("ORDER BY created_at {} LIMIT {}", order, limit));
+        let mut stmt = self.conn.prepare(&sql)?;
+
+        let records = stmt.query_map(rusqlite::params_from_iter(&filter.values), record_from_row)?;
 
         records.collect::<Result<Vec<_>, _>>().map_err(Into::into)
     }
 
     pub fn query_directory(&self, dir: &str) -> Result<Vec<FileRecord>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT path,
-                    created_by_package, created_by_process, created_at,
-                    last_accessed_by_package, last_accessed_by_process, last_accessed_at
-             FROM files WHERE path LIKE ?1 ORDER BY path"
-        )?;
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM files WHERE path LIKE ?1 ORDER BY path",
+            SELECT_COLUMNS
+        ))?;
 
         let pattern = format!("{}%", dir.trim_end_matches('/'));
-        let records = stmt.query_map([pattern], |row| {
-            Ok(FileRecord {
-                path: row.get(0)?,
-                created_by_package: row.get(1)?,
-                created_by_process: row.get(2)?,
-                created_at: row.get(3)?,
-                last_accessed_by_package: row.get(4)?,
-                last_accessed_by_process: row.get(5)?,
-                last_accessed_at: row.get(6)?,
-            })
-        })?;
+        let records = stmt.query_map([pattern], record_from_row)?;
 
         records.collect::<Result<Vec<_>, _>>().map_err(Into::into)
     }
 
+    pub fn get_record(&self, path: &str) -> Result<Option<FileRecord>> {
+        let record = self.conn.query_row(
+            &format!("SELECT {} FROM files WHERE path = ?1", SELECT_COLUMNS),
+            [path],
+            record_from_row,
+        ).optional()?;
+        Ok(record)
+    }
+
     pub fn list_all(&self) -> Result<Vec<FileRecord>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT path,
-                    created_by_package, created_by_process, created_at,
-                    last_accessed_by_package, last_accessed_by_process, last_accessed_at
-             FROM files ORDER BY last_accessed_at DESC"
-        )?;
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM files ORDER BY last_accessed_at DESC",
+            SELECT_COLUMNS
+        ))?;
 
-        let records = stmt.query_map([], |row| {
-            Ok(FileRecord {
-                path: row.get(0)?,
-                created_by_package: row.get(1)?,
-                created_by_process: row.get(2)?,
-                created_at: row.get(3)?,
-                last_accessed_by_package: row.get(4)?,
-                last_accessed_by_process: row.get(5)?,
-                last_accessed_at: row.get(6)?,
-            })
-        })?;
+        let records = stmt.query_map([], record_from_row)?;
+
+        records.collect::<Result<Vec<_>, _>>().map_err(Into::into)
+    }
+
+    /// Records last accessed by a known package other than the one that
+    /// created them, ordered by (creator, accessor) pair and then path.
+    pub fn query_contested(&self) -> Result<Vec<FileRecord>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM files
+             WHERE created_by_package != last_accessed_by_package AND last_accessed_by_package != 'unknown'
+             ORDER BY created_by_package, last_accessed_by_package, path",
+            SELECT_COLUMNS
+        ))?;
+
+        let records = stmt.query_map([], record_from_row)?;
 
         records.collect::<Result<Vec<_>, _>>().map_err(Into::into)
     }
 
+    /// Stream every record, in `sort` order, through `f` without collecting
+    /// them, optionally only those created by a package whose name starts
+    /// with `package_prefix`. Returns the number of records visited.
+    pub fn for_each_record<F>(&self, package_prefix: Option<&str>, sort: RecordSort, f: F) -> Result<usize>
+    where
+        F: FnMut(FileRecord) -> Result<()>,
+    {
+        self.for_each_record_since(package_prefix, CreatedRange::default(), sort, f)
+    }
+
+    /// [`for_each_record`](Self::for_each_record) limited to files created within `range`.
+    pub fn for_each_record_since<F>(&self, package_prefix: Option<&str>, range: CreatedRange, sort: RecordSort, mut f: F) -> Result<usize>
+    where
+        F: FnMut(FileRecord) -> Result<()>,
+    {
+        let mut filter = Filter::default();
+        filter.created_in(range);
+        filter.package_prefix(package_prefix);
+        let mut stmt = self.conn.prepare(&filter.select_records(&format!("ORDER BY {}", sort.order_by())))?;
+
+        let mut rows = stmt.query(rusqlite::params_from_iter(&filter.values))?;
+        let mut count = 0;
+        while let Some(row) = rows.next()? {
+            f(record_from_row(row)?)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    pub fn count_files(&self) -> Result<usize> {
+        let count: usize = self.conn.query_row(
+            "SELECT COUNT(*) FROM files", [], |row| row.get(0)
+        )?;
+        Ok(count)
+    }
+
     pub fn get_stats(&self) -> Result<(usize, usize, String)> {
         let file_count: usize = self.conn.query_row(
             "SELECT COUNT(*) FROM files", [], |row| row.get(0)
@@ -367,18 +912,51 @@ impl Database {
             "SELECT COUNT(DISTINCT created_by_package) FROM files", [], |row| row.get(0)
         )?;
 
-        let db_path = get_user_home().join(".local/share/hdas/attributions.db");
-        let db_location = db_path.to_string_lossy().to_string();
+        let db_location = db_path()?.to_string_lossy().to_string();
 
         Ok((file_count, package_count, db_location))
     }
 
+    /// Record count and total recorded size (`size_bytes`) per creating
+    /// package, most records first.
+    pub fn package_stats(&self) -> Result<Vec<(String, usize, u64)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT created_by_package, COUNT(*), COALESCE(SUM(size_bytes), 0) FROM files
+             GROUP BY created_by_package ORDER BY COUNT(*) DESC, created_by_package"
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(Into::into)
+    }
+
+    /// Record count per creating package, largest first.
+    pub fn package_file_counts(&self) -> Result<Vec<(String, usize)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT created_by_package, COUNT(*) FROM files
+             GROUP BY created_by_package ORDER BY COUNT(*) DESC, created_by_package"
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(Into::into)
+    }
+
+    /// Tracked packages the detected package manager no longer has installed.
     pub fn get_orphans(&self) -> Result<Vec<String>> {
         let pm = crate::pkgmgr::PkgMgr::detect()
             .ok_or_else(|| anyhow::anyhow!("No supported package manager found (need pacman, dpkg, rpm, xbps, or apk)"))?;
+        self.get_orphans_with(&pm)
+    }
 
+    /// [`get_orphans`](Self::get_orphans) using a specific package manager.
+    /// Fails rather than guessing when the installed set can't be read, or
+    /// comes back empty, since either would mark every package as orphaned.
+    pub fn get_orphans_with(&self, pm: &crate::pkgmgr::PkgMgr) -> Result<Vec<String>> {
         let installed = pm.list_installed()
             .map_err(|e| anyhow::anyhow!("Failed to list installed packages via {}: {}", pm.name(), e))?;
+        if installed.is_empty() {
+            anyhow::bail!(
+                "{} reported no installed packages; refusing to treat every tracked package as orphaned",
+                pm.name()
+            );
+        }
 
         let mut stmt = self.conn.prepare(
             "SELECT DISTINCT created_by_package FROM files WHERE created_by_package != 'unknown'"
@@ -387,9 +965,7 @@ impl Database {
         let tracked: Vec<String> = stmt.query_map([], |row| row.get(0))?
             .collect::<Result<Vec<_>, _>>()?;
 
-        Ok(tracked.into_iter()
-            .filter(|p| !installed.contains(p))
-            .collect())
+        Ok(orphaned_packages(pm, tracked, &installed))
     }
 
     /// Get all file records for a list of packages (used by recheck).
@@ -399,29 +975,36 @@ impl Database {
         }
         let placeholders: Vec<String> = (1..=packages.len()).map(|i| format!("?{}", i)).collect();
         let sql = format!(
-            "SELECT path,
-                    created_by_package, created_by_process, created_at,
-                    last_accessed_by_package, last_accessed_by_process, last_accessed_at
-             FROM files WHERE created_by_package IN ({})
+            "SELECT {} FROM files WHERE created_by_package IN ({})
              ORDER BY created_by_package, path",
+            SELECT_COLUMNS,
             placeholders.join(", ")
         );
         let mut stmt = self.conn.prepare(&sql)?;
         let params: Vec<&dyn rusqlite::ToSql> = packages.iter().map(|p| p as &dyn rusqlite::ToSql).collect();
-        let records = stmt.query_map(params.as_slice(), |row| {
-            Ok(FileRecord {
-                path: row.get(0)?,
-                created_by_package: row.get(1)?,
-                created_by_process: row.get(2)?,
-                created_at: row.get(3)?,
-                last_accessed_by_package: row.get(4)?,
-                last_accessed_by_process: row.get(5)?,
-                last_accessed_at: row.get(6)?,
-            })
-        })?;
+        let records = stmt.query_map(params.as_slice(), record_from_row)?;
         records.collect::<Result<Vec<_>, _>>().map_err(Into::into)
     }
 
+    /// Paths of records with no known creator ("unknown"), sorted.
+    pub fn list_unknown(&self) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT path FROM files WHERE created_by_package = 'unknown' ORDER BY path"
+        )?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(Into::into)
+    }
+
+    /// Give an unattributed record its creator. Returns false if the record
+    /// is gone or was attributed in the meantime (e.g. by the monitor).
+    pub fn update_creator(&self, path: &str, package: &str) -> Result<bool> {
+        let changed = self.conn.execute(
+            "UPDATE files SET created_by_package = ?2 WHERE path = ?1 AND created_by_package = 'unknown'",
+            params![path, package],
+        )?;
+        Ok(changed > 0)
+    }
+
     /// Reassign a file's created_by_package to a new owner.
     pub fn reassign_file(&self, path: &str, new_package: &str) -> Result<()> {
         self.conn.execute(
@@ -442,18 +1025,211 @@ impl Database {
         Ok(pruned)
     }
 
+    /// Number of records created by any of `packages`.
+    pub fn count_package_records(&self, packages: &[String]) -> Result<usize> {
+        if packages.is_empty() {
+            return Ok(0);
+        }
+        let placeholders: Vec<String> = (1..=packages.len()).map(|i| format!("?{}", i)).collect();
+        let sql = format!("SELECT COUNT(*) FROM files WHERE created_by_package IN ({})", placeholders.join(", "));
+        let params: Vec<&dyn rusqlite::ToSql> = packages.iter().map(|p| p as &dyn rusqlite::ToSql).collect();
+        let count: usize = self.conn.query_row(&sql, params.as_slice(), |row| row.get(0))?;
+        Ok(count)
+    }
+
+    /// Delete the least recently accessed records until at most `max` remain.
+    /// Returns the number evicted.
+    pub fn evict_oldest(&self, max: usize) -> Result<usize> {
+        let count = self.count_files()?;
+        if count <= max {
+            return Ok(0);
+        }
+        let removed = self.conn.execute(
+            "DELETE FROM files WHERE path IN (
+                SELECT path FROM files ORDER BY last_accessed_at ASC LIMIT ?1
+            )",
+            [count - max],
+        )?;
+        Ok(removed)
+    }
+
+    /// Rebuild the database file to reclaim space from deleted rows.
+    pub fn vacuum(&self) -> Result<()> {
+        self.conn.execute_batch("VACUUM")?;
+        Ok(())
+    }
+
+    /// Whether the database is in incremental auto-vacuum mode.
+    pub fn incremental_vacuum_enabled(&self) -> Result<bool> {
+        let mode: i32 = self.conn.query_row("PRAGMA auto_vacuum", [], |r| r.get(0))?;
+        Ok(mode == 2)
+    }
+
+    /// Switch between incremental and no auto-vacuum. Changing the mode of an
+    /// existing database requires a full VACUUM, so this rewrites the file once.
+    pub fn set_auto_vacuum(&self, incremental: bool) -> Result<()> {
+        let mode = if incremental { "INCREMENTAL" } else { "NONE" };
+        self.conn.execute_batch(&format!("PRAGMA auto_vacuum = {}; VACUUM;", mode))?;
+        Ok(())
+    }
+
+    /// Release free pages back to the filesystem. Cheap, and a no-op unless
+    /// the database is in incremental auto-vacuum mode.
+    pub fn incremental_vacuum(&self) -> Result<()> {
+        self.conn.execute_batch("PRAGMA incremental_vacuum")?;
+        Ok(())
+    }
+
     pub fn delete_file_records(&self, paths: &[String]) -> Result<usize> {
-        if paths.is_empty() {
+        self.batch_delete(paths)
+    }
+
+    /// Move the record for `old`, and any records under it when a directory
+    /// was renamed, to `new` (the monitor calls this when it sees a rename).
+    /// `new` is the full destination; each moved path is mapped under it and
+    /// then through `tracked` (the monitor's depth truncation), so records
+    /// land where a fresh event at the new location would put them; records
+    /// `tracked` maps to None are dropped. If the destination is already
+    /// tracked, its creator is kept and only the last-accessed columns are
+    /// taken from the moved record, when newer. Returns the number of records
+    /// that landed at their destination: sources dropped, or merged into a
+    /// destination without changing it, aren't counted.
+    pub fn rename_record(&self, old: &str, new: &str, tracked: impl Fn(&str) -> Option<String>) -> Result<usize> {
+        if old == new {
             return Ok(0);
         }
-        let mut total = 0;
-        for chunk in paths.chunks(500) {
-            let placeholders: Vec<String> = (1..=chunk.len()).map(|i| format!("?{}", i)).collect();
-            let sql = format!("DELETE FROM files WHERE path IN ({})", placeholders.join(", "));
-            let params: Vec<&dyn rusqlite::ToSql> = chunk.iter().map(|p| p as &dyn rusqlite::ToSql).collect();
-            total += self.conn.prepare(&sql)?.execute(params.as_slice())?;
+        let (low, high) = prefix_range(Some(&format!("{}/", old)));
+        let tx = self.conn.unchecked_transaction()?;
+        let mut moved = 0;
+        // Parents first, so a directory's own record claims a shared destination
+        let sources: Vec<String> = tx
+            .prepare("SELECT path FROM files WHERE path = ?1 OR (path >= ?2 AND path < ?3) ORDER BY path")?
+            .query_map(params![old, low, high], |row| row.get(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        {
+            let mut copy = tx.prepare(
+                "INSERT INTO files (
+                    path,
+                    created_by_package, created_by_process, created_at,
+                    last_accessed_by_package, last_accessed_by_process, last_accessed_at,
+                    created_via_parent, size_bytes
+                )
+                SELECT ?2,
+                    created_by_package, created_by_process, created_at,
+                    last_accessed_by_package, last_accessed_by_process, last_accessed_at,
+                    created_via_parent, size_bytes
+                FROM files WHERE path = ?1
+                ON CONFLICT(path) DO UPDATE SET
+                    last_accessed_by_package = excluded.last_accessed_by_package,
+                    last_accessed_by_process = excluded.last_accessed_by_process,
+                    last_accessed_at = excluded.last_accessed_at
+                WHERE COALESCE(excluded.last_accessed_at, 0) > COALESCE(files.last_accessed_at, 0)"
+            )?;
+            let mut delete = tx.prepare("DELETE FROM files WHERE path = ?1")?;
+            for source in &sources {
+                let dest = tracked(&format!("{}{}", new, &source[old.len()..]));
+                if dest.as_deref() == Some(source.as_str()) {
+                    continue;
+                }
+                if let Some(dest) = dest {
+                    moved += copy.execute(params![source, dest])?;
+                }
+                delete.execute([source])?;
+            }
         }
-        Ok(total)
+        tx.commit()?;
+        Ok(moved)
+    }
+
+    /// Merge one record from an `hdas export` file. A new path is inserted as
+    /// is. For a tracked path, a known creator is never replaced (but an
+    /// `unknown` one is), the last-accessed columns are taken when the
+    /// imported access is newer, and a size fills in an unmeasured one.
+    pub fn import_record(&self, record: &FileRecord) -> Result<ImportOutcome> {
+        let Some(existing) = self.get_record(&record.path)? else {
+            self.restore_record(record)?;
+            return Ok(ImportOutcome::Inserted);
+        };
+
+        let take_creator = existing.created_by_package == "unknown" && record.created_by_package != "unknown";
+        let take_access = record.last_accessed_at > existing.last_accessed_at;
+        if !take_creator && !take_access && record.size_bytes.is_none() {
+            return Ok(ImportOutcome::Skipped);
+        }
+
+        let known = |t: i64| (t != 0).then_some(t);
+        if take_creator {
+            self.conn.execute(
+                "UPDATE files SET created_by_package = ?2, created_by_process = ?3, created_at = ?4,
+                        created_via_parent = NULL
+                 WHERE path = ?1",
+                params![record.path, record.created_by_package, record.created_by_process, known(record.created_at)],
+            )?;
+        }
+        if take_access {
+            self.conn.execute(
+                "UPDATE files SET last_accessed_by_package = ?2, last_accessed_by_process = ?3, last_accessed_at = ?4
+                 WHERE path = ?1",
+                params![
+                    record.path,
+                    record.last_accessed_by_package,
+                    record.last_accessed_by_process,
+                    known(record.last_accessed_at)
+                ],
+            )?;
+        }
+        // Only a NULL (unmeasured) size is filled; a measured one, even 0, stays
+        let size_filled = record.size_bytes.is_some()
+            && self.conn.execute(
+                "UPDATE files SET size_bytes = ?2 WHERE path = ?1 AND size_bytes IS NULL",
+                params![record.path, record.size_bytes],
+            )? > 0;
+        if take_creator || take_access || size_filled {
+            Ok(ImportOutcome::Updated)
+        } else {
+            Ok(ImportOutcome::Skipped)
+        }
+    }
+
+    /// [`import_record`](Self::import_record) for every record, in one
+    /// transaction. Returns (inserted, updated, skipped).
+    pub fn import_records(&self, records: &[FileRecord]) -> Result<(usize, usize, usize)> {
+        let tx = self.conn.unchecked_transaction()?;
+        let (mut inserted, mut updated, mut skipped) = (0, 0, 0);
+        for record in records {
+            match self.import_record(record)? {
+                ImportOutcome::Inserted => inserted += 1,
+                ImportOutcome::Updated => updated += 1,
+                ImportOutcome::Skipped => skipped += 1,
+            }
+        }
+        tx.commit()?;
+        Ok((inserted, updated, skipped))
+    }
+
+    /// Put back a record removed by `clean --archive` when its file is
+    /// restored. Returns false if the path is already tracked again.
+    pub fn restore_record(&self, record: &FileRecord) -> Result<bool> {
+        let known = |t: i64| (t != 0).then_some(t);
+        let inserted = self.conn.execute(
+            "INSERT OR IGNORE INTO files (
+                path,
+                created_by_package, created_by_process, created_at,
+                last_accessed_by_package, last_accessed_by_process, last_accessed_at,
+                size_bytes
+             ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                record.path,
+                record.created_by_package,
+                record.created_by_process,
+                known(record.created_at),
+                record.last_accessed_by_package,
+                record.last_accessed_by_process,
+                known(record.last_accessed_at),
+                record.size_bytes,
+            ],
+        )?;
+        Ok(inserted > 0)
     }
 
     pub fn forget_package(&self, package: &str) -> Result<usize> {
@@ -464,6 +1240,34 @@ impl Database {
         Ok(deleted)
     }
 
+    /// Reassign every record of `old` (as creator or last accessor) to `new`,
+    /// e.g. after an upstream rename. Returns (records created by `old`,
+    /// records last accessed by `old`, rows updated); with `dry_run` nothing
+    /// is changed and rows updated is the number that would be.
+    pub fn merge_package(&self, old: &str, new: &str, dry_run: bool) -> Result<(usize, usize, usize)> {
+        let tx = self.conn.unchecked_transaction()?;
+        let (created, accessed, rows): (usize, usize, usize) = tx.query_row(
+            "SELECT COALESCE(SUM(created_by_package = ?1), 0),
+                    COALESCE(SUM(last_accessed_by_package = ?1), 0),
+                    COUNT(*)
+             FROM files WHERE created_by_package = ?1 OR last_accessed_by_package = ?1",
+            [old],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+        if dry_run {
+            return Ok((created, accessed, rows));
+        }
+        let updated = tx.execute(
+            "UPDATE files SET
+                created_by_package = CASE WHEN created_by_package = ?1 THEN ?2 ELSE created_by_package END,
+                last_accessed_by_package = CASE WHEN last_accessed_by_package = ?1 THEN ?2 ELSE last_accessed_by_package END
+             WHERE created_by_package = ?1 OR last_accessed_by_package = ?1",
+            params![old, new],
+        )?;
+        tx.commit()?;
+        Ok((created, accessed, updated))
+    }
+
     pub fn path_exists(&self, path: &str) -> bool {
         self.conn.query_row(
             "SELECT 1 FROM files WHERE path = ?1",
@@ -490,13 +1294,379 @@ impl Database {
     }
 }
 
-#[derive(Debug, Serialize)]
+/// One access for [`Database::record_access_batch`].
+#[derive(Debug, Clone)]
+pub struct AccessEvent {
+    pub path: String,
+    pub package: String,
+    pub process: String,
+    /// Attributed through an ancestor process rather than the accessing
+    /// process itself
+    pub via_parent: bool,
+    /// From an ignored process: only the last-accessed columns are updated,
+    /// and a new path gets an unknown creator
+    pub is_ignored: bool,
+    /// Unix seconds
+    pub at: i64,
+    /// The path's own size (`lstat`), for a record not measured yet; None
+    /// when it couldn't be read
+    pub size: Option<u64>,
+}
+
+/// How [`Database::query_file`] matches paths.
+#[derive(Debug, Clone, Copy)]
+pub enum PathMatch<'a> {
+    /// The path contains this (SQL LIKE wildcards allowed; empty matches all)
+    Like(&'a str),
+    /// The regex matches somewhere in the path (`query --regex`), checked row
+    /// by row since SQLite has no regex operator
+    Regex(&'a regex::Regex),
+}
+
+/// Creation-time window for `list` and `package` (`--since` / `--until`), in
+/// Unix seconds. Once either end is set, records with an unknown creation
+/// time no longer match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CreatedRange {
+    /// Earliest creation time to include
+    pub since: Option<i64>,
+    /// Creation times at or after this are excluded
+    pub until: Option<i64>,
+}
+
+impl CreatedRange {
+    pub fn is_unbounded(self) -> bool {
+        self.since.is_none() && self.until.is_none()
+    }
+}
+
+/// Order of record listings (`list --sort`, `package --sort`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum RecordSort {
+    /// Most recently accessed first
+    #[default]
+    Accessed,
+    /// Largest first, by size when cataloged
+    Size,
+}
+
+impl RecordSort {
+    fn order_by(self) -> &'static str {
+        match self {
+            Self::Accessed => "last_accessed_at DESC",
+            Self::Size => "size_bytes DESC, last_accessed_at DESC",
+        }
+    }
+
+    /// Put already-fetched records (newest access first) in this order.
+    pub fn apply(self, records: &mut [FileRecord]) {
+        if self == Self::Size {
+            records.sort_by_key(|r| std::cmp::Reverse(r.size_bytes));
+        }
+    }
+}
+
+/// What [`Database::import_record`] did with a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportOutcome {
+    Inserted,
+    /// The creator and/or last access was filled in from the import
+    Updated,
+    /// The database already had the same or better information
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileRecord {
     pub path: String,
     pub created_by_package: String,
     pub created_by_process: String,
+    /// Unix seconds; 0 when unknown (NULL or missing in the database)
     pub created_at: i64,
     pub last_accessed_by_package: String,
     pub last_accessed_by_process: String,
+    /// Unix seconds; 0 when unknown
     pub last_accessed_at: i64,
+    /// The path's own `lstat` size when cataloged (for a directory, its
+    /// entry rather than its contents); None when not measured yet
+    #[serde(default)]
+    pub size_bytes: Option<u64>,
+}
+
+/// The packages in `tracked` that aren't in `installed`, compared by the
+/// package manager's normalized name.
+fn orphaned_packages(
+    pm: &crate::pkgmgr::PkgMgr,
+    tracked: Vec<String>,
+    installed: &std::collections::HashSet<String>,
+) -> Vec<String> {
+    tracked.into_iter()
+        .filter(|p| !installed.contains(&pm.normalize_package(p)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh, fully migrated database that lives only in memory.
+    fn memory_db() -> Database {
+        let conn = Connection::open_in_memory().unwrap();
+        Database::migrate(&conn).unwrap();
+        Database { conn }
+    }
+
+    fn insert(db: &Database, path: &str, package: &str, created_at: i64, accessed_by: &str, accessed_at: i64) {
+        db.conn
+            .execute(
+                "INSERT INTO files (
+                    path,
+                    created_by_package, created_by_process, created_at,
+                    last_accessed_by_package, last_accessed_by_process, last_accessed_at
+                ) VALUES (?1, ?2, ?2, ?3, ?4, ?4, ?5)",
+                params![path, package, created_at, accessed_by, accessed_at],
+            )
+            .unwrap();
+    }
+
+    fn paths(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("/home/u/.cache/app/{:04}", i)).collect()
+    }
+
+    #[test]
+    fn batch_delete_spans_chunks() {
+        let db = memory_db();
+        let all = paths(1234);
+        for path in &all {
+            insert(&db, path, "app", 1, "app", 1);
+        }
+        insert(&db, "/home/u/.cache/other", "other", 1, "other", 1);
+
+        // Untracked paths don't count towards the result
+        let mut to_delete = all.clone();
+        to_delete.push("/home/u/.cache/never-tracked".to_string());
+        assert_eq!(db.delete_file_records(&to_delete).unwrap(), 1234);
+        assert_eq!(db.count_files().unwrap(), 1);
+        assert!(db.get_record("/home/u/.cache/other").unwrap().is_some());
+    }
+
+    #[test]
+    fn batch_delete_failure_rolls_back_every_chunk() {
+        let db = memory_db();
+        let all = paths(1234);
+        for path in &all {
+            insert(&db, path, "app", 1, "app", 1);
+        }
+        // Fail inside the third chunk, after two have been deleted
+        db.conn
+            .execute_batch(
+                "CREATE TRIGGER refuse BEFORE DELETE ON files WHEN old.path = '/home/u/.cache/app/1100'
+                 BEGIN SELECT RAISE(ABORT, 'refused'); END;",
+            )
+            .unwrap();
+
+        assert!(db.delete_file_records(&all).is_err());
+        assert_eq!(db.count_files().unwrap(), 1234);
+    }
+
+    /// What SQLite plans to do for `sql` with `values` bound, one step per line.
+    fn query_plan(db: &Database, sql: &str, values: &[rusqlite::types::Value]) -> String {
+        let mut stmt = db.conn.prepare(&format!("EXPLAIN QUERY PLAN {}", sql)).unwrap();
+        let steps = stmt
+            .query_map(rusqlite::params_from_iter(values), |row| row.get::<_, String>(3))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        steps.join("\n")
+    }
+
+    #[test]
+    fn time_filters_use_their_indexes() {
+        let db = memory_db();
+
+        let filter = file_filter(&PathMatch::Like("mozilla"), Some(1_700_000_000), None);
+        let plan = query_plan(&db, &filter.select_records(""), &filter.values);
+        assert!(plan.contains("USING INDEX idx_created_at (created_at>?)"), "{}", plan);
+
+        let re = regex::Regex::new("cache").unwrap();
+        let filter = file_filter(&PathMatch::Regex(&re), Some(1_700_000_000), None);
+        let plan = query_plan(&db, &filter.select_records(""), &filter.values);
+        assert!(plan.contains("USING INDEX idx_created_at (created_at>?)"), "{}", plan);
+
+        let plan = query_plan(&db, &Filter::default().select_records("ORDER BY last_accessed_at DESC"), &[]);
+        assert!(plan.contains("USING INDEX idx_last_accessed_at"), "{}", plan);
+
+        // `list --since/--until`
+        let range = CreatedRange { since: Some(1_700_000_000), until: Some(1_710_000_000) };
+        let mut filter = Filter::default();
+        filter.created_in(range);
+        let plan = query_plan(&db, &filter.select_records("ORDER BY last_accessed_at DESC"), &filter.values);
+        assert!(plan.contains("USING INDEX idx_created_at (created_at>? AND created_at<?)"), "{}", plan);
+
+        // `package --since`, with and without a range
+        for range in [range, CreatedRange::default()] {
+            let mut filter = Filter::default();
+            filter.created_by("firefox");
+            filter.created_in(range);
+            let plan = query_plan(&db, &filter.select_records("ORDER BY created_at DESC LIMIT 10"), &filter.values);
+            assert!(!plan.contains("SCAN files"), "{}", plan);
+        }
+    }
+
+    #[test]
+    fn unused_filters_leave_no_condition() {
+        let filter = file_filter(&PathMatch::Like("x"), None, None);
+        assert_eq!(filter.conditions, vec!["path LIKE ?"]);
+        assert_eq!(filter.values.len(), 1);
+        assert!(!Filter::default().select_records("").contains("WHERE"));
+
+        let mut filter = Filter::default();
+        filter.created_in(CreatedRange::default());
+        assert!(filter.conditions.is_empty());
+        filter.created_in(CreatedRange { since: None, until: Some(500) });
+        assert_eq!(filter.conditions, vec!["created_at >= ?", "created_at < ?"]);
+    }
+
+    #[test]
+    fn orphans_compare_normalized_names() {
+        use crate::pkgmgr::PkgMgr;
+
+        // Installed lists are normalized when read; tracked names are as recorded
+        let installed: std::collections::HashSet<String> =
+            ["firefox-esr", "libc6", "bash"].iter().map(|s| s.to_string()).collect();
+        let tracked = vec![
+            "Firefox-ESR".to_string(),
+            "libc6:amd64".to_string(),
+            " bash ".to_string(),
+            "discord".to_string(),
+            "libfoo:i386".to_string(),
+        ];
+        assert_eq!(
+            orphaned_packages(&PkgMgr::Dpkg, tracked, &installed),
+            vec!["discord", "libfoo:i386"]
+        );
+
+        let installed: std::collections::HashSet<String> = ["firefox"].iter().map(|s| s.to_string()).collect();
+        let tracked = vec!["firefox".to_string(), "FIREFOX".to_string(), "thunderbird".to_string()];
+        assert_eq!(orphaned_packages(&PkgMgr::Pacman, tracked, &installed), vec!["FIREFOX", "thunderbird"]);
+    }
+
+    #[test]
+    fn rename_onto_a_tracked_path_keeps_its_creator() {
+        let db = memory_db();
+        insert(&db, "/h/.config/old", "newpkg", 300, "newpkg", 900);
+        insert(&db, "/h/.config/new", "oldpkg", 100, "oldpkg", 200);
+
+        assert_eq!(db.rename_record("/h/.config/old", "/h/.config/new", |p| Some(p.to_string())).unwrap(), 1);
+        assert!(db.get_record("/h/.config/old").unwrap().is_none());
+        let record = db.get_record("/h/.config/new").unwrap().unwrap();
+        assert_eq!(record.created_by_package, "oldpkg");
+        assert_eq!(record.created_at, 100);
+        assert_eq!(record.last_accessed_by_package, "newpkg");
+        assert_eq!(record.last_accessed_at, 900);
+
+        // An older access doesn't replace the destination's
+        insert(&db, "/h/.config/stale", "stalepkg", 50, "stalepkg", 60);
+        assert_eq!(db.rename_record("/h/.config/stale", "/h/.config/new", |p| Some(p.to_string())).unwrap(), 0);
+        assert!(db.get_record("/h/.config/stale").unwrap().is_none());
+        let record = db.get_record("/h/.config/new").unwrap().unwrap();
+        assert_eq!(record.last_accessed_by_package, "newpkg");
+        assert_eq!(record.last_accessed_at, 900);
+    }
+
+    #[test]
+    fn renamed_children_are_truncated_from_their_full_path() {
+        let db = memory_db();
+        insert(&db, "/h/.config/app", "app", 100, "app", 100);
+        insert(&db, "/h/.config/app/sub", "app", 100, "app", 100);
+        // Depth 1 under .config, as the monitor would truncate
+        let tracked = |p: &str| {
+            let rest = p.strip_prefix("/h/.config/")?;
+            Some(format!("/h/.config/{}", rest.split('/').next()?))
+        };
+
+        // The child merges into the directory's new record without changing it
+        assert_eq!(db.rename_record("/h/.config/app", "/h/.config/backup/app", tracked).unwrap(), 1);
+        let paths: Vec<String> = db
+            .conn
+            .prepare("SELECT path FROM files")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(paths, vec!["/h/.config/backup"]);
+    }
+
+    fn access(path: &str, size: Option<u64>) -> AccessEvent {
+        AccessEvent {
+            path: path.to_string(),
+            package: "pkg".to_string(),
+            process: "proc".to_string(),
+            via_parent: false,
+            is_ignored: false,
+            at: 100,
+            size,
+        }
+    }
+
+    fn stored_size(db: &Database, path: &str) -> Option<u64> {
+        db.conn.query_row("SELECT size_bytes FROM files WHERE path = ?1", [path], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn sizes_are_measured_once() {
+        let db = memory_db();
+        db.record_access_batch(&[access("/h/.config/a", None), access("/h/.config/b", Some(0))]).unwrap();
+        assert_eq!(stored_size(&db, "/h/.config/a"), None);
+        assert_eq!(stored_size(&db, "/h/.config/b"), Some(0));
+        assert!(db.size_unmeasured("/h/.config/a"));
+        assert!(!db.size_unmeasured("/h/.config/b"));
+
+        // A measured 0 stays; an unmeasured size is filled in
+        db.record_access_batch(&[access("/h/.config/a", Some(7)), access("/h/.config/b", Some(9))]).unwrap();
+        assert_eq!(stored_size(&db, "/h/.config/a"), Some(7));
+        assert_eq!(stored_size(&db, "/h/.config/b"), Some(0));
+
+        db.backfill_size("/h/.config/a", 50).unwrap();
+        assert_eq!(stored_size(&db, "/h/.config/a"), Some(7));
+    }
+
+    #[test]
+    fn measured_empty_size_survives_export_and_restore() {
+        let db = memory_db();
+        db.record_access_batch(&[access("/h/.config/empty", Some(0)), access("/h/.config/new", None)]).unwrap();
+
+        let exported = serde_json::to_string(&db.list_all().unwrap()).unwrap();
+        let records: Vec<FileRecord> = serde_json::from_str(&exported).unwrap();
+        let fresh = memory_db();
+        for record in &records {
+            fresh.restore_record(record).unwrap();
+        }
+        assert_eq!(stored_size(&fresh, "/h/.config/empty"), Some(0));
+        assert_eq!(stored_size(&fresh, "/h/.config/new"), None);
+
+        // Exports from before sizes were kept have no size field
+        let old: FileRecord = serde_json::from_str(
+            r#"{"path":"/h/.config/x","created_by_package":"p","created_by_process":"p","created_at":1,
+                "last_accessed_by_package":"p","last_accessed_by_process":"p","last_accessed_at":1}"#,
+        )
+        .unwrap();
+        assert_eq!(old.size_bytes, None);
+    }
+
+    #[test]
+    fn import_fills_only_an_unmeasured_size() {
+        let db = memory_db();
+        db.record_access_batch(&[access("/h/.config/empty", Some(0)), access("/h/.config/new", None)]).unwrap();
+        let stale = |path: &str| FileRecord { size_bytes: Some(4096), ..db.get_record(path).unwrap().unwrap() };
+
+        let empty = stale("/h/.config/empty");
+        assert!(matches!(db.import_record(&empty).unwrap(), ImportOutcome::Skipped));
+        assert_eq!(stored_size(&db, "/h/.config/empty"), Some(0));
+
+        let new = stale("/h/.config/new");
+        assert!(matches!(db.import_record(&new).unwrap(), ImportOutcome::Updated));
+        assert_eq!(stored_size(&db, "/h/.config/new"), Some(4096));
+    }
 }
@@ -0,0 +1,51 @@
+//! Structured monitor event logging to the systemd journal (`monitor --journald`).
+//! Only compiled in with the `journald` feature so the default build doesn't
+//! pull in the journal client.
+
+/// Whether this binary was built with journald support.
+pub const SUPPORTED: bool = cfg!(feature = "journald");
+
+/// Path of journald's native protocol socket.
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Journal fields attached to each recorded event, so `journalctl` can filter
+/// with e.g. `PACKAGE=firefox` or `ATTRIBUTION=via_parent`.
+#[cfg_attr(not(feature = "journald"), allow(dead_code))]
+pub struct EventFields<'a> {
+    /// Process (thread group) id of the opener (or remover, or renamer)
+    pub pid: u32,
+    /// Thread that made the syscall; differs from `pid` in worker threads
+    pub tid: u32,
+    pub package: &'a str,
+    pub process: &'a str,
+    pub path: &'a str,
+    /// `created`, `via_parent`, `ignored`, `deleted`, or `renamed`
+    pub attribution: &'static str,
+}
+
+/// True if journald is running and accepting native-protocol messages.
+pub fn available() -> bool {
+    SUPPORTED && std::path::Path::new(JOURNAL_SOCKET).exists()
+}
+
+/// Send one event to the journal. Returns false if it couldn't be sent, so
+/// the caller can fall back to stdout.
+#[cfg(feature = "journald")]
+pub fn send(message: &str, fields: &EventFields) -> bool {
+    use libsystemd::logging::{journal_send, Priority};
+    let (pid, tid) = (fields.pid.to_string(), fields.tid.to_string());
+    let vars = [
+        ("OPENER_PID", pid.as_str()),
+        ("OPENER_TID", tid.as_str()),
+        ("PACKAGE", fields.package),
+        ("PROCESS", fields.process),
+        ("PATH", fields.path),
+        ("ATTRIBUTION", fields.attribution),
+    ];
+    journal_send(Priority::Info, message, vars.into_iter()).is_ok()
+}
+
+#[cfg(not(feature = "journald"))]
+pub fn send(_message: &str, _fields: &EventFields) -> bool {
+    false
+}
@@ -1,11 +1,15 @@
-use clap::{Parser, Subcommand, CommandFactory};
+use clap::{FromArgMatches, Parser, Subcommand, CommandFactory};
 use anyhow::Result;
+use std::io::IsTerminal;
 
+mod archive;
 mod cleanup;
 mod config;
 mod db;
+mod journald;
 mod monitor;
 mod pkgmgr;
+mod progress;
 mod query;
 
 /// Home Directory Attribution System - track which packages create files in your home directory
@@ -17,28 +21,41 @@ mod query;
 {usage-heading} {usage}
 
 Query:
-  list           List all cataloged files and their package attributions
-  package        Show all files created by a specific package
-  dir            Show all tracked files under a directory
-  query          Search files by path pattern
-  orphans        Show files from packages that are no longer installed
-  recheck        Re-check orphan files and fix misattributions
+  list             List all cataloged files and their package attributions
+  package          Show all files created by a specific package
+  dir              Show all tracked files under a directory
+  tree             Show tracked files as a directory tree
+  query            Search files by path pattern
+  orphans          Show files from packages that are no longer installed
+  contested        Show files last accessed by a package other than their creator
+  recheck          Re-check orphan files and fix misattributions
+  verify           Re-attribute unknown records via the package manager
+  export           Export all records as JSON, JSON Lines, or CSV
 
 Cleanup:
-  clean          Delete files created by a specific package
-  clean-orphans  Delete all files from uninstalled packages
-  prune          Remove stale records (deleted, excluded, ignored)
-  forget         Drop database records for a package (no file deletion)
+  clean            Delete files created by a specific package
+  clean-orphans    Delete all files from uninstalled packages
+  prune            Remove stale records (deleted, excluded, ignored)
+  forget           Drop database records for a package or path (no file deletion)
+  gc               Prune, drop orphan records, and vacuum in one pass
+  merge-package    Reassign a renamed package's records to its new name
+  restore-archive  Put back files saved by `clean --archive`
 
 Info:
-  status         Show monitor, database, and config at a glance
-  explain        Show how a path would be tracked (depth truncation)
+  status           Show monitor, database, and config at a glance
+  stats            Per-package leaderboard by file count or disk usage
+  top              Rank packages by file count or recorded size
+  explain          Show how a path would be tracked (depth truncation)
+  version          Show version, schema, and environment details
+  doctor           Check the environment and report what's broken
 
 Admin:
-  monitor        Start the eBPF monitor daemon (requires root)
-  config         Manage configuration (show, edit, init, validate)
-  ignore         Add a package to ignored_packages and prune its records
-  exclude        Add a path to excluded_paths and prune its records
+  monitor          Start the eBPF monitor daemon (requires root)
+  config           Manage configuration (show, edit, init, validate)
+  ignore           Add a package to ignored_packages and prune its records
+  exclude          Add a path to excluded_paths and prune its records
+  migrate-db       Report the database schema version and run pending migrations
+  import           Merge records from an `hdas export` file
 
 {options}
 Use \"hdas help <command>\" for more information about a command.
@@ -50,6 +67,45 @@ struct Cli {
     /// Output results as JSON
     #[arg(long, global = true)]
     json: bool,
+
+    /// Like --json, but record listings (list, query, package, dir) stream one
+    /// compact JSON object per line, without the envelope
+    #[arg(long, global = true)]
+    ndjson: bool,
+
+    /// strftime format for displayed timestamps (overrides time_format in config)
+    #[arg(long, global = true, value_name = "FORMAT")]
+    time_format: Option<String>,
+
+    /// IANA timezone for displayed timestamps, e.g. UTC or Europe/Berlin
+    /// (overrides timezone in config; default is system local time / $TZ)
+    #[arg(long, global = true, value_name = "ZONE")]
+    timezone: Option<String>,
+
+    /// Resolve ~, relative paths, and monitored dirs against this home instead
+    /// (e.g. a mounted backup); the database and config are unaffected
+    #[arg(long, global = true, value_name = "PATH")]
+    home: Option<std::path::PathBuf>,
+
+    /// Print how long the main phases (DB query, existence checks, sizes) took to stderr
+    #[arg(long, global = true)]
+    debug_timing: bool,
+
+    /// Show progress bars on stderr for long scans (existence checks, sizes, ownership lookups)
+    #[arg(long, global = true)]
+    progress: bool,
+
+    /// When to color output; auto colors a terminal unless NO_COLOR is set
+    #[arg(long, global = true, value_enum, value_name = "WHEN", default_value = "auto")]
+    color: query::ColorWhen,
+
+    /// Size units for displayed sizes (overrides size_units in config)
+    #[arg(long, global = true, value_enum, value_name = "UNITS")]
+    size_units: Option<config::SizeUnits>,
+
+    /// Force a package manager backend instead of auto-detecting
+    #[arg(long, global = true, value_enum, value_name = "NAME")]
+    package_manager: Option<pkgmgr::PkgMgr>,
 }
 
 #[derive(Subcommand)]
@@ -57,71 +113,335 @@ enum Commands {
     // ── Querying ─────────────────────────────────────────────
 
     /// List all cataloged files and their package attributions
-    List,
+    List {
+        /// Only files created by this package (like `package`); `unknown` groups
+        /// unattributed files by creating process
+        #[arg(long, value_name = "PKG")]
+        created_by: Option<String>,
+        /// Undecorated output for scripts: tab-separated lines or NUL-terminated fields
+        #[arg(long, value_enum, value_name = "FORMAT", conflicts_with_all = ["json", "ndjson"])]
+        format: Option<query::RecordFormat>,
+        /// Only files whose creating package matches this regex (e.g. '^python3?-')
+        #[arg(long, value_name = "REGEX", conflicts_with = "created_by")]
+        package_regex: Option<String>,
+        /// Only files whose creating process matches this regex (e.g. 'pip|poetry')
+        #[arg(long, value_name = "REGEX", conflicts_with = "created_by")]
+        process_regex: Option<String>,
+        /// Only files created at or after DATE: YYYY-MM-DD (local time) or an age like 7d, 24h, 30m, 2w
+        #[arg(long, value_name = "DATE")]
+        since: Option<String>,
+        /// Only files created before DATE (a YYYY-MM-DD date includes that whole day)
+        #[arg(long, value_name = "DATE")]
+        until: Option<String>,
+        /// Order of the listing: most recently accessed first, or largest first
+        #[arg(long, value_enum, value_name = "KEY", default_value = "accessed")]
+        sort: db::RecordSort,
+    },
     /// Show all files created by a specific package
     Package {
         /// Package name to look up
         name: String,
+        /// Show only the N most recently created files
+        #[arg(long, value_name = "N", conflicts_with = "oldest")]
+        newest: Option<usize>,
+        /// Show only the N oldest files
+        #[arg(long, value_name = "N")]
+        oldest: Option<usize>,
+        /// Summarize file count, creating processes, date range, and size instead of listing files
+        #[arg(long, conflicts_with_all = ["newest", "oldest"])]
+        summary: bool,
+        /// Print only the number of matching records
+        #[arg(long, conflicts_with_all = ["newest", "oldest", "summary"])]
+        count_only: bool,
+        /// Print the common directory of the package's files and open it with xdg-open
+        #[arg(long, conflicts_with_all = ["newest", "oldest", "summary", "count_only"])]
+        open: bool,
+        /// Split files into runtime-generated and shipped by the (installed) package
+        #[arg(long, conflicts_with_all = ["newest", "oldest", "summary", "count_only", "open"])]
+        diff_installed: bool,
+        /// List files the package last accessed (including ones other packages created)
+        #[arg(long, conflicts_with_all = ["newest", "oldest", "summary", "count_only", "open", "diff_installed"])]
+        accessed: bool,
+        /// List files the package created or last accessed, labeled with its role(s), newest access first
+        #[arg(long, visible_alias = "full", conflicts_with_all = ["newest", "oldest", "summary", "count_only", "open", "diff_installed", "accessed"])]
+        both: bool,
+        /// Count files attributed directly vs via a parent process, and list the latter
+        #[arg(long, conflicts_with_all = ["newest", "oldest", "summary", "count_only", "open", "diff_installed", "accessed", "both"])]
+        attribution: bool,
+        /// Undecorated output for scripts: tab-separated lines or NUL-terminated fields
+        #[arg(long, value_enum, value_name = "FORMAT", conflicts_with_all = ["json", "ndjson", "summary", "count_only", "open", "diff_installed", "accessed", "both", "attribution"])]
+        format: Option<query::RecordFormat>,
+        /// Write the package's records (respecting --newest/--oldest) to FILE as JSON for review;
+        /// feed it back with `hdas clean --from-stdin < FILE`
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["summary", "count_only", "open", "diff_installed", "accessed", "both", "attribution", "format"])]
+        export_manifest: Option<std::path::PathBuf>,
+        /// Ask the package manager who owns each file now and flag records that disagree (one lookup per file)
+        #[arg(long, conflicts_with_all = ["summary", "count_only", "open", "diff_installed", "accessed", "both", "attribution", "format", "export_manifest"])]
+        owner_check: bool,
+        /// Order of the listing: most recently accessed first, or largest first
+        #[arg(long, value_enum, value_name = "KEY", default_value = "accessed", conflicts_with_all = ["summary", "count_only", "open", "diff_installed", "accessed", "both", "attribution"])]
+        sort: db::RecordSort,
+        /// Only files created at or after DATE: YYYY-MM-DD (local time) or an age like 7d, 24h, 30m, 2w
+        #[arg(long, value_name = "DATE", conflicts_with_all = ["summary", "count_only", "open", "diff_installed", "accessed", "both", "attribution"])]
+        since: Option<String>,
+        /// Only files created before DATE (a YYYY-MM-DD date includes that whole day)
+        #[arg(long, value_name = "DATE", conflicts_with_all = ["summary", "count_only", "open", "diff_installed", "accessed", "both", "attribution"])]
+        until: Option<String>,
+        /// Delete the package's files and forget all of its records, even for files that couldn't be deleted
+        #[arg(long, conflicts_with_all = ["newest", "oldest", "summary", "count_only", "open", "diff_installed", "accessed", "both", "attribution", "format", "export_manifest", "owner_check", "since", "until"])]
+        purge: bool,
+        /// With --purge: skip the confirmation prompt
+        #[arg(short, long, requires = "purge")]
+        force: bool,
+        /// With --purge: show what would be deleted and forgotten without doing it
+        #[arg(short = 'n', long, requires = "purge")]
+        dry_run: bool,
+        /// With --purge: VACUUM the database afterwards to reclaim its space
+        #[arg(long, requires = "purge")]
+        vacuum: bool,
     },
     /// Show all tracked files under a directory
     Dir {
         /// Directory path (absolute, relative to ~, or with ~/ prefix)
         path: String,
+        /// Print only the number of matching records
+        #[arg(long)]
+        count_only: bool,
+    },
+    /// Show tracked files as a directory tree with per-directory record counts
+    Tree {
+        /// Directory to root the tree at (absolute, relative to ~, or with ~/ prefix); default is every record
+        dir: Option<String>,
+        /// Only files created by this package
+        #[arg(short, long)]
+        package: Option<String>,
     },
     /// Query files by path pattern (supports SQL LIKE wildcards)
     Query {
-        /// Path pattern to search for (e.g. "mozilla", "%.cache%")
-        pattern: String,
+        /// Path pattern to search for (e.g. "mozilla", "%.cache%"); optional with a time range, --not-exists, or a regex filter
+        #[arg(required_unless_present_any = ["today", "this_week", "this_month", "last_boot", "not_exists", "package_regex", "process_regex"])]
+        pattern: Option<String>,
+        /// Treat the pattern as a regex matched anywhere in the path (e.g. '\.config/.*\.log$')
+        /// instead of a LIKE substring
+        #[arg(short = 'e', long, requires = "pattern")]
+        regex: bool,
+        /// Print only the number of matching records
+        #[arg(long)]
+        count_only: bool,
+        /// Only files created since midnight (in the display timezone)
+        #[arg(long, group = "range")]
+        today: bool,
+        /// Only files created since Monday 00:00 (in the display timezone)
+        #[arg(long, group = "range")]
+        this_week: bool,
+        /// Only files created since the 1st of the month, 00:00 (in the display timezone)
+        #[arg(long, group = "range")]
+        this_month: bool,
+        /// Only files created since the system booted
+        #[arg(long, group = "range")]
+        last_boot: bool,
+        /// Only records whose files no longer exist (what `prune` would remove); skips auto-prune
+        #[arg(long)]
+        not_exists: bool,
+        /// Undecorated output for scripts: tab-separated lines or NUL-terminated fields
+        #[arg(long, value_enum, value_name = "FORMAT", conflicts_with_all = ["json", "ndjson", "count_only"])]
+        format: Option<query::RecordFormat>,
+        /// Ask the package manager who owns each file now and flag records that disagree (one lookup per file)
+        #[arg(long, conflicts_with_all = ["count_only", "format"])]
+        owner_check: bool,
+        /// Only files whose creating package matches this regex (e.g. '^python3?-')
+        #[arg(long, value_name = "REGEX")]
+        package_regex: Option<String>,
+        /// Only files whose creating process matches this regex (e.g. 'pip|poetry')
+        #[arg(long, value_name = "REGEX")]
+        process_regex: Option<String>,
     },
     /// Show files from packages that are no longer installed
-    Orphans,
+    Orphans {
+        /// Also list files that couldn't be attributed ("unknown"), grouped by creating process
+        #[arg(long)]
+        include_unknown: bool,
+    },
+    /// Show files last accessed by a different package than the one that created them
+    Contested,
     /// Re-check orphan files against package manager and reassign ownership
     Recheck,
+    /// Ask the package manager who owns each "unknown" record's path and attribute it to that package
+    Verify {
+        /// Show what would be re-attributed without modifying the database
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
+    /// Export every record as JSON, JSON Lines, or CSV
+    Export {
+        /// Output format
+        #[arg(long, value_enum, default_value = "json")]
+        format: query::ExportFormat,
+        /// Write to this file instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<std::path::PathBuf>,
+    },
 
     // ── Cleanup ──────────────────────────────────────────────
 
     /// Delete files created by a specific package
     Clean {
         /// Package whose files should be deleted
-        package: String,
+        #[arg(required_unless_present = "from_stdin")]
+        package: Option<String>,
+        /// Read paths to delete from stdin (one per line, or a `package --export-manifest` file)
+        #[arg(long, conflicts_with = "package")]
+        from_stdin: bool,
+        /// Keep paths matching this glob (repeatable; matched against the full path)
+        #[arg(long, value_name = "GLOB")]
+        exclude: Vec<String>,
         /// Skip confirmation prompt
         #[arg(short, long)]
         force: bool,
         /// Show what would be deleted without actually deleting
         #[arg(short = 'n', long)]
         dry_run: bool,
+        /// Write a JSON manifest of deleted paths (sizes, packages, timestamps, errors) to this file
+        #[arg(long, value_name = "FILE", conflicts_with = "dry_run")]
+        report: Option<std::path::PathBuf>,
+        /// Refuse to clean if a target is under an unmounted /etc/fstab entry, on a read-only mount, or a mount point
+        #[arg(long)]
+        check_mounts: bool,
+        /// First save everything into a zstd-compressed tarball under ~/.local/share/hdas/archive
+        /// (undo with `restore-archive`)
+        #[arg(long)]
+        archive: bool,
     },
     /// Delete all files from uninstalled packages
     CleanOrphans {
+        /// Keep paths matching this glob (repeatable; matched against the full path)
+        #[arg(long, value_name = "GLOB")]
+        exclude: Vec<String>,
         /// Skip confirmation prompt
         #[arg(short, long)]
         force: bool,
         /// Show what would be deleted without actually deleting
         #[arg(short = 'n', long)]
         dry_run: bool,
+        /// Skip the mount safety check (see `clean --check-mounts`), which is on by default here
+        #[arg(long)]
+        no_check_mounts: bool,
     },
     /// Remove stale records (deleted files, excluded paths, ignored packages)
-    Prune,
-    /// Drop database records for a package without deleting files or changing config
+    Prune {
+        /// Only prune deleted files created by these packages (comma-separated or repeated)
+        #[arg(long, value_name = "PKG", value_delimiter = ',')]
+        packages: Vec<String>,
+    },
+    /// Prune stale records, drop gone orphan records, and vacuum if worthwhile
+    Gc {
+        /// Show what would be removed without modifying the database
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
+    /// Drop database records for a package or path without deleting files or changing config
     Forget {
-        /// Package name whose records should be removed
-        package: String,
+        /// Package name, or a path (anything containing '/' or starting with '~'; relative paths
+        /// are taken from the home directory) whose record should be removed
+        target: String,
+        /// Treat the path as a shell-style glob (e.g. '~/.cache/*.log') and list the matches
+        /// before forgetting them
+        #[arg(long)]
+        glob: bool,
+        /// With --glob: skip the confirmation prompt
+        #[arg(short, long, requires = "glob")]
+        force: bool,
+    },
+    /// Reassign a renamed or replaced package's records to its new name
+    MergePackage {
+        /// Package name the records are currently attributed to
+        old: String,
+        /// Package name to attribute them to
+        new: String,
+        /// Show how many records would change without modifying the database
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
+    /// Put back files saved by `clean --archive` and re-add their records
+    RestoreArchive {
+        /// Archive name or path (omit to list the available archives)
+        archive: Option<String>,
+        /// Show what would be restored without extracting anything
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+        /// Replace paths that exist again instead of skipping them
+        #[arg(long)]
+        overwrite: bool,
+        /// Accept an archive from outside the archive directory (~/.local/share/hdas/archive)
+        #[arg(long)]
+        allow_external: bool,
     },
 
     // ── Info ─────────────────────────────────────────────────
 
     /// Show monitor, database, and config status at a glance
     Status,
+    /// Per-package leaderboard by file count, or by disk usage with --packages-by-size
+    Stats {
+        /// Rank packages by disk space used by their existing files (slow on large catalogs)
+        #[arg(long)]
+        packages_by_size: bool,
+        /// Show only the first N packages
+        #[arg(long, value_name = "N")]
+        top: Option<usize>,
+    },
+    /// Rank packages by file count or total recorded size (database only, fast)
+    Top {
+        /// Show only the first N packages (0 for all)
+        #[arg(short, long, default_value = "10")]
+        limit: usize,
+        /// Rank by total recorded size instead of file count
+        #[arg(long)]
+        by_size: bool,
+    },
     /// Explain how a path would be tracked (show depth truncation)
     Explain {
         /// Full path to test (e.g. ~/.cache/mozilla/firefox/something)
         path: String,
+        /// List every monitored dir that matches, marking the one that wins
+        #[arg(long)]
+        all: bool,
     },
+    /// Show version, database schema, and environment details
+    Version,
+    /// Check database, config, package manager, BPF, and service health
+    Doctor,
 
     // ── Administration ───────────────────────────────────────
 
     /// Start the eBPF monitor daemon (requires root)
-    Monitor,
+    Monitor {
+        /// Only record events from this uid (default: owner of the monitored home)
+        #[arg(long)]
+        uid: Option<u32>,
+        /// Don't walk the process tree; record "unknown" when the direct exe isn't packaged
+        #[arg(long)]
+        no_parent_walk: bool,
+        /// Per-CPU perf buffer size in pages, a power of two (default: 64)
+        #[arg(long, value_name = "N")]
+        perf_pages: Option<usize>,
+        /// Resolve and print attributions without writing to the database
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+        /// Stop after this many seconds
+        #[arg(long, value_name = "SECS")]
+        duration: Option<u64>,
+        /// Log events to the systemd journal with PACKAGE/PROCESS/PATH/ATTRIBUTION/OPENER_PID/OPENER_TID fields
+        /// (requires the `journald` build feature)
+        #[arg(long)]
+        journald: bool,
+        /// Seconds between loss-stat checks and max_records eviction (default: 60)
+        #[arg(long, value_name = "SECS")]
+        stats_interval: Option<u64>,
+        /// On exit, write a JSON session report (top packages, totals, ignored breakdown); "-" for stdout
+        #[arg(long, value_name = "FILE")]
+        report: Option<std::path::PathBuf>,
+    },
     /// Manage configuration
     Config {
         #[command(subcommand)]
@@ -137,6 +457,20 @@ enum Commands {
         /// Path to exclude (absolute, ~/relative, or relative to home)
         path: String,
     },
+    /// Report the database schema version and run pending migrations (in one transaction)
+    MigrateDb {
+        /// Only list the pending migrations
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
+    /// Merge records from an `hdas export` file into the database
+    Import {
+        /// File written by `hdas export`
+        file: std::path::PathBuf,
+        /// Format of the file (default: from its extension, .json/.jsonl/.csv)
+        #[arg(long, value_enum)]
+        format: Option<query::ExportFormat>,
+    },
 
     // ── Hidden ───────────────────────────────────────────────
 
@@ -156,48 +490,224 @@ enum Commands {
 enum ConfigAction {
     /// Show current configuration file contents
     Show,
-    /// Open configuration in $EDITOR
-    Edit,
+    /// Open configuration in $EDITOR (reopens or restores the previous version if the edit doesn't parse)
+    Edit {
+        /// Restore the backup without asking when the edited file is invalid
+        #[arg(short, long)]
+        force: bool,
+        /// Don't open the editor; just run the post-edit checks and fix offer
+        #[arg(long, conflicts_with = "force")]
+        validate_only: bool,
+    },
     /// Create default config file if it doesn't exist
     Init,
     /// Validate configuration for errors and warnings
-    Validate,
+    Validate {
+        /// Remove duplicate entries and clamp excessive depths, rewriting the file
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Show only the settings that differ from the defaults
+    Diff,
 }
 
 fn main() -> Result<()> {
-    let cli = Cli::parse();
-    let json = cli.json;
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    // Full subcommand path (e.g. "config validate") for the JSON envelope
+    let mut command_path = Vec::new();
+    let mut sub = matches.subcommand();
+    while let Some((name, sub_matches)) = sub {
+        command_path.push(name);
+        sub = sub_matches.subcommand();
+    }
+    query::set_json_command(&command_path.join(" "));
+    let json = cli.json || cli.ndjson;
+    if cli.ndjson {
+        query::set_ndjson();
+    }
+
+    if let Some(format) = cli.time_format {
+        if !query::is_valid_time_format(&format) {
+            eprintln!("Invalid --time-format '{}': not a valid strftime string", format);
+            std::process::exit(1);
+        }
+        query::set_time_format(format);
+    }
+
+    if let Some(name) = cli.timezone {
+        match query::parse_timezone(&name) {
+            Ok(tz) => query::set_timezone(tz),
+            Err(e) => {
+                eprintln!("Invalid --timezone: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(home) = cli.home {
+        if !home.is_dir() {
+            eprintln!("--home {}: not a directory", home.display());
+            std::process::exit(1);
+        }
+        db::set_home_override(home);
+    }
+
+    if cli.debug_timing {
+        query::set_debug_timing();
+    }
+
+    // Bars would only garble JSON consumers' stderr or a log file
+    if cli.progress && !json && std::io::stderr().is_terminal() {
+        progress::enable();
+    }
+
+    query::set_color(cli.color);
+
+    if let Some(units) = cli.size_units {
+        query::set_size_units(units);
+    }
+
+    if let Some(pm) = cli.package_manager {
+        if let Err(e) = pkgmgr::PkgMgr::force(pm) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
 
     match cli.command {
-        Commands::Monitor => {
+        Commands::Monitor { uid, no_parent_walk, perf_pages, dry_run, duration, journald, stats_interval, report } => {
             if !nix::unistd::Uid::effective().is_root() {
                 eprintln!("Monitor requires root privileges. Run with sudo.");
                 std::process::exit(1);
             }
-            monitor::run_monitor()?;
+            monitor::run_monitor(monitor::MonitorOptions {
+                uid,
+                no_parent_walk,
+                perf_pages,
+                dry_run,
+                duration: duration.map(std::time::Duration::from_secs),
+                journald,
+                stats_interval: stats_interval.map(std::time::Duration::from_secs),
+                report,
+            })?;
+        }
+        Commands::List { created_by, format, package_regex, process_regex, since, until, sort } => {
+            let range = query::parse_created_range(since.as_deref(), until.as_deref())?;
+            match created_by {
+                Some(package) => query::list_created_by(&package, format, range, sort, json)?,
+                None => {
+                    let filter = query::AttributionFilter::new(package_regex.as_deref(), process_regex.as_deref())?;
+                    query::list_all(format, &filter, range, sort, json)?
+                }
+            }
+        }
+        Commands::Query { pattern, regex, count_only, today, this_week, this_month, last_boot, not_exists, format, owner_check, package_regex, process_regex } => {
+            let pattern = pattern.unwrap_or_default();
+            let path_regex = regex.then(|| query::compile_path_regex(&pattern)).transpose()?;
+            let path = match &path_regex {
+                Some(re) => db::PathMatch::Regex(re),
+                None => db::PathMatch::Like(&pattern),
+            };
+            let filter = query::AttributionFilter::new(package_regex.as_deref(), process_regex.as_deref())?;
+            let range = if today {
+                Some(query::TimeRange::Today)
+            } else if this_week {
+                Some(query::TimeRange::ThisWeek)
+            } else if this_month {
+                Some(query::TimeRange::ThisMonth)
+            } else if last_boot {
+                Some(query::TimeRange::LastBoot)
+            } else {
+                None
+            };
+            if count_only {
+                query::count_query_file(path, range, not_exists, &filter, json)?
+            } else {
+                query::query_file(path, range, not_exists, &filter, query::RecordOutput::from_flags(format, None, owner_check), json)?
+            }
+        }
+        Commands::Package { name, newest, oldest, summary, count_only, open, diff_installed, accessed, both, attribution, format, export_manifest, owner_check, sort, since, until, purge, force, dry_run, vacuum } => {
+            let range = query::parse_created_range(since.as_deref(), until.as_deref())?;
+            if purge {
+                cleanup::purge_package(&name, force, dry_run, vacuum, json)?
+            } else if attribution {
+                query::package_attribution(&name, json)?
+            } else if accessed || both {
+                query::query_package_accessed(&name, both, json)?
+            } else if diff_installed {
+                query::package_diff_installed(&name, json)?
+            } else if open {
+                query::open_package_location(&name, json)?
+            } else if count_only {
+                query::count_package(&name, json)?
+            } else if summary {
+                query::package_summary(&name, json)?
+            } else {
+                let listing = query::PackageListing { newest, oldest, range, sort };
+                query::query_package(&name, listing, query::RecordOutput::from_flags(format, export_manifest.as_deref(), owner_check), json)?
+            }
         }
-        Commands::List => query::list_all(json)?,
-        Commands::Query { pattern } => query::query_file(&pattern, json)?,
-        Commands::Package { name } => query::query_package(&name, json)?,
-        Commands::Dir { path } => query::query_directory(&path, json)?,
-        Commands::Orphans => query::show_orphans(json)?,
+        Commands::Dir { path, count_only } => {
+            if count_only {
+                query::count_directory(&path, json)?
+            } else {
+                query::query_directory(&path, json)?
+            }
+        }
+        Commands::Tree { dir, package } => query::show_tree(dir.as_deref(), package.as_deref(), json)?,
+        Commands::Orphans { include_unknown } => query::show_orphans(include_unknown, json)?,
+        Commands::Contested => query::show_contested(json)?,
         Commands::Recheck => query::recheck(json)?,
-        Commands::Clean { package, force, dry_run } => cleanup::clean_package(&package, force, dry_run, json)?,
-        Commands::CleanOrphans { force, dry_run } => cleanup::clean_orphans(force, dry_run, json)?,
-        Commands::Prune => cleanup::prune()?,
-        Commands::Forget { package } => query::forget_package_cmd(&package)?,
+        Commands::Verify { dry_run } => query::verify(dry_run, json)?,
+        Commands::Export { format, output } => query::export(format, output.as_deref())?,
+        Commands::Clean { package, exclude, force, dry_run, report, check_mounts, archive, .. } => {
+            let opts = cleanup::CleanOptions { force, dry_run, report: report.as_deref(), check_mounts, archive };
+            match package {
+                Some(package) => cleanup::clean_package(&package, &exclude, &opts, json)?,
+                None => cleanup::clean_from_stdin(&exclude, &opts, json)?,
+            }
+        }
+        Commands::CleanOrphans { exclude, force, dry_run, no_check_mounts } => {
+            cleanup::clean_orphans(force, dry_run, &exclude, !no_check_mounts, json)?
+        }
+        Commands::Prune { packages } => cleanup::prune(&packages)?,
+        Commands::Gc { dry_run } => cleanup::gc(dry_run, json)?,
+        Commands::Forget { target, glob, force } => {
+            if glob || query::is_forget_path(&target) {
+                query::forget_path_cmd(&target, glob, force, json)?
+            } else {
+                query::forget_package_cmd(&target)?
+            }
+        }
+        Commands::MergePackage { old, new, dry_run } => query::merge_package_cmd(&old, &new, dry_run, json)?,
+        Commands::RestoreArchive { archive, dry_run, overwrite, allow_external } => {
+            archive::restore_archive(archive.as_deref(), dry_run, overwrite, allow_external, json)?
+        }
         Commands::Config { action } => {
             match action {
                 Some(ConfigAction::Show) | None => query::show_config()?,
-                Some(ConfigAction::Edit) => query::edit_config()?,
+                Some(ConfigAction::Edit { force, validate_only }) => query::edit_config(force, validate_only)?,
                 Some(ConfigAction::Init) => query::init_config()?,
-                Some(ConfigAction::Validate) => query::validate_config(json)?,
+                Some(ConfigAction::Validate { fix }) => query::validate_config(fix, json)?,
+                Some(ConfigAction::Diff) => query::config_diff(json)?,
             }
         }
         Commands::Status => query::show_status(json)?,
-        Commands::Explain { path } => query::explain_path(&path, json)?,
+        Commands::Stats { packages_by_size, top } => query::show_stats(packages_by_size, top, json)?,
+        Commands::Top { limit, by_size } => query::show_top(limit, by_size, json)?,
+        Commands::Explain { path, all } => query::explain_path(&path, all, json)?,
+        Commands::Version => query::show_version(json)?,
+        Commands::Doctor => {
+            if !query::doctor(json)? {
+                std::process::exit(1);
+            }
+        }
         Commands::Ignore { package } => query::ignore_package_cmd(&package)?,
         Commands::Exclude { path } => query::exclude_path_cmd(&path)?,
+        Commands::MigrateDb { dry_run } => query::migrate_db(dry_run, json)?,
+        Commands::Import { file, format } => query::import(&file, format, json)?,
         Commands::Completions { shell } => {
             clap_complete::generate(shell, &mut Cli::command(), "hdas", &mut std::io::stdout());
         }
@@ -1,10 +1,16 @@
 use anyhow::Result;
 use std::fs;
 use std::mem::MaybeUninit;
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 use libbpf_rs::skel::{SkelBuilder, OpenSkel};
 use libbpf_rs::OpenObject;
+use serde::{Deserialize, Serialize};
+
+use crate::pkgmgr::OwnerResolver;
 
 mod monitor_skel {
     include!(concat!(env!("OUT_DIR"), "/monitor.skel.rs"));
@@ -23,6 +29,188 @@ fn get_ppid(pid: u32) -> Option<u32> {
     fields.get(1)?.parse().ok()
 }
 
+/// Real uid of `pid`: the user who started it, even for a setuid helper
+/// like sudo whose effective uid is root.
+fn get_ruid(pid: u32) -> Option<u32> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    status.lines().find_map(|line| line.strip_prefix("Uid:"))?.split_whitespace().next()?.parse().ok()
+}
+
+/// One line of /proc/<pid>/mountinfo: which part of which filesystem is
+/// mounted where.
+pub(crate) struct MountEntry {
+    dev: String,
+    root: String,
+    pub(crate) mount_point: String,
+    fs_type: String,
+    /// Mounted with the per-mount `ro` option
+    pub(crate) read_only: bool,
+}
+
+/// mountinfo escapes space, tab, newline, and backslash as octal (`\040`).
+pub(crate) fn unescape_mountinfo(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        // Read the digits as bytes: slicing `field` could split a UTF-8
+        // character after a stray backslash (fstab is edited by hand)
+        if bytes[i] == b'\\' && i + 3 < bytes.len() {
+            let digits = &bytes[i + 1..i + 4];
+            if digits.iter().all(|d| d.is_ascii_digit() && *d <= b'7') {
+                let value = digits.iter().fold(0u16, |acc, d| acc * 8 + u16::from(d - b'0'));
+                if let Ok(c) = u8::try_from(value) {
+                    out.push(c);
+                    i += 4;
+                    continue;
+                }
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+pub(crate) fn read_mountinfo(pid: &str) -> Option<Vec<MountEntry>> {
+    let content = fs::read_to_string(format!("/proc/{}/mountinfo", pid)).ok()?;
+    Some(content.lines().filter_map(|line| {
+        let mut fields = line.split(' ');
+        let dev = fields.nth(2)?.to_string();
+        let root = unescape_mountinfo(fields.next()?);
+        let mount_point = unescape_mountinfo(fields.next()?);
+        let read_only = fields.next()?.split(',').any(|o| o == "ro");
+        // Optional fields end at the "-" separator; the filesystem type follows
+        let fs_type = fields.skip_while(|f| *f != "-").nth(1)?.to_string();
+        Some(MountEntry { dev, root, mount_point, fs_type, read_only })
+    }).collect())
+}
+
+/// Strip `prefix` from `path` on a path-component boundary.
+pub(crate) fn strip_path_prefix<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    let prefix = prefix.trim_end_matches('/');
+    let rest = path.strip_prefix(prefix)?;
+    (rest.is_empty() || rest.starts_with('/')).then_some(rest)
+}
+
+fn mount_namespace(pid: &str) -> Option<std::path::PathBuf> {
+    fs::read_link(format!("/proc/{}/ns/mnt", pid)).ok()
+}
+
+/// Translate an absolute path as seen by a sandboxed process (bwrap, flatpak,
+/// containers) into the path the host sees. The process's mountinfo tells us
+/// which filesystem and subdirectory backs the path; the host's mountinfo tells
+/// us where that subdirectory is visible. Returns the path unchanged for
+/// processes in our own mount namespace, and None if it has no host location.
+fn host_path(pid: u32, path: &str, host_ns: Option<&std::path::Path>) -> Option<String> {
+    let pid_str = pid.to_string();
+    let ns = mount_namespace(&pid_str);
+    if ns.is_none() || ns.as_deref() == host_ns {
+        return Some(path.to_string());
+    }
+
+    // Longest mount point in the sandbox that contains the path
+    let sandbox_mounts = read_mountinfo(&pid_str)?;
+    let (mount, rest) = sandbox_mounts
+        .iter()
+        .filter_map(|m| strip_path_prefix(path, &m.mount_point).map(|rest| (m, rest)))
+        .max_by_key(|(m, _)| m.mount_point.len())?;
+    let fs_path = format!("{}{}", mount.root.trim_end_matches('/'), rest);
+
+    // Host mount of the same filesystem whose root covers that location
+    let host_mounts = read_mountinfo("self")?;
+    let (host_mount, host_rest) = host_mounts
+        .iter()
+        .filter(|m| m.dev == mount.dev)
+        .filter_map(|m| strip_path_prefix(&fs_path, &m.root).map(|rest| (m, rest)))
+        .max_by_key(|(m, _)| m.root.len())?;
+    Some(format!("{}{}", host_mount.mount_point.trim_end_matches('/'), host_rest))
+}
+
+/// Describe a home directory that isn't a plain directory on a regular
+/// mount: an overlay filesystem, a bind mount of some other directory, or a
+/// path reached through a symlink. None for an ordinary home.
+fn unusual_home_mount(home: &std::path::Path) -> Option<String> {
+    let home_str = home.to_string_lossy();
+    let canonical = fs::canonicalize(home).ok()?;
+    if canonical != home {
+        return Some(format!("{} resolves to {}", home_str, canonical.display()));
+    }
+    let mounts = read_mountinfo("self")?;
+    let mount = mounts
+        .iter()
+        .filter(|m| strip_path_prefix(&home_str, &m.mount_point).is_some())
+        .max_by_key(|m| m.mount_point.len())?;
+    if mount.fs_type == "overlay" {
+        Some(format!("{} is on an overlay filesystem mounted at {}", home_str, mount.mount_point))
+    } else if mount.root != "/" && mount.fs_type != "btrfs" {
+        // A non-root source is a bind mount, except on btrfs where it's
+        // usually just the subvolume (e.g. /@home)
+        Some(format!("{} is on a bind mount of {} at {}", home_str, mount.root, mount.mount_point))
+    } else {
+        None
+    }
+}
+
+/// True if `pid` is the monitor, another hdas invocation, or a descendant of
+/// either (e.g. the editor spawned by `hdas config edit`).
+fn is_hdas_activity(pid: u32, monitor_pid: u32, self_exe: Option<&str>) -> bool {
+    let mut ancestors = vec![pid];
+    while ancestors.len() < MAX_WALK_DEPTH as usize {
+        match get_ppid(ancestors[ancestors.len() - 1]) {
+            Some(p) if p > 1 => ancestors.push(p),
+            _ => break,
+        }
+    }
+    // The pid comparison is free; exe links only get read when it fails
+    ancestors.contains(&monitor_pid)
+        || self_exe.is_some_and(|exe| ancestors.iter().any(|&p| get_exe_path(p).as_deref() == Some(exe)))
+}
+
+const AT_FDCWD: i32 = -100;
+
+/// Base directory for a relative openat: the process CWD for AT_FDCWD, or the
+/// directory behind `dfd`. Falls back to `home` only for AT_FDCWD when the
+/// process has already exited.
+fn resolve_dirfd(pid: u32, dfd: i32, home: &std::path::Path) -> Option<std::path::PathBuf> {
+    if dfd == AT_FDCWD {
+        return Some(fs::read_link(format!("/proc/{}/cwd", pid)).unwrap_or_else(|_| home.to_path_buf()));
+    }
+    let target = fs::read_link(format!("/proc/{}/fd/{}", pid, dfd)).ok()?;
+    target.is_absolute().then_some(target)
+}
+
+/// The `/proc` lookups the process-tree walk makes. Attribution goes
+/// through this, like package owners go through [`OwnerResolver`], so a fake
+/// process tree can stand in for the real one.
+trait ProcessSource {
+    fn exe(&self, pid: u32) -> Option<String>;
+    fn ppid(&self, pid: u32) -> Option<u32>;
+    fn comm(&self, pid: u32) -> Option<String>;
+    fn ruid(&self, pid: u32) -> Option<u32>;
+}
+
+/// The live process table.
+struct Procfs;
+
+impl ProcessSource for Procfs {
+    fn exe(&self, pid: u32) -> Option<String> {
+        get_exe_path(pid)
+    }
+
+    fn ppid(&self, pid: u32) -> Option<u32> {
+        get_ppid(pid)
+    }
+
+    fn comm(&self, pid: u32) -> Option<String> {
+        get_comm(pid)
+    }
+
+    fn ruid(&self, pid: u32) -> Option<u32> {
+        get_ruid(pid)
+    }
+}
+
 fn get_exe_path(pid: u32) -> Option<String> {
     let exe_path = format!("/proc/{}/exe", pid);
     fs::read_link(&exe_path).ok().map(|p| {
@@ -31,17 +219,159 @@ fn get_exe_path(pid: u32) -> Option<String> {
     })
 }
 
-fn query_owner_cached(path: &str, pm: &crate::pkgmgr::PkgMgr, cache: &PackageCache) -> Option<String> {
+fn query_owner_cached<R: OwnerResolver + ?Sized>(path: &str, resolver: &R, cache: &PackageCache) -> Option<String> {
     if let Some(cached) = cache.borrow().get(path) {
         return cached.clone();
     }
 
-    let result = pm.query_owner(path);
+    let result = resolver.owner(path);
 
     cache.borrow_mut().insert(path.to_string(), result.clone());
     result
 }
 
+/// Modification time of `path` in nanoseconds, for spotting a replaced binary.
+fn mtime_ns(path: &str) -> Option<i64> {
+    use std::os::unix::fs::MetadataExt;
+    let meta = fs::metadata(path).ok()?;
+    Some(meta.mtime().saturating_mul(1_000_000_000).saturating_add(meta.mtime_nsec()))
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct CachedOwner {
+    owner: Option<String>,
+    /// [`mtime_ns`] of the executable when its owner was looked up
+    mtime: i64,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct ExeOwnerFile {
+    /// Backend the owners came from; a different one discards the cache
+    package_manager: String,
+    entries: HashMap<String, CachedOwner>,
+}
+
+/// Package owners of executables, kept in `owner-cache.json` between monitor
+/// runs so the process-tree walk doesn't ask the package manager about the
+/// same binaries on every start. An entry is only trusted while the
+/// executable's mtime is unchanged, so an upgrade (or a binary installed
+/// after it was first seen as unowned) is looked up again.
+struct ExeOwnerCache {
+    file: ExeOwnerFile,
+    dirty: bool,
+}
+
+impl ExeOwnerCache {
+    fn path() -> std::path::PathBuf {
+        crate::db::data_dir().join("owner-cache.json")
+    }
+
+    /// Load the saved cache for `package_manager`, dropping entries whose
+    /// executable changed or is gone. A missing or unreadable file gives an
+    /// empty cache.
+    fn load(package_manager: &str) -> Self {
+        let mut file: ExeOwnerFile = fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .filter(|f: &ExeOwnerFile| f.package_manager == package_manager)
+            .unwrap_or_default();
+        file.package_manager = package_manager.to_string();
+        let before = file.entries.len();
+        file.entries.retain(|exe, cached| mtime_ns(exe) == Some(cached.mtime));
+        let dirty = file.entries.len() != before;
+        Self { file, dirty }
+    }
+
+    fn owner<R: OwnerResolver + ?Sized>(&mut self, exe: &str, resolver: &R) -> Option<String> {
+        let mtime = mtime_ns(exe);
+        if let Some(cached) = self.file.entries.get(exe) {
+            if Some(cached.mtime) == mtime {
+                return cached.owner.clone();
+            }
+        }
+
+        let owner = resolver.owner(exe);
+        // A binary that can't be stat'ed (deleted while running) isn't kept
+        match mtime {
+            Some(mtime) => {
+                self.file.entries.insert(exe.to_string(), CachedOwner { owner: owner.clone(), mtime });
+            }
+            None => {
+                self.file.entries.remove(exe);
+            }
+        }
+        self.dirty = true;
+        owner
+    }
+
+    /// Write the cache back if anything changed, owned by the monitoring user.
+    fn save(&mut self) -> Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let (_, uid, gid) = crate::db::get_user_info();
+        crate::db::create_dir_all_with_owner(&crate::db::data_dir(), uid, gid)?;
+        let path = Self::path();
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec(&self.file)?)?;
+        if let (Some(uid), Some(gid)) = (uid, gid) {
+            let _ = std::os::unix::fs::chown(&tmp, Some(uid), Some(gid));
+        }
+        fs::rename(&tmp, &path)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+
+/// Accesses waiting to be committed in one transaction. The checks made for
+/// later events (is the path tracked yet, does it have a creator) consult
+/// it too, so buffering doesn't change what gets recorded.
+#[derive(Default)]
+struct PendingWrites {
+    events: Vec<crate::db::AccessEvent>,
+    oldest: Option<Instant>,
+}
+
+impl PendingWrites {
+    fn push(&mut self, event: crate::db::AccessEvent) {
+        self.oldest.get_or_insert_with(Instant::now);
+        self.events.push(event);
+    }
+
+    fn is_due(&self) -> bool {
+        self.events.len() >= WRITE_BATCH_SIZE || self.oldest.is_some_and(|t| t.elapsed() >= WRITE_BATCH_INTERVAL)
+    }
+
+    fn tracks(&self, path: &str) -> bool {
+        self.events.iter().any(|e| e.path == path)
+    }
+
+    /// Whether a pending write gives `path` a creator, as
+    /// `Database::path_has_known_creator` will once it's committed.
+    fn has_known_creator(&self, path: &str) -> bool {
+        self.events.iter().any(|e| e.path == path && !e.is_ignored && e.package.trim() != "unknown")
+    }
+
+    /// Commit everything pending. On failure the batch is dropped and
+    /// reported, like a failed single write.
+    fn flush(&mut self, db: Option<&crate::db::Database>) {
+        self.oldest = None;
+        if self.events.is_empty() {
+            return;
+        }
+        if let Some(db) = db {
+            // Sized now, outside the transaction; stored only if unmeasured
+            for event in &mut self.events {
+                event.size = path_size(&event.path);
+            }
+            if let Err(e) = db.record_access_batch(&self.events) {
+                eprintln!("DB error: {} ({} access(es) not recorded)", e, self.events.len());
+            }
+        }
+        self.events.clear();
+    }
+}
 
 #[derive(Clone)]
 pub struct PackageInfo {
@@ -50,35 +380,114 @@ pub struct PackageInfo {
     pub via_parent: bool,
 }
 
-fn get_package_for_pid_tree(pid: u32, comm: &str, pm: &crate::pkgmgr::PkgMgr, cache: &PackageCache) -> PackageInfo {
+/// Settings that shape how a pid is attributed to a package.
+struct AttributionRules {
+    walk_parents: bool,
+    /// Packages (shells, coreutils) that only count if nothing better is found
+    transparent_packages: HashSet<String>,
+    /// Privilege-transition helpers (sudo, pkexec) skipped in favor of the invoker
+    transition_processes: HashSet<String>,
+}
+
+impl AttributionRules {
+    fn new(config: &crate::config::Config, no_parent_walk: bool) -> Self {
+        Self {
+            walk_parents: config.parent_walk && !no_parent_walk,
+            transparent_packages: config.transparent_packages.iter().cloned().collect(),
+            transition_processes: config.transition_processes.iter().cloned().collect(),
+        }
+    }
+
+    /// True if the package owning `exe` should only be a fallback while the
+    /// walk keeps looking for a more meaningful ancestor.
+    fn is_pass_through(&self, package: &str, exe: &str) -> bool {
+        self.transparent_packages.contains(package) || self.is_transition_exe(exe)
+    }
+
+    /// Whether `exe` is a transition helper. Entries containing '/' name the
+    /// binary's full path, others its file name. The resolved exe is used
+    /// rather than comm, which is truncated and set by the process itself.
+    fn is_transition_exe(&self, exe: &str) -> bool {
+        let name = exe.rsplit('/').next().unwrap_or(exe);
+        self.transition_processes
+            .iter()
+            .any(|helper| if helper.contains('/') { helper == exe } else { helper == name })
+    }
+
+    /// Whether `pid`, running as another user (usually root), is a transition
+    /// helper that `uid` started or runs beneath one, e.g. a command run with
+    /// `sudo` from the user's shell.
+    fn invoked_through_transition<P: ProcessSource>(&self, procs: &P, pid: u32, uid: u32) -> bool {
+        let mut current = pid;
+        for _ in 0..MAX_WALK_DEPTH {
+            if procs.exe(current).is_some_and(|exe| self.is_transition_exe(&exe)) && procs.ruid(current) == Some(uid) {
+                return true;
+            }
+            match procs.ppid(current) {
+                Some(p) if p > 1 => current = p,
+                _ => break,
+            }
+        }
+        false
+    }
+}
+
+/// How many ancestors the process-tree walks look at.
+const MAX_WALK_DEPTH: u32 = 10;
+
+/// Resolve the package responsible for `pid`, walking up the process tree when
+/// the direct exe isn't packaged. Transparent packages (shells, coreutils) and
+/// privilege-transition helpers (sudo, pkexec) are only used as a fallback: the
+/// walk continues looking for a packaged ancestor that is neither, since a
+/// helper `sh` or `sudo` is rarely the meaningful owner.
+fn get_package_for_pid_tree<P: ProcessSource, R: OwnerResolver + ?Sized>(
+    pid: u32,
+    comm: &str,
+    procs: &P,
+    resolver: &R,
+    cache: &RefCell<ExeOwnerCache>,
+    rules: &AttributionRules,
+) -> PackageInfo {
     let mut current_pid = pid;
     let mut depth = 0;
-    const MAX_DEPTH: u32 = 10;
+    let mut fallback: Option<PackageInfo> = None;
 
-    if let Some(exe) = get_exe_path(pid) {
-        if let Some(pkg) = query_owner_cached(&exe, pm, cache) {
-            return PackageInfo {
+    if let Some(exe) = procs.exe(pid) {
+        if let Some(pkg) = cache.borrow_mut().owner(&exe, resolver) {
+            let pass_through = rules.is_pass_through(&pkg, &exe);
+            let info = PackageInfo {
                 package: pkg,
                 process: comm.to_string(),
                 via_parent: false,
             };
+            if !rules.walk_parents || !pass_through {
+                return info;
+            }
+            fallback = Some(info);
         }
     }
 
-    while depth < MAX_DEPTH {
-        let ppid = match get_ppid(current_pid) {
+    while rules.walk_parents && depth < MAX_WALK_DEPTH {
+        let ppid = match procs.ppid(current_pid) {
             Some(p) if p > 1 => p,
             _ => break,
         };
 
-        if let Some(exe) = get_exe_path(ppid) {
-            if let Some(pkg) = query_owner_cached(&exe, pm, cache) {
-                let parent_comm = get_comm(ppid).unwrap_or_else(|| "unknown".to_string());
-                return PackageInfo {
+        if let Some(exe) = procs.exe(ppid) {
+            if let Some(pkg) = cache.borrow_mut().owner(&exe, resolver) {
+                let parent_comm = procs.comm(ppid).unwrap_or_else(|| "unknown".to_string());
+                let pass_through = rules.is_pass_through(&pkg, &exe);
+                let info = PackageInfo {
                     package: pkg,
                     process: parent_comm,
                     via_parent: true,
                 };
+                if !pass_through {
+                    return info;
+                }
+                if fallback.is_none() {
+                    fallback = Some(info);
+                }
             }
         }
 
@@ -86,11 +495,17 @@ fn get_package_for_pid_tree(pid: u32, comm: &str, pm: &crate::pkgmgr::PkgMgr, ca
         depth += 1;
     }
 
-    PackageInfo {
+    fallback.unwrap_or_else(|| PackageInfo {
         package: "unknown".to_string(),
         process: comm.to_string(),
         via_parent: false,
-    }
+    })
+}
+
+/// Size recorded for a tracked path: its own `lstat` size, so a directory
+/// counts its entry rather than a walk of its contents.
+fn path_size(path: &str) -> Option<u64> {
+    fs::symlink_metadata(path).ok().map(|m| m.len())
 }
 
 fn get_comm(pid: u32) -> Option<String> {
@@ -165,12 +580,399 @@ pub fn get_tracked_path(
     None
 }
 
-pub fn run_monitor() -> Result<()> {
+/// Monitored-dir match patterns, built up front (and on config reload) so the
+/// per-event check doesn't allocate.
+struct DirMatcher {
+    /// Absolute dirs (e.g. `/etc`), without trailing slash
+    abs_bases: Vec<String>,
+    /// Home dotdirs as (`{home}/.name/`, `{home}/.name`, `.name/`)
+    home_dirs: Vec<(String, String, String)>,
+}
+
+impl DirMatcher {
+    fn new(monitored_dirs: &[crate::config::MonitoredDir], home: &std::path::Path) -> Self {
+        let home_str = home.to_string_lossy();
+        let mut abs_bases = Vec::new();
+        let mut home_dirs = Vec::new();
+        for dir in monitored_dirs {
+            if dir.path.starts_with('/') {
+                abs_bases.push(dir.path.trim_end_matches('/').to_string());
+            } else {
+                let dir_name = dir.path.trim_start_matches('.');
+                home_dirs.push((
+                    format!("{}/.{}/", home_str, dir_name),
+                    format!("{}/.{}", home_str, dir_name),
+                    format!(".{}/", dir_name),
+                ));
+            }
+        }
+        Self { abs_bases, home_dirs }
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        self.abs_bases.iter().any(|base| {
+            path.starts_with(base.as_str())
+                && (path.len() == base.len() || path[base.len()..].starts_with('/'))
+        }) || self.home_dirs.iter().any(|(abs_prefix, abs_exact, rel_prefix)| {
+            path.starts_with(abs_prefix.as_str())
+                || path == abs_exact
+                || path.starts_with(rel_prefix.as_str())
+        })
+    }
+}
+
+/// Config-derived filters the event callback consults. Rebuilt from a fresh
+/// `Config` on SIGHUP and swapped in between polls.
+struct MatchSettings {
+    monitored_dirs: Vec<crate::config::MonitoredDir>,
+    tracking_depth: u32,
+    excluded_paths: Vec<String>,
+    /// `ignored_paths` globs without a '/', matched against the opened file's name
+    ignored_names: Vec<glob::Pattern>,
+    /// `ignored_paths` globs with a '/', made absolute (`~/…` and relative
+    /// patterns are under the home) and matched against the whole path
+    ignored_globs: Vec<glob::Pattern>,
+    ignored_processes: HashSet<String>,
+    ignored_packages: HashSet<String>,
+    dir_matcher: DirMatcher,
+    /// Parent walking and the packages/processes it looks past
+    rules: AttributionRules,
+    /// Symlinked monitored dirs as (canonical target, configured path), so
+    /// opens through the target are recorded under the configured path
+    symlinked_dirs: Vec<(std::path::PathBuf, std::path::PathBuf)>,
+}
+
+impl MatchSettings {
+    /// Whether `path` (absolute) is skipped by an `ignored_paths` glob. `*`
+    /// and `?` stay within one component; `**` spans directories.
+    fn is_ignored_path(&self, path: &str) -> bool {
+        let options = glob::MatchOptions { require_literal_separator: true, ..Default::default() };
+        let name = std::path::Path::new(path).file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+        self.ignored_names.iter().any(|p| p.matches(&name))
+            || self.ignored_globs.iter().any(|p| p.matches_with(path, options))
+    }
+
+    /// `full_path` as it's recorded: opened through a symlinked monitored
+    /// dir's target, it's moved under the configured (link) path.
+    fn configured_path(&self, full_path: std::path::PathBuf) -> std::path::PathBuf {
+        match self
+            .symlinked_dirs
+            .iter()
+            .find_map(|(target, link)| full_path.strip_prefix(target).ok().map(|rest| (link, rest)))
+        {
+            Some((link, rest)) if rest.as_os_str().is_empty() => link.clone(),
+            Some((link, rest)) => link.join(rest),
+            None => full_path,
+        }
+    }
+
+    /// `no_parent_walk` is `monitor --no-parent-walk`, which outlasts reloads.
+    fn new(config: &crate::config::Config, home: &std::path::Path, no_parent_walk: bool) -> Self {
+        Self {
+            monitored_dirs: config.monitored_dirs.clone(),
+            tracking_depth: config.tracking_depth,
+            excluded_paths: config.excluded_paths.clone(),
+            // Invalid globs are reported by `config validate`
+            ignored_names: config
+                .ignored_paths
+                .iter()
+                .filter(|p| !p.contains('/'))
+                .filter_map(|p| glob::Pattern::new(p).ok())
+                .collect(),
+            ignored_globs: config
+                .ignored_paths
+                .iter()
+                .filter(|p| p.contains('/'))
+                .filter_map(|p| glob::Pattern::new(&crate::config::expand_home_pattern(p, home)).ok())
+                .collect(),
+            ignored_processes: config.ignored_processes.iter().cloned().collect(),
+            ignored_packages: config.ignored_packages.iter().cloned().collect(),
+            dir_matcher: DirMatcher::new(&config.monitored_dirs, home),
+            rules: AttributionRules::new(config, no_parent_walk),
+            symlinked_dirs: config
+                .symlinked_monitored_dirs(home)
+                .into_iter()
+                .map(|(link, target)| (target, link))
+                .collect(),
+        }
+    }
+}
+
+/// Runtime options for the monitor, set from the command line.
+#[derive(Default)]
+pub struct MonitorOptions {
+    /// Only record events from this uid. Defaults to the home owner.
+    pub uid: Option<u32>,
+    /// Skip process-tree walking even if the config enables it.
+    pub no_parent_walk: bool,
+    /// Per-CPU perf buffer size in pages (power of two).
+    pub perf_pages: Option<usize>,
+    /// Resolve and print attributions without touching the database.
+    pub dry_run: bool,
+    /// Stop automatically after this long.
+    pub duration: Option<Duration>,
+    /// Send event lines to the systemd journal with structured fields.
+    pub journald: bool,
+    /// How often loss stats and max_records are checked (default 60s).
+    pub stats_interval: Option<Duration>,
+    /// Write a session report here on exit (`-` for stdout).
+    pub report: Option<std::path::PathBuf>,
+}
+
+/// libbpf-rs default per-CPU perf buffer size.
+pub const DEFAULT_PERF_PAGES: usize = 64;
+
+/// How often loss stats are checked and reported, unless `--stats-interval`.
+const STATS_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Repeat lines for the same tracked path within this window are not printed.
+const OUTPUT_DEDUP_WINDOW: Duration = Duration::from_secs(1);
+
+/// Size at which expired entries are swept from the output de-dup map.
+const OUTPUT_DEDUP_SWEEP: usize = 1024;
+
+/// Consecutive lossy intervals before printing tuning guidance.
+const SUSTAINED_LOSS_INTERVALS: u32 = 3;
+
+/// Buffered database writes are committed once this many are pending...
+const WRITE_BATCH_SIZE: usize = 256;
+
+/// ...or once the oldest has waited this long.
+const WRITE_BATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+static STOP: AtomicBool = AtomicBool::new(false);
+static RELOAD: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_stop_signal(signo: nix::libc::c_int) {
+    // A second signal while shutting down exits at once, skipping the flush,
+    // with the shell's status for death by that signal (130 INT, 143 TERM)
+    if STOP.swap(true, Ordering::SeqCst) {
+        unsafe { nix::libc::_exit(128 + signo) };
+    }
+}
+
+extern "C" fn handle_reload_signal(_: nix::libc::c_int) {
+    RELOAD.store(true, Ordering::SeqCst);
+}
+
+/// Install SIGINT/SIGTERM handlers so the poll loop can exit, write what's
+/// pending and print a summary (`systemctl stop` then sees a clean exit).
+/// No SA_RESTART: the blocking poll must be interrupted.
+fn install_stop_handler() -> Result<()> {
+    use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+    let action = SigAction::new(
+        SigHandler::Handler(handle_stop_signal),
+        SaFlags::empty(),
+        SigSet::empty(),
+    );
+    unsafe {
+        sigaction(Signal::SIGINT, &action)?;
+        sigaction(Signal::SIGTERM, &action)?;
+    }
+    Ok(())
+}
+
+/// Install a SIGHUP handler that asks the poll loop to reload the config.
+fn install_reload_handler() -> Result<()> {
+    use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+    let action = SigAction::new(
+        SigHandler::Handler(handle_reload_signal),
+        SaFlags::empty(),
+        SigSet::empty(),
+    );
+    unsafe {
+        sigaction(Signal::SIGHUP, &action)?;
+    }
+    Ok(())
+}
+
+/// Whether `path` is one of `excluded_paths` or under one.
+fn is_excluded(excluded_paths: &[String], path: &str) -> bool {
+    excluded_paths.iter().any(|ex| {
+        let base = ex.trim_end_matches('/');
+        path.starts_with(base) && (path.len() == base.len() || path[base.len()..].starts_with('/'))
+    })
+}
+
+/// Reload the config and swap in new match settings. The perf buffer callback
+/// only runs inside `poll`, on this thread, so no event sees a half-updated
+/// state.
+fn reload_settings(settings: &RefCell<MatchSettings>, home: &std::path::Path, no_parent_walk: bool) {
+    match crate::config::Config::load() {
+        Ok(config) => {
+            let new = MatchSettings::new(&config, home, no_parent_walk);
+            println!(
+                "Reloaded config: {} monitored dir(s), {} excluded path(s), {} ignored path pattern(s), {} ignored process(es), {} ignored package(s), default depth {}, \
+                 process tree walking {} ({} transparent package(s), {} transition process(es))",
+                config.monitored_dirs.len(),
+                config.excluded_paths.len(),
+                config.ignored_paths.len(),
+                config.ignored_processes.len(),
+                config.ignored_packages.len(),
+                config.tracking_depth,
+                if new.rules.walk_parents { "on" } else { "off" },
+                config.transparent_packages.len(),
+                config.transition_processes.len()
+            );
+            *settings.borrow_mut() = new;
+        }
+        Err(e) => eprintln!("Config reload failed, keeping previous settings: {}", e),
+    }
+}
+
+/// Exclusive lock held for the monitor's lifetime so a second instance (say,
+/// a manual run while the systemd unit is active) refuses to start instead of
+/// double-recording. The kernel drops the lock when the process exits.
+fn acquire_monitor_lock() -> Result<nix::fcntl::Flock<fs::File>> {
+    use nix::fcntl::{Flock, FlockArg};
+    use std::io::{Read, Seek, Write};
+
+    let (_, uid, gid) = crate::db::get_user_info();
+    let dir = crate::db::data_dir();
+    crate::db::create_dir_all_with_owner(&dir, uid, gid)?;
+    let path = dir.join("monitor.lock");
+    let file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)?;
+    if let (Some(uid), Some(gid)) = (uid, gid) {
+        if let Err(e) = std::os::unix::fs::chown(&path, Some(uid), Some(gid)) {
+            eprintln!("Warning: failed to chown {}: {}", path.display(), e);
+        }
+    }
+
+    let mut lock = match Flock::lock(file, FlockArg::LockExclusiveNonblock) {
+        Ok(lock) => lock,
+        Err((mut file, nix::errno::Errno::EWOULDBLOCK)) => {
+            let mut pid = String::new();
+            let _ = file.read_to_string(&mut pid);
+            let pid = pid.trim();
+            anyhow::bail!(
+                "Another hdas monitor is already running (pid {}); lock held on {}",
+                if pid.is_empty() { "unknown" } else { pid },
+                path.display()
+            );
+        }
+        Err((_, e)) => return Err(anyhow::anyhow!("Could not lock {}: {}", path.display(), e)),
+    };
+    lock.set_len(0)?;
+    lock.rewind()?;
+    writeln!(lock, "{}", std::process::id())?;
+    Ok(lock)
+}
+
+/// Tallies kept over a monitor run for `--report`.
+#[derive(Default)]
+struct SessionCounters {
+    /// Events written to the database (or that would be, in a dry run)
+    recorded: u64,
+    /// Recorded paths that weren't tracked before, per attributed package
+    new_paths: HashMap<String, u64>,
+    /// Records made by ignored processes (accessor-only updates)
+    accessor_only: u64,
+    /// Records dropped because their path was unlinked
+    removed: u64,
+    /// Records moved because their path was renamed
+    renamed: u64,
+    skipped_excluded: u64,
+    skipped_ignored_paths: u64,
+    skipped_ignored_packages: u64,
+    skipped_ignored_processes: u64,
+}
+
+#[derive(Serialize)]
+struct PackageActivity {
+    package: String,
+    new_paths: u64,
+}
+
+#[derive(Serialize)]
+struct IgnoredBreakdown {
+    /// Events under excluded_paths
+    excluded_paths: u64,
+    /// Events for file names matching ignored_paths
+    ignored_paths: u64,
+    /// Events attributed to ignored_packages
+    ignored_packages: u64,
+    /// Events from ignored_processes on already-tracked paths
+    ignored_processes: u64,
+    /// Records by ignored processes that only updated the accessor
+    accessor_only: u64,
+}
+
+/// End-of-run summary written by `monitor --report`.
+#[derive(Serialize)]
+struct SessionReport {
+    /// Unix seconds when monitoring started
+    started_at: i64,
+    duration_secs: u64,
+    dry_run: bool,
+    events_received: u64,
+    events_lost: u64,
+    recorded: u64,
+    new_paths: u64,
+    /// Records dropped because their path was unlinked (or moved out of the
+    /// monitored dirs) while monitoring
+    removed: u64,
+    /// Records moved to follow a rename
+    renamed: u64,
+    /// Packages by number of new paths, most first
+    packages: Vec<PackageActivity>,
+    ignored: IgnoredBreakdown,
+    evicted: usize,
+}
+
+fn write_session_report(path: &std::path::Path, report: &SessionReport) -> Result<()> {
+    if path.as_os_str() == "-" {
+        return crate::query::print_json(report);
+    }
+    let content = serde_json::to_string_pretty(report)?;
+    fs::write(path, content + "\n")
+        .map_err(|e| anyhow::anyhow!("Could not write report {}: {}", path.display(), e))
+}
+
+fn loss_percent(lost: u64, received: u64) -> f64 {
+    let total = lost + received;
+    if total == 0 {
+        0.0
+    } else {
+        lost as f64 * 100.0 / total as f64
+    }
+}
+
+pub fn run_monitor(opts: MonitorOptions) -> Result<()> {
     let config = crate::config::Config::load()?;
 
+    let stats_interval = opts.stats_interval.unwrap_or(STATS_INTERVAL);
+    if stats_interval.is_zero() {
+        anyhow::bail!("--stats-interval must be at least 1 second");
+    }
+
+    let perf_pages = opts.perf_pages.unwrap_or(DEFAULT_PERF_PAGES);
+    if perf_pages == 0 || !perf_pages.is_power_of_two() {
+        anyhow::bail!("--perf-pages must be a power of two (got {})", perf_pages);
+    }
+
+    let (home, home_uid, _) = crate::db::get_user_info();
+    let target_uid = opts.uid
+        .or(home_uid)
+        .unwrap_or_else(|| nix::unistd::getuid().as_raw());
+    if opts.journald && !crate::journald::SUPPORTED {
+        anyhow::bail!("--journald needs a build with journald support (cargo build --features journald)");
+    }
+    let journald = opts.journald && crate::journald::available();
+    if opts.journald && !journald {
+        eprintln!("Warning: journald is not available; logging events to stdout");
+    }
+
     let pm = crate::pkgmgr::PkgMgr::detect()
         .ok_or_else(|| anyhow::anyhow!("No supported package manager found (need pacman, dpkg, rpm, xbps, or apk)"))?;
 
+    // Dry runs don't write to the database, so they may run alongside the daemon
+    let _lock = if opts.dry_run { None } else { Some(acquire_monitor_lock()?) };
+
     println!("HDAS Monitor starting...");
     println!("Package manager: {}", pm.name());
     print!("Monitored directories: ");
@@ -186,10 +988,36 @@ pub fn run_monitor() -> Result<()> {
     println!();
     println!("Ignored processes: {} configured", config.ignored_processes.len());
     println!("Ignored packages: {} configured", config.ignored_packages.len());
+    println!("Transparent packages: {} configured", config.transparent_packages.len());
+    println!("Transition processes: {} configured", config.transition_processes.len());
     println!("Default tracking depth: {}", config.tracking_depth);
-    println!("Process tree walking: enabled");
+    println!("Target uid: {}", target_uid);
+    println!("Process tree walking: {}", if config.parent_walk && !opts.no_parent_walk { "enabled" } else { "disabled" });
+    println!("Perf buffer: {} pages per CPU", perf_pages);
+    if let Some(max) = config.max_records {
+        println!("Max records: {}", max);
+    }
     println!();
 
+    if let Some(detail) = unusual_home_mount(&home) {
+        eprintln!(
+            "Warning: {}. Paths opened through the resolved location are recorded under {}; \
+             files reached by other routes (e.g. an overlay's upper directory) may not match later queries.",
+            detail,
+            home.display()
+        );
+    }
+    for (link, target) in config.symlinked_monitored_dirs(&home) {
+        println!("Monitored dir {} is a symlink to {}; matching both", link.display(), target.display());
+    }
+
+    for dir in config.depth_zero_high_churn_dirs() {
+        eprintln!(
+            "Warning: '{}' is tracked at depth 0 — every file gets its own record, which can grow the database to millions of entries. Consider depth 1 or 2.",
+            dir
+        );
+    }
+
     let skel_builder = MonitorSkelBuilder::default();
     let mut open_object = MaybeUninit::<OpenObject>::uninit();
     let open_skel = skel_builder.open(&mut open_object)?;
@@ -199,121 +1027,312 @@ pub fn run_monitor() -> Result<()> {
         .progs
         .trace_openat
         .attach_tracepoint("syscalls", "sys_enter_openat")?;
+    // Events are emitted on exit, and only for opens that succeeded
+    let _link_openat_exit = skel
+        .progs
+        .trace_openat_exit
+        .attach_tracepoint("syscalls", "sys_exit_openat")?;
+    // Successful unlinks drop the record right away instead of waiting for `prune`
+    let _link_unlinkat = skel
+        .progs
+        .trace_unlinkat
+        .attach_tracepoint("syscalls", "sys_enter_unlinkat")?;
+    let _link_unlinkat_exit = skel
+        .progs
+        .trace_unlinkat_exit
+        .attach_tracepoint("syscalls", "sys_exit_unlinkat")?;
+    // Renames move the record so attributions follow atomically replaced
+    // files. rename/renameat only exist on some architectures.
+    let _link_renameat2 = skel
+        .progs
+        .trace_renameat2
+        .attach_tracepoint("syscalls", "sys_enter_renameat2")?;
+    let _link_renameat2_exit = skel
+        .progs
+        .trace_renameat2_exit
+        .attach_tracepoint("syscalls", "sys_exit_renameat2")?;
+    let _links_legacy_rename: Vec<_> = [
+        (&skel.progs.trace_renameat, "sys_enter_renameat"),
+        (&skel.progs.trace_renameat_exit, "sys_exit_renameat"),
+        (&skel.progs.trace_rename, "sys_enter_rename"),
+        (&skel.progs.trace_rename_exit, "sys_exit_rename"),
+    ]
+    .into_iter()
+    .filter_map(|(prog, tracepoint)| prog.attach_tracepoint("syscalls", tracepoint).ok())
+    .collect();
 
-    let db = crate::db::Database::new()?;
-    let home = crate::db::get_user_home();
+    let db = Rc::new(if opts.dry_run {
+        None
+    } else {
+        Some(crate::db::Database::new()?)
+    });
+    let db_cb = Rc::clone(&db);
+    // Accesses are written in batches: by the callback when a batch fills,
+    // by the poll loop when one has waited long enough, and on exit
+    let pending = Rc::new(RefCell::new(PendingWrites::default()));
+    let pending_cb = Rc::clone(&pending);
+    // Separate connection for the periodic max_records check
+    let evict_db = match config.max_records {
+        Some(max) if !opts.dry_run => Some((crate::db::Database::new()?, max)),
+        _ => None,
+    };
 
-    println!("Monitor running. Press Ctrl+C to stop.");
+    if opts.dry_run {
+        println!("Dry run: attributions are printed but nothing is written to the database.");
+    }
+    match opts.duration {
+        Some(d) => println!("Monitor running for {}s. Press Ctrl+C to stop early.", d.as_secs()),
+        None => println!("Monitor running. Press Ctrl+C to stop."),
+    }
+    println!("Send SIGHUP to reload monitored dirs, exclusions, ignore lists, and parent-walk settings from the config.");
     println!();
 
-    let monitored_dirs = config.monitored_dirs.clone();
-    let tracking_depth = config.tracking_depth;
-    let excluded_paths = config.excluded_paths.clone();
-    let ignored_processes: std::collections::HashSet<String> = config
-        .ignored_processes
-        .iter()
-        .cloned()
-        .collect();
-    let ignored_packages: std::collections::HashSet<String> = config
-        .ignored_packages
-        .iter()
-        .cloned()
-        .collect();
-
+    let settings = Rc::new(RefCell::new(MatchSettings::new(&config, &home, opts.no_parent_walk)));
+    let settings_cb = Rc::clone(&settings);
+    let reload_home = home.clone();
+    // Owners of tracked files themselves, for this session only
     let package_cache: PackageCache = RefCell::new(HashMap::new());
+    let exe_cache = Rc::new(RefCell::new(ExeOwnerCache::load(pm.name())));
+    let exe_cache_cb = Rc::clone(&exe_cache);
     let monitor_pid = std::process::id();
+    let self_exe = get_exe_path(monitor_pid);
+    // HDAS's own data and config dirs, plus the database files wherever
+    // db_path puts them, so `hdas config edit` and database writes never show
+    // up as tracked files
+    let db_file = crate::db::db_path()?.to_string_lossy().into_owned();
+    let self_dirs: Vec<String> = [
+        crate::db::data_dir(),
+        crate::config::Config::path().parent().map(|p| p.to_path_buf()).unwrap_or_default(),
+    ]
+    .iter()
+    .filter(|p| !p.as_os_str().is_empty())
+    .map(|p| p.to_string_lossy().into_owned())
+    .chain(["", "-wal", "-shm", "-journal"].iter().map(|suffix| format!("{}{}", db_file, suffix)))
+    .collect();
+    let host_mount_ns = mount_namespace("self");
+    // Processes that open files through the resolved home path (e.g. /var/home
+    // when /home is a symlink) are recorded under the home path users query by
+    let canonical_home = fs::canonicalize(&home)
+        .ok()
+        .filter(|c| *c != home)
+        .map(|c| c.to_string_lossy().into_owned());
+    // Last time each tracked path was printed; console only, DB writes are unaffected
+    let mut last_printed: HashMap<String, Instant> = HashMap::new();
+
+    let counters = Rc::new(RefCell::new(SessionCounters::default()));
+    let counters_cb = Rc::clone(&counters);
+    let started_at = chrono::Utc::now().timestamp();
+
+    let received = Rc::new(Cell::new(0u64));
+    let lost = Rc::new(Cell::new(0u64));
+    let received_cb = Rc::clone(&received);
+    let lost_cb = Rc::clone(&lost);
 
     let perf = libbpf_rs::PerfBufferBuilder::new(&skel.maps.events)
+        .pages(perf_pages)
+        .lost_cb(move |_cpu, count| {
+            lost_cb.set(lost_cb.get() + count);
+        })
         .sample_cb(move |_cpu, data: &[u8]| {
+            received_cb.set(received_cb.get() + 1);
+
             if data.len() < std::mem::size_of::<Event>() || data.as_ptr().align_offset(std::mem::align_of::<Event>()) != 0 {
                 return;
             }
 
             let event = unsafe { &*(data.as_ptr() as *const Event) };
 
-            if event.pid == monitor_pid {
+            let comm = c_str(&event.comm);
+            let filename = c_str(&event.filename);
+
+            let db: &Option<crate::db::Database> = &db_cb;
+            let settings = settings_cb.borrow();
+            // Whose event this is: other users' events only count when the
+            // target user ran them through a transition helper (`sudo rm`,
+            // `pkexec`), and HDAS's own never do. Both walk /proc, so they're
+            // checked only once the path is known to be monitored.
+            let is_user_event =
+                || event.uid == target_uid || settings.rules.invoked_through_transition(&Procfs, event.tgid, target_uid);
+            let is_own_event = || is_hdas_activity(event.tgid, monitor_pid, self_exe.as_deref());
+            // The host path an event's (dirfd, name) pair refers to, in the
+            // form it's recorded under
+            let resolve = |dfd: i32, filename: &str| -> Option<std::path::PathBuf> {
+                let full_path = if filename.starts_with('/') {
+                    // Sandboxed processes see their own mount namespace; record
+                    // the host-visible path, or nothing if there isn't one.
+                    std::path::PathBuf::from(host_path(event.tgid, filename, host_mount_ns.as_deref())?)
+                } else {
+                    // Skip rather than guess when the base directory can't be resolved
+                    // (e.g. the dirfd was already closed by the time we look).
+                    resolve_dirfd(event.tgid, dfd, &home)?.join(filename)
+                };
+
+                let full_path = match canonical_home.as_deref().and_then(|c| full_path.strip_prefix(c).ok()) {
+                    Some(rest) => home.join(rest),
+                    None => full_path,
+                };
+                Some(settings.configured_path(full_path))
+            };
+            let Some(full_path) = resolve(event.dfd, filename) else {
+                return;
+            };
+            let full_path_str = full_path.to_string_lossy();
+
+            if self_dirs.iter().any(|dir| strip_path_prefix(&full_path_str, dir).is_some()) {
                 return;
             }
-            let mut ancestor = event.pid;
-            for _ in 0..5 {
-                match get_ppid(ancestor) {
-                    Some(p) if p > 1 => {
-                        if p == monitor_pid {
+
+            if event.kind == EVENT_UNLINK || event.kind == EVENT_RENAME {
+                if !settings.dir_matcher.matches(&full_path_str) || !is_user_event() || is_own_event() {
+                    return;
+                }
+                let Some(tracked_path) = get_tracked_path(&full_path_str, &home, &settings.monitored_dirs, settings.tracking_depth) else {
+                    return;
+                };
+                // Removing or renaming a file inside a tracked directory
+                // leaves the directory's record alone; only a tracked path
+                // that is really gone loses (or moves) its record.
+                if std::path::Path::new(&tracked_path).exists() {
+                    return;
+                }
+                // Where a renamed path went, if that's somewhere we track.
+                // Moving a file out of the monitored dirs counts as deleting it.
+                let destination = if event.kind == EVENT_RENAME {
+                    if data.len() < std::mem::size_of::<RenameEvent>() {
+                        return;
+                    }
+                    let rename = unsafe { &*(data.as_ptr() as *const RenameEvent) };
+                    resolve(rename.newdfd, c_str(&rename.newname)).and_then(|new_path| {
+                        let new_path_str = new_path.to_string_lossy();
+                        let untracked = self_dirs.iter().any(|dir| strip_path_prefix(&new_path_str, dir).is_some())
+                            || is_excluded(&settings.excluded_paths, &new_path_str)
+                            || settings.is_ignored_path(&new_path_str)
+                            || !settings.dir_matcher.matches(&new_path_str);
+                        if untracked {
+                            return None;
+                        }
+                        let tracked = get_tracked_path(&new_path_str, &home, &settings.monitored_dirs, settings.tracking_depth)?;
+                        Some((new_path_str.into_owned(), tracked))
+                    })
+                } else {
+                    None
+                };
+
+                // The record may still be waiting in the batch (a temp file
+                // written and renamed into place, say)
+                pending_cb.borrow_mut().flush(db.as_ref());
+                let package = match &db {
+                    Some(db) => {
+                        let record = match db.get_record(&tracked_path) {
+                            Ok(Some(record)) => record,
+                            Ok(None) => return,
+                            Err(e) => {
+                                eprintln!("DB error: {}", e);
+                                return;
+                            }
+                        };
+                        let result = match &destination {
+                            Some((new, _)) => db.rename_record(&tracked_path, new, |p| {
+                                get_tracked_path(p, &home, &settings.monitored_dirs, settings.tracking_depth)
+                            }),
+                            None => db.delete_file_records(std::slice::from_ref(&tracked_path)),
+                        };
+                        if let Err(e) = result {
+                            eprintln!("DB error: {}", e);
                             return;
                         }
-                        ancestor = p;
+                        record.created_by_package
                     }
-                    _ => break,
+                    None => "unknown".to_string(),
+                };
+                last_printed.remove(&tracked_path);
+
+                let (line, path, attribution) = match &destination {
+                    Some((_, new)) => {
+                        counters_cb.borrow_mut().renamed += 1;
+                        (format!("[>] {} ({}) -> {} (from {})", package, comm, new, tracked_path), new.as_str(), "renamed")
+                    }
+                    None => {
+                        counters_cb.borrow_mut().removed += 1;
+                        (format!("[-] {} ({}) -> {}", package, comm, tracked_path), tracked_path.as_str(), "deleted")
+                    }
+                };
+                let fields = crate::journald::EventFields {
+                    pid: event.tgid,
+                    tid: event.tid,
+                    package: &package,
+                    process: comm,
+                    path,
+                    attribution,
+                };
+                if !(journald && crate::journald::send(&line, &fields)) {
+                    println!("{}", line);
                 }
+                return;
             }
 
-            let comm = std::str::from_utf8(&event.comm)
-                .unwrap_or("unknown")
-                .trim_end_matches('\0');
-
-            let filename = std::str::from_utf8(&event.filename)
-                .unwrap_or("unknown")
-                .trim_end_matches('\0');
-
-            let full_path = if filename.starts_with('/') {
-                std::path::PathBuf::from(filename)
-            } else {
-                let mut p = home.clone();
-                p.push(filename);
-                p
-            };
-
-            let full_path_str = full_path.to_string_lossy();
-
-            if excluded_paths.iter().any(|ex| {
-                let base = ex.trim_end_matches('/');
-                full_path_str.starts_with(base)
-                    && (full_path_str.len() == base.len() || full_path_str[base.len()..].starts_with('/'))
-            }) {
+            if !settings.dir_matcher.matches(&full_path_str) || !is_user_event() {
                 return;
             }
 
-            let home_str = home.to_string_lossy();
-            let is_monitored = monitored_dirs.iter().any(|dir| {
-                if dir.path.starts_with('/') {
-                    let base = dir.path.trim_end_matches('/');
-                    full_path_str.starts_with(base)
-                        && (full_path_str.len() == base.len()
-                            || full_path_str[base.len()..].starts_with('/'))
-                } else {
-                    let dir_name = dir.path.trim_start_matches('.');
-                    let abs_prefix = format!("{}/.{}/", home_str, dir_name);
-                    let abs_exact = format!("{}/.{}", home_str, dir_name);
-                    let rel_prefix = format!(".{}/", dir_name);
-                    full_path_str.starts_with(&abs_prefix)
-                        || full_path_str.as_ref() == abs_exact.as_str()
-                        || full_path_str.starts_with(&rel_prefix)
-                }
-            });
+            if is_excluded(&settings.excluded_paths, &full_path_str) {
+                counters_cb.borrow_mut().skipped_excluded += 1;
+                return;
+            }
 
-            if !is_monitored {
+            if settings.is_ignored_path(&full_path_str) {
+                counters_cb.borrow_mut().skipped_ignored_paths += 1;
                 return;
             }
-            let tracked_path = match get_tracked_path(&full_path_str, &home, &monitored_dirs, tracking_depth) {
+
+            let tracked_path = match get_tracked_path(&full_path_str, &home, &settings.monitored_dirs, settings.tracking_depth) {
                 Some(p) => p,
                 None => return,
             };
 
             // Check DB early — if we already have a known creator, skip entirely.
             // This avoids expensive package manager queries for files we've already seen.
-            let path_exists = db.path_exists(&tracked_path);
-            if path_exists && db.path_has_known_creator(&tracked_path) {
+            // In dry-run mode there is no database, so every event is resolved and printed.
+            let (pending_path, pending_creator) = {
+                let pending = pending_cb.borrow();
+                (pending.tracks(&tracked_path), pending.has_known_creator(&tracked_path))
+            };
+            // Measured when the batch is committed
+            if pending_creator {
+                return;
+            }
+            let path_exists = pending_path || db.as_ref().is_some_and(|db| db.path_exists(&tracked_path));
+            if path_exists && db.as_ref().is_some_and(|db| db.path_has_known_creator(&tracked_path)) {
+                // Records from before sizes were kept get measured here, once
+                if let Some(db) = db.as_ref().filter(|db| !pending_path && db.size_unmeasured(&tracked_path)) {
+                    if let Err(e) = path_size(&tracked_path).map_or(Ok(()), |size| db.backfill_size(&tracked_path, size)) {
+                        eprintln!("DB error: {}", e);
+                    }
+                }
+                return;
+            }
+
+            // Past the known-creator shortcut, since nothing above records an access
+            if is_own_event() {
                 return;
             }
 
             // Also skip early if this is an ignored process and the path is already tracked
             // (even with unknown creator — ignored procs only update last_accessed)
-            if path_exists && ignored_processes.contains(comm) {
+            if path_exists && settings.ignored_processes.contains(comm) {
+                counters_cb.borrow_mut().skipped_ignored_processes += 1;
                 return;
             }
 
             // Only now do the expensive package resolution
-            let mut pkg_info = get_package_for_pid_tree(event.pid, comm, &pm, &package_cache);
+            let mut pkg_info = get_package_for_pid_tree(
+                event.tgid,
+                comm,
+                &Procfs,
+                &pm,
+                &exe_cache_cb,
+                &settings.rules,
+            );
 
             if pm.is_self_package(&pkg_info.package) || pkg_info.package == "unknown" {
                 if let Some(owner) = query_owner_cached(&full_path_str, &pm, &package_cache) {
@@ -321,26 +1340,57 @@ pub fn run_monitor() -> Result<()> {
                 }
             }
 
-            if ignored_packages.contains(&pkg_info.package) {
+            if settings.ignored_packages.contains(&pkg_info.package) {
+                counters_cb.borrow_mut().skipped_ignored_packages += 1;
                 return;
             }
 
-            let is_ignored_proc = ignored_processes.contains(&pkg_info.process);
+            let is_ignored_proc = settings.ignored_processes.contains(&pkg_info.process);
 
             // For parent-resolved ignored processes on existing paths, skip
             if path_exists && is_ignored_proc {
+                counters_cb.borrow_mut().skipped_ignored_processes += 1;
                 return;
             }
 
-            if let Err(e) = db.record_access(
-                &tracked_path,
-                &pkg_info.package,
-                &pkg_info.process,
-                is_ignored_proc
-            ) {
-                eprintln!("DB error: {}", e);
+            if db.is_some() {
+                let mut pending = pending_cb.borrow_mut();
+                pending.push(crate::db::AccessEvent {
+                    path: tracked_path.clone(),
+                    package: pkg_info.package.clone(),
+                    process: pkg_info.process.clone(),
+                    via_parent: pkg_info.via_parent,
+                    is_ignored: is_ignored_proc,
+                    at: chrono::Utc::now().timestamp(),
+                    size: None,
+                });
+                if pending.is_due() {
+                    pending.flush(db.as_ref());
+                }
+            }
+
+            {
+                let mut counters = counters_cb.borrow_mut();
+                counters.recorded += 1;
+                if is_ignored_proc {
+                    counters.accessor_only += 1;
+                } else if !path_exists {
+                    *counters.new_paths.entry(pkg_info.package.clone()).or_default() += 1;
+                }
             }
 
+            let now = Instant::now();
+            if last_printed
+                .get(&tracked_path)
+                .is_some_and(|t| now.duration_since(*t) < OUTPUT_DEDUP_WINDOW)
+            {
+                return;
+            }
+            if last_printed.len() >= OUTPUT_DEDUP_SWEEP {
+                last_printed.retain(|_, t| now.duration_since(*t) < OUTPUT_DEDUP_WINDOW);
+            }
+            last_printed.insert(tracked_path.clone(), now);
+
             let indicator = if is_ignored_proc {
                 "~"
             } else if pkg_info.via_parent {
@@ -355,24 +1405,443 @@ pub fn run_monitor() -> Result<()> {
                 String::new()
             };
 
-            println!("[{}] {} ({}){} -> {}",
+            let line = format!("[{}] {} ({}){} -> {}",
                 indicator,
                 pkg_info.package,
                 comm,
                 via,
                 tracked_path
             );
+            let fields = crate::journald::EventFields {
+                pid: event.tgid,
+                tid: event.tid,
+                package: &pkg_info.package,
+                process: &pkg_info.process,
+                path: &tracked_path,
+                attribution: if is_ignored_proc {
+                    "ignored"
+                } else if pkg_info.via_parent {
+                    "via_parent"
+                } else {
+                    "created"
+                },
+            };
+            if !(journald && crate::journald::send(&line, &fields)) {
+                println!("{}", line);
+            }
         })
         .build()?;
 
-    loop {
-        perf.poll(std::time::Duration::from_millis(100))?;
+    install_stop_handler()?;
+    install_reload_handler()?;
+
+    let started = Instant::now();
+    let mut last_report = Instant::now();
+    let (mut last_received, mut last_lost) = (0u64, 0u64);
+    let mut lossy_intervals = 0u32;
+    let mut advised = false;
+    let mut evicted = 0usize;
+
+    while !STOP.load(Ordering::SeqCst) {
+        if opts.duration.is_some_and(|d| started.elapsed() >= d) {
+            break;
+        }
+        if let Err(e) = perf.poll(Duration::from_millis(100)) {
+            if STOP.load(Ordering::SeqCst) {
+                break;
+            }
+            if !RELOAD.load(Ordering::SeqCst) {
+                pending.borrow_mut().flush((*db).as_ref());
+                return Err(e.into());
+            }
+        }
+        if pending.borrow().is_due() {
+            pending.borrow_mut().flush((*db).as_ref());
+        }
+
+        if RELOAD.swap(false, Ordering::SeqCst) {
+            reload_settings(&settings, &reload_home, opts.no_parent_walk);
+        }
+
+        if last_report.elapsed() < stats_interval {
+            continue;
+        }
+        last_report = Instant::now();
+
+        let interval_received = received.get() - last_received;
+        let interval_lost = lost.get() - last_lost;
+        last_received = received.get();
+        last_lost = lost.get();
+
+        if let Some((db, max)) = &evict_db {
+            match db.evict_oldest(*max) {
+                Ok(0) => {}
+                Ok(n) => {
+                    evicted += n;
+                    println!("Evicted {} least recently accessed record(s) (max_records = {})", n, max);
+                }
+                Err(e) => eprintln!("DB error during eviction: {}", e),
+            }
+        }
+
+        if interval_lost == 0 {
+            lossy_intervals = 0;
+            continue;
+        }
+        lossy_intervals += 1;
+        eprintln!(
+            "Warning: lost {} event(s) in the last {}s ({:.1}% of {})",
+            interval_lost,
+            stats_interval.as_secs(),
+            loss_percent(interval_lost, interval_received),
+            interval_lost + interval_received
+        );
+        if lossy_intervals >= SUSTAINED_LOSS_INTERVALS && !advised {
+            advised = true;
+            eprintln!(
+                "Events are being dropped continuously. Try a larger buffer (--perf-pages {}), \
+                 or add busy processes to ignored_processes / busy paths to excluded_paths.",
+                perf_pages * 2
+            );
+        }
+    }
+
+    if STOP.load(Ordering::SeqCst) {
+        println!("Shutting down...");
+    }
+    // Events already in the perf buffers, then whatever hasn't been committed
+    if let Err(e) = perf.consume() {
+        eprintln!("Warning: failed to read the remaining events: {}", e);
+    }
+    pending.borrow_mut().flush((*db).as_ref());
+
+    println!();
+    println!(
+        "Monitor stopped after {}s: {} event(s) received, {} lost ({:.1}%)",
+        started.elapsed().as_secs(),
+        received.get(),
+        lost.get(),
+        loss_percent(lost.get(), received.get())
+    );
+    if evicted > 0 {
+        println!("Evicted {} record(s) to stay under max_records", evicted);
+    }
+    if !opts.dry_run {
+        if let Err(e) = exe_cache.borrow_mut().save() {
+            eprintln!("Warning: failed to save {}: {}", ExeOwnerCache::path().display(), e);
+        }
+    }
+
+    if let Some(path) = &opts.report {
+        let counters = counters.borrow();
+        let mut packages: Vec<PackageActivity> = counters
+            .new_paths
+            .iter()
+            .map(|(package, &new_paths)| PackageActivity { package: package.clone(), new_paths })
+            .collect();
+        packages.sort_by(|a, b| b.new_paths.cmp(&a.new_paths).then_with(|| a.package.cmp(&b.package)));
+        let report = SessionReport {
+            started_at,
+            duration_secs: started.elapsed().as_secs(),
+            dry_run: opts.dry_run,
+            events_received: received.get(),
+            events_lost: lost.get(),
+            recorded: counters.recorded,
+            new_paths: counters.new_paths.values().sum(),
+            removed: counters.removed,
+            renamed: counters.renamed,
+            packages,
+            ignored: IgnoredBreakdown {
+                excluded_paths: counters.skipped_excluded,
+                ignored_paths: counters.skipped_ignored_paths,
+                ignored_packages: counters.skipped_ignored_packages,
+                ignored_processes: counters.skipped_ignored_processes,
+                accessor_only: counters.accessor_only,
+            },
+            evicted,
+        };
+        write_session_report(path, &report)?;
+        if path.as_os_str() != "-" {
+            println!("Wrote session report to {}", path.display());
+        }
     }
+    Ok(())
 }
 
+/// `Event::kind` for a successful unlinkat (file or directory removal);
+/// opens are 0.
+const EVENT_UNLINK: u32 = 1;
+/// `Event::kind` for a successful rename; the event is a [`RenameEvent`].
+const EVENT_RENAME: u32 = 2;
+
+/// Mirror of `struct event` in monitor.bpf.c.
 #[repr(C)]
 struct Event {
-    pid: u32,
+    /// Thread group id, i.e. the process id userspace sees. All `/proc`
+    /// lookups (exe, cwd, fds, mount namespace, parent walk) use this.
+    tgid: u32,
+    /// Kernel task id of the thread that made the syscall; equal to `tgid`
+    /// for the main thread. Only reported, never looked up.
+    tid: u32,
+    uid: u32,
+    dfd: i32,
+    /// 0 for an open, [`EVENT_UNLINK`] or [`EVENT_RENAME`]
+    kind: u32,
     comm: [u8; 16],
     filename: [u8; 256],
 }
+
+/// Mirror of `struct rename_event` in monitor.bpf.c. `base.dfd` and
+/// `base.filename` are the old path.
+#[repr(C)]
+struct RenameEvent {
+    base: Event,
+    newdfd: i32,
+    newname: [u8; 256],
+}
+
+/// The text of a NUL-terminated buffer from the BPF side. Rename events are
+/// built in a reused scratch buffer, so bytes past the terminator can be
+/// left over from an earlier event.
+fn c_str(bytes: &[u8]) -> &str {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    std::str::from_utf8(&bytes[..end]).unwrap_or("unknown")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A process in [`FakeProcs`]: parent, executable, comm, real uid.
+    struct FakeProcess {
+        ppid: u32,
+        exe: String,
+        comm: String,
+        ruid: u32,
+    }
+
+    struct FakeProcs(HashMap<u32, FakeProcess>);
+
+    impl FakeProcs {
+        /// `(pid, ppid, exe, comm, ruid)` per process.
+        fn new(procs: &[(u32, u32, &str, &str, u32)]) -> Self {
+            Self(
+                procs
+                    .iter()
+                    .map(|&(pid, ppid, exe, comm, ruid)| {
+                        (pid, FakeProcess { ppid, exe: exe.to_string(), comm: comm.to_string(), ruid })
+                    })
+                    .collect(),
+            )
+        }
+    }
+
+    impl ProcessSource for FakeProcs {
+        fn exe(&self, pid: u32) -> Option<String> {
+            self.0.get(&pid).map(|p| p.exe.clone())
+        }
+
+        fn ppid(&self, pid: u32) -> Option<u32> {
+            self.0.get(&pid).map(|p| p.ppid)
+        }
+
+        fn comm(&self, pid: u32) -> Option<String> {
+            self.0.get(&pid).map(|p| p.comm.clone())
+        }
+
+        fn ruid(&self, pid: u32) -> Option<u32> {
+            self.0.get(&pid).map(|p| p.ruid)
+        }
+    }
+
+    /// Owners by exe path, counting lookups.
+    struct FakeResolver {
+        owners: HashMap<String, String>,
+        lookups: Cell<usize>,
+    }
+
+    impl FakeResolver {
+        fn new(owners: &[(&str, &str)]) -> Self {
+            Self {
+                owners: owners.iter().map(|(exe, pkg)| (exe.to_string(), pkg.to_string())).collect(),
+                lookups: Cell::new(0),
+            }
+        }
+    }
+
+    impl OwnerResolver for FakeResolver {
+        fn owner(&self, exe: &str) -> Option<String> {
+            self.lookups.set(self.lookups.get() + 1);
+            self.owners.get(exe).cloned()
+        }
+    }
+
+    fn empty_cache() -> RefCell<ExeOwnerCache> {
+        RefCell::new(ExeOwnerCache { file: ExeOwnerFile::default(), dirty: false })
+    }
+
+    fn rules(no_parent_walk: bool) -> AttributionRules {
+        AttributionRules::new(&crate::config::Config::default(), no_parent_walk)
+    }
+
+    const OWNERS: &[(&str, &str)] = &[
+        ("/usr/bin/kitty", "kitty"),
+        ("/usr/bin/zsh", "zsh"),
+        ("/usr/bin/bash", "bash"),
+        ("/usr/bin/sudo", "sudo"),
+        ("/usr/lib/firefox/firefox", "firefox"),
+    ];
+
+    #[test]
+    fn packaged_exe_is_attributed_directly() {
+        let procs = FakeProcs::new(&[(200, 100, "/usr/lib/firefox/firefox", "firefox", 1000)]);
+        let info = get_package_for_pid_tree(200, "Cache2 I/O", &procs, &FakeResolver::new(OWNERS), &empty_cache(), &rules(false));
+        assert_eq!(info.package, "firefox");
+        assert_eq!(info.process, "Cache2 I/O");
+        assert!(!info.via_parent);
+    }
+
+    #[test]
+    fn unpackaged_exe_walks_to_packaged_parent() {
+        let procs = FakeProcs::new(&[
+            (300, 200, "/home/u/.local/bin/tool", "tool", 1000),
+            (200, 100, "/usr/bin/kitty", "kitty", 1000),
+        ]);
+        let info = get_package_for_pid_tree(300, "tool", &procs, &FakeResolver::new(OWNERS), &empty_cache(), &rules(false));
+        assert_eq!(info.package, "kitty");
+        assert_eq!(info.process, "kitty");
+        assert!(info.via_parent);
+    }
+
+    #[test]
+    fn shells_and_sudo_are_skipped_for_a_meaningful_ancestor() {
+        let procs = FakeProcs::new(&[
+            (400, 300, "/usr/bin/bash", "bash", 0),
+            (300, 200, "/usr/bin/sudo", "sudo", 1000),
+            (200, 100, "/usr/bin/zsh", "zsh", 1000),
+            (100, 1, "/usr/bin/kitty", "kitty", 1000),
+        ]);
+        let info = get_package_for_pid_tree(400, "bash", &procs, &FakeResolver::new(OWNERS), &empty_cache(), &rules(false));
+        assert_eq!(info.package, "kitty");
+        assert!(info.via_parent);
+    }
+
+    #[test]
+    fn pass_through_package_is_the_fallback() {
+        let procs = FakeProcs::new(&[(400, 300, "/usr/bin/bash", "bash", 1000), (300, 1, "/usr/bin/zsh", "zsh", 1000)]);
+        let info = get_package_for_pid_tree(400, "bash", &procs, &FakeResolver::new(OWNERS), &empty_cache(), &rules(false));
+        assert_eq!(info.package, "bash");
+        assert!(!info.via_parent);
+    }
+
+    #[test]
+    fn no_parent_walk_stops_at_the_process() {
+        let procs = FakeProcs::new(&[
+            (300, 200, "/home/u/.local/bin/tool", "tool", 1000),
+            (200, 100, "/usr/bin/kitty", "kitty", 1000),
+        ]);
+        let info = get_package_for_pid_tree(300, "tool", &procs, &FakeResolver::new(OWNERS), &empty_cache(), &rules(true));
+        assert_eq!(info.package, "unknown");
+        assert_eq!(info.process, "tool");
+    }
+
+    #[test]
+    fn walk_stops_at_init() {
+        let procs = FakeProcs::new(&[(300, 1, "/opt/tool", "tool", 1000), (1, 0, "/usr/bin/kitty", "systemd", 0)]);
+        let info = get_package_for_pid_tree(300, "tool", &procs, &FakeResolver::new(OWNERS), &empty_cache(), &rules(false));
+        assert_eq!(info.package, "unknown");
+    }
+
+    #[test]
+    fn exe_owner_is_cached() {
+        // The cache keys on the exe's mtime, so use a file that exists
+        let exe = std::env::current_exe().unwrap().to_string_lossy().into_owned();
+        let procs = FakeProcs::new(&[(500, 1, &exe, "tool", 1000)]);
+        let resolver = FakeResolver::new(&[(exe.as_str(), "tool")]);
+        let cache = empty_cache();
+        for _ in 0..3 {
+            let info = get_package_for_pid_tree(500, "tool", &procs, &resolver, &cache, &rules(false));
+            assert_eq!(info.package, "tool");
+        }
+        assert_eq!(resolver.lookups.get(), 1);
+    }
+
+    #[test]
+    fn opens_through_a_symlinked_dir_target_are_recorded_under_the_link() {
+        let tmp = tempfile::tempdir().unwrap();
+        let home = tmp.path().join("home");
+        let dotfiles = tmp.path().join("dotfiles/config");
+        fs::create_dir_all(&home).unwrap();
+        fs::create_dir_all(dotfiles.join("nvim")).unwrap();
+        std::os::unix::fs::symlink(&dotfiles, home.join(".config")).unwrap();
+
+        let settings = MatchSettings::new(&crate::config::Config::default(), &home, false);
+        let target = fs::canonicalize(&dotfiles).unwrap();
+        let recorded = settings.configured_path(target.join("nvim/init.lua"));
+        assert_eq!(recorded, home.join(".config/nvim/init.lua"));
+        assert!(settings.dir_matcher.matches(&recorded.to_string_lossy()));
+        assert_eq!(settings.configured_path(target.clone()), home.join(".config"));
+        // Paths elsewhere are left alone
+        let other = tmp.path().join("elsewhere/file");
+        assert_eq!(settings.configured_path(other.clone()), other);
+    }
+
+    #[test]
+    fn transition_helper_started_by_user_keeps_root_events() {
+        let procs = FakeProcs::new(&[
+            (400, 300, "/usr/bin/vim", "vim", 0),
+            (300, 200, "/usr/bin/sudo", "sudo", 1000),
+            (200, 100, "/usr/bin/zsh", "zsh", 1000),
+        ]);
+        let rules = rules(false);
+        assert!(rules.invoked_through_transition(&procs, 400, 1000));
+        assert!(!rules.invoked_through_transition(&procs, 400, 1001));
+    }
+
+    #[test]
+    fn transition_helpers_match_on_exe_not_comm() {
+        // A process calling itself "sudo" isn't a transition helper
+        let procs = FakeProcs::new(&[(400, 300, "/usr/bin/vim", "vim", 0), (300, 1, "/tmp/evil", "sudo", 1000)]);
+        assert!(!rules(false).invoked_through_transition(&procs, 400, 1000));
+
+        let config = crate::config::Config {
+            transition_processes: vec!["/usr/local/bin/doas".to_string()],
+            ..Default::default()
+        };
+        let rules = AttributionRules::new(&config, false);
+        assert!(rules.is_transition_exe("/usr/local/bin/doas"));
+        assert!(!rules.is_transition_exe("/usr/bin/doas"));
+    }
+
+    #[test]
+    fn event_layout_matches_the_bpf_structs() {
+        use std::mem::{offset_of, size_of};
+
+        // struct event: five 4-byte fields, comm[16], filename[256]
+        assert_eq!(offset_of!(Event, tgid), 0);
+        assert_eq!(offset_of!(Event, tid), 4);
+        assert_eq!(offset_of!(Event, uid), 8);
+        assert_eq!(offset_of!(Event, dfd), 12);
+        assert_eq!(offset_of!(Event, kind), 16);
+        assert_eq!(offset_of!(Event, comm), 20);
+        assert_eq!(offset_of!(Event, filename), 36);
+        assert_eq!(size_of::<Event>(), 292);
+
+        // struct rename_event: the base event, newdfd, newname[256]
+        assert_eq!(offset_of!(RenameEvent, base), 0);
+        assert_eq!(offset_of!(RenameEvent, newdfd), 292);
+        assert_eq!(offset_of!(RenameEvent, newname), 296);
+        assert_eq!(size_of::<RenameEvent>(), 552);
+    }
+
+    #[test]
+    fn mountinfo_escapes_decode_without_splitting_characters() {
+        assert_eq!(unescape_mountinfo("/mnt/My\\040Disk"), "/mnt/My Disk");
+        assert_eq!(unescape_mountinfo("/mnt/back\\134slash"), "/mnt/back\\slash");
+        // Not escapes: left as written
+        assert_eq!(unescape_mountinfo("/mnt/\\xé/data"), "/mnt/\\xé/data");
+        assert_eq!(unescape_mountinfo("/mnt/\\éé"), "/mnt/\\éé");
+        assert_eq!(unescape_mountinfo("/mnt/\\477"), "/mnt/\\477");
+        assert_eq!(unescape_mountinfo("/mnt/\\08"), "/mnt/\\08");
+    }
+}
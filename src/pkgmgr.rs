@@ -1,9 +1,13 @@
 use std::collections::HashSet;
 use std::process::Command;
+use std::sync::OnceLock;
 use std::time::Duration;
 
+/// Backend forced via `--package-manager`, bypassing auto-detection.
+static FORCED: OnceLock<PkgMgr> = OnceLock::new();
+
 /// Detected system package manager
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
 pub enum PkgMgr {
     Pacman,
     Dpkg,
@@ -13,8 +17,22 @@ pub enum PkgMgr {
 }
 
 impl PkgMgr {
+    /// Force a specific backend for the rest of the process. Fails if the
+    /// backend's binary isn't on PATH.
+    pub fn force(pm: Self) -> Result<(), String> {
+        if !which(pm.binary()) {
+            return Err(format!("--package-manager {}: '{}' not found in PATH", pm.name(), pm.binary()));
+        }
+        let _ = FORCED.set(pm);
+        Ok(())
+    }
+
     /// Auto-detect the system package manager by checking which binaries exist.
+    /// Returns the forced backend instead if one was set with `force`.
     pub fn detect() -> Option<Self> {
+        if let Some(pm) = FORCED.get() {
+            return Some(*pm);
+        }
         // Order matters: check more specific ones first
         if which("pacman") { return Some(Self::Pacman); }
         if which("dpkg")   { return Some(Self::Dpkg); }
@@ -36,6 +54,17 @@ impl PkgMgr {
         }
     }
 
+    /// The binary used to detect this package manager.
+    pub fn binary(&self) -> &str {
+        match self {
+            Self::Pacman => "pacman",
+            Self::Dpkg   => "dpkg",
+            Self::Rpm    => "rpm",
+            Self::Xbps   => "xbps-query",
+            Self::Apk    => "apk",
+        }
+    }
+
     pub fn name(&self) -> &str {
         match self {
             Self::Pacman => "pacman",
@@ -61,9 +90,25 @@ impl PkgMgr {
                 .args(["list", "--installed", "-q"])
                 .output()?,
         };
+        // A failing query (locked database, broken install) prints nothing on
+        // stdout; passing that on as "nothing installed" would make every
+        // tracked package look removed.
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(std::io::Error::other(format!(
+                "{} exited with {}: {}",
+                self.name(),
+                output.status,
+                stderr.trim()
+            )));
+        }
 
-        let text = String::from_utf8_lossy(&output.stdout);
-        let set = match self {
+        Ok(self.parse_installed(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    /// Normalized package names from the installed-list command's output.
+    fn parse_installed(&self, text: &str) -> HashSet<String> {
+        let set: HashSet<String> = match self {
             // xbps-query -l outputs "ii <pkg>-<ver>  <desc>" — we need column 2 minus the version
             Self::Xbps => text.lines().filter_map(|line| {
                 let pkg_ver = line.split_whitespace().nth(1)?;
@@ -75,103 +120,98 @@ impl PkgMgr {
             Self::Apk => text.lines().filter_map(|line| {
                 let trimmed = line.trim();
                 if trimmed.is_empty() { return None; }
-                // Alpine packages: name-version. Version starts after last hyphen
-                // that is followed by a digit.
-                let mut split_at = None;
-                for (i, _) in trimmed.match_indices('-') {
-                    if trimmed[i+1..].starts_with(|c: char| c.is_ascii_digit()) {
-                        split_at = Some(i);
-                        break;
-                    }
-                }
-                match split_at {
-                    Some(i) => Some(trimmed[..i].to_string()),
-                    None => Some(trimmed.to_string()),
-                }
+                Some(strip_apk_version(trimmed).to_string())
             }).collect(),
-            // pacman, dpkg, rpm give clean package-per-line
-            _ => text.lines()
-                .map(|s| s.trim().to_string())
-                .filter(|s| !s.is_empty())
-                .collect(),
+            // pacman, dpkg, rpm give one package per line
+            _ => text.lines().map(|s| s.to_string()).collect(),
         };
 
-        Ok(set)
+        set.iter()
+            .map(|name| self.normalize_package(name))
+            .filter(|name| !name.is_empty())
+            .collect()
     }
 
-    /// Query which package owns a given filesystem path.
+    /// Query which package owns a given filesystem path. When several packages
+    /// claim the path, the first reported owner wins; use `query_owners` to see
+    /// them all.
     pub fn query_owner(&self, path: &str) -> Option<String> {
+        self.query_owners(path).into_iter().next()
+    }
+
+    /// Query every package that claims ownership of a filesystem path, in the
+    /// order the package manager reports them. Empty if the path is unowned.
+    pub fn query_owners(&self, path: &str) -> Vec<String> {
         const TIMEOUT: Duration = Duration::from_secs(10);
-        match self {
-            Self::Pacman => {
-                let output = run_with_timeout(
-                    Command::new("pacman").args(["-Qo", path]),
-                    TIMEOUT,
-                )?;
-                if !output.status.success() { return None; }
-                let text = String::from_utf8_lossy(&output.stdout);
-                // "/<path> is owned by <package> <version>"
-                text.split_whitespace().nth(4).map(|s| s.to_string())
-            }
-            Self::Dpkg => {
-                let output = run_with_timeout(
-                    Command::new("dpkg").args(["-S", path]),
-                    TIMEOUT,
-                )?;
-                if !output.status.success() { return None; }
-                let text = String::from_utf8_lossy(&output.stdout);
-                // "package: /path" or "package:arch: /path"
-                let first_line = text.lines().next()?;
-                let pkg = first_line.split(':').next()?.trim();
-                if pkg.is_empty() { None } else { Some(pkg.to_string()) }
-            }
-            Self::Rpm => {
-                let output = run_with_timeout(
-                    Command::new("rpm").args(["-qf", "--qf", "%{NAME}", path]),
-                    TIMEOUT,
-                )?;
-                if !output.status.success() { return None; }
-                let text = String::from_utf8_lossy(&output.stdout).trim().to_string();
-                if text.is_empty() || text.contains("not owned") { None } else { Some(text) }
-            }
-            Self::Xbps => {
-                let output = run_with_timeout(
-                    Command::new("xbps-query").args(["-o", path]),
-                    TIMEOUT,
-                )?;
-                if !output.status.success() { return None; }
-                let text = String::from_utf8_lossy(&output.stdout);
-                // "<pkg>-<ver>: /path"
-                let first_line = text.lines().next()?;
-                let pkg_ver = first_line.split(':').next()?.trim();
+        let (program, args): (&str, Vec<&str>) = match self {
+            Self::Pacman => ("pacman", vec!["-Qo", path]),
+            Self::Dpkg   => ("dpkg", vec!["-S", path]),
+            Self::Rpm    => ("rpm", vec!["-qf", "--qf", "%{NAME}\\n", path]),
+            Self::Xbps   => ("xbps-query", vec!["-o", path]),
+            Self::Apk    => ("apk", vec!["info", "--who-owns", path]),
+        };
+        let Some(output) = run_with_timeout(Command::new(program).args(&args), TIMEOUT) else {
+            return vec![];
+        };
+        if !output.status.success() {
+            return vec![];
+        }
+        self.parse_owners(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    /// Normalized, de-duplicated owners from the owner-query command's output.
+    fn parse_owners(&self, text: &str) -> Vec<String> {
+        let owners: Vec<String> = match self {
+            // "/<path> is owned by <package> <version>", one line per owner
+            Self::Pacman => text.lines().filter_map(|line| {
+                let idx = line.find(" is owned by ")?;
+                line[idx + 13..].split_whitespace().next().map(|s| s.to_string())
+            }).collect(),
+            // "pkg1, pkg2:arch: /path" — a single line can list several owners
+            Self::Dpkg => text.lines()
+                .filter(|line| !line.starts_with("diversion "))
+                .filter_map(|line| line.split_once(": ").map(|(pkgs, _)| pkgs))
+                .flat_map(|pkgs| pkgs.split(", "))
+                .map(|pkg| pkg.to_string())
+                .collect(),
+            Self::Rpm => text.lines()
+                .map(|s| s.trim())
+                .filter(|s| !s.is_empty() && !s.contains("not owned"))
+                .map(|s| s.to_string())
+                .collect(),
+            // "<pkg>-<ver>: /path"
+            Self::Xbps => text.lines().filter_map(|line| {
+                let pkg_ver = line.split(':').next()?.trim();
                 let last_dash = pkg_ver.rfind('-')?;
                 let name = &pkg_ver[..last_dash];
                 if name.is_empty() { None } else { Some(name.to_string()) }
-            }
-            Self::Apk => {
-                let output = run_with_timeout(
-                    Command::new("apk").args(["info", "--who-owns", path]),
-                    TIMEOUT,
-                )?;
-                if !output.status.success() { return None; }
-                let text = String::from_utf8_lossy(&output.stdout);
-                // "<path> is owned by <package>-<version>"
-                let owned_by = text.find("is owned by ")?;
-                let after = &text[owned_by + 12..];
-                let pkg_ver = after.trim();
-                // Strip trailing -version (first hyphen followed by digit)
-                let mut split_at = None;
-                for (i, _) in pkg_ver.match_indices('-') {
-                    if pkg_ver[i+1..].starts_with(|c: char| c.is_ascii_digit()) {
-                        split_at = Some(i);
-                        break;
-                    }
-                }
-                match split_at {
-                    Some(i) => Some(pkg_ver[..i].to_string()),
-                    None => Some(pkg_ver.to_string()),
-                }
-            }
+            }).collect(),
+            // "<path> is owned by <package>-<version>"
+            Self::Apk => text.lines().filter_map(|line| {
+                let owned_by = line.find("is owned by ")?;
+                let pkg_ver = line[owned_by + 12..].trim();
+                Some(strip_apk_version(pkg_ver).to_string())
+            }).collect(),
+        };
+
+        let mut seen = HashSet::new();
+        owners
+            .iter()
+            .map(|o| self.normalize_package(o))
+            .filter(|o| !o.is_empty() && seen.insert(o.clone()))
+            .collect()
+    }
+
+    /// Canonical form of a package name, applied wherever names enter hdas
+    /// (installed lists, owner lookups, recorded attributions) so they compare
+    /// equal. Surrounding whitespace is trimmed for every manager. dpkg names
+    /// also drop their multiarch qualifier (`libc6:amd64` → `libc6`) and are
+    /// lowercased, since dpkg matches package names case-insensitively.
+    pub fn normalize_package(&self, name: &str) -> String {
+        let name = name.trim();
+        match self {
+            Self::Dpkg => name.split(':').next().unwrap_or(name).trim().to_lowercase(),
+            _ => name.to_string(),
         }
     }
 
@@ -181,6 +221,30 @@ impl PkgMgr {
     }
 }
 
+/// Maps a filesystem path (usually a process exe) to its owning package.
+/// Attribution code depends on this rather than on `PkgMgr` directly so a
+/// fake resolver can stand in for subprocess calls.
+pub trait OwnerResolver {
+    fn owner(&self, exe: &str) -> Option<String>;
+}
+
+impl OwnerResolver for PkgMgr {
+    fn owner(&self, exe: &str) -> Option<String> {
+        self.query_owner(exe)
+    }
+}
+
+/// Alpine packages are "name-version"; the version starts after the first
+/// hyphen that is followed by a digit.
+fn strip_apk_version(pkg_ver: &str) -> &str {
+    for (i, _) in pkg_ver.match_indices('-') {
+        if pkg_ver[i+1..].starts_with(|c: char| c.is_ascii_digit()) {
+            return &pkg_ver[..i];
+        }
+    }
+    pkg_ver
+}
+
 fn run_with_timeout(cmd: &mut Command, timeout: Duration) -> Option<std::process::Output> {
     use std::time::Instant;
 
@@ -212,3 +276,42 @@ fn which(name: &str) -> bool {
         })
         .unwrap_or(false)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dpkg_multiarch_qualifier_is_dropped() {
+        assert_eq!(PkgMgr::Dpkg.normalize_package("libc6:amd64"), "libc6");
+        assert_eq!(
+            PkgMgr::Dpkg.parse_owners("libc6:amd64: /usr/lib/x86_64-linux-gnu/libc.so.6\n"),
+            vec!["libc6"]
+        );
+        assert_eq!(
+            PkgMgr::Dpkg.parse_owners("libc6:amd64, libc6:i386: /usr/share/doc/libc6\n"),
+            vec!["libc6"]
+        );
+        assert!(PkgMgr::Dpkg.parse_installed("libc6:amd64\nlibc6:i386\nbash\n").contains("libc6"));
+    }
+
+    #[test]
+    fn owner_and_installed_names_agree() {
+        // The same package as each manager reports it when asked who owns a
+        // file, and in its installed list
+        let cases: &[(PkgMgr, &str, &str, &str)] = &[
+            (PkgMgr::Pacman, "/usr/bin/firefox is owned by firefox 128.0-1\n", "firefox\nbash\n", "firefox"),
+            (PkgMgr::Dpkg, "Firefox-ESR:amd64: /usr/bin/firefox-esr\n", "firefox-esr\n", "firefox-esr"),
+            (PkgMgr::Dpkg, " libc6:amd64 : /usr/lib/libc.so.6\n", "libc6:amd64\n", "libc6"),
+            (PkgMgr::Rpm, "  firefox  \n", "firefox\nbash\n", "firefox"),
+            (PkgMgr::Xbps, "firefox-128.0_1: /usr/bin/firefox\n", "ii firefox-128.0_1  Mozilla Firefox\n", "firefox"),
+            (PkgMgr::Apk, "/usr/bin/firefox is owned by firefox-128.0-r0\n", "firefox-128.0-r0\n", "firefox"),
+        ];
+        for &(pm, owner_output, installed_output, expected) in cases {
+            let owners = pm.parse_owners(owner_output);
+            assert_eq!(owners, vec![expected], "{:?} owner output", pm);
+            assert!(pm.parse_installed(installed_output).contains(&owners[0]), "{:?} installed output", pm);
+            assert_eq!(pm.normalize_package(&format!(" {} ", expected)), expected, "{:?} recorded name", pm);
+        }
+    }
+}
@@ -0,0 +1,24 @@
+//! Optional progress bars (`--progress`) for scans that stat many files.
+//! Bars always draw to stderr so stdout stays clean for pipes.
+
+use indicatif::{ProgressBar, ProgressStyle};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Turn progress bars on for the rest of the process.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// A bar counting `len` items, labeled with `message`. A hidden no-op bar
+/// unless `--progress` enabled them, so call sites don't need to check.
+pub fn bar(len: u64, message: &'static str) -> ProgressBar {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return ProgressBar::hidden();
+    }
+    let style = ProgressStyle::with_template("{msg} [{bar:30}] {pos}/{len} ({eta})")
+        .expect("valid progress template")
+        .progress_chars("=> ");
+    ProgressBar::new(len).with_style(style).with_message(message)
+}
@@ -1,48 +1,602 @@
 use anyhow::Result;
-use chrono::{DateTime, Datelike, Local, Utc};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Datelike, Local, TimeZone, Utc};
+use chrono_tz::Tz;
 use owo_colors::OwoColorize;
 use serde::Serialize;
 use std::io::IsTerminal;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use crate::config::{Config, SizeUnits, MAX_USUAL_DEPTH};
+use crate::db::{CreatedRange, FileRecord, PathMatch, RecordSort};
+
+/// When to color output (`--color`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorWhen {
+    /// When stdout is a terminal and NO_COLOR isn't set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+static COLOR: OnceLock<bool> = OnceLock::new();
+
+/// Decide coloring for this process (from `--color`). Must be called before
+/// the first `use_color`.
+pub fn set_color(when: ColorWhen) {
+    let _ = COLOR.set(resolve_color(when));
+}
+
+fn resolve_color(when: ColorWhen) -> bool {
+    match when {
+        ColorWhen::Always => true,
+        ColorWhen::Never => false,
+        // https://no-color.org: any non-empty value turns color off
+        ColorWhen::Auto => {
+            std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()) && std::io::stdout().is_terminal()
+        }
+    }
+}
+
+/// Whether to color stdout output, shared by every command.
+pub(crate) fn use_color() -> bool {
+    *COLOR.get_or_init(|| resolve_color(ColorWhen::Auto))
+}
+
+/// Version of the `--json` output format. Bump when a field is renamed or
+/// removed, or a value changes meaning; adding fields does not bump it.
+pub const JSON_SCHEMA_VERSION: u32 = 1;
+
+static JSON_COMMAND: OnceLock<String> = OnceLock::new();
+
+/// Record the subcommand name reported in the JSON envelope.
+pub fn set_json_command(name: &str) {
+    let _ = JSON_COMMAND.set(name.to_string());
+}
+
+fn json_command() -> &'static str {
+    JSON_COMMAND.get().map(String::as_str).unwrap_or("")
+}
+
+#[derive(Serialize)]
+struct JsonEnvelope<'a, T: ?Sized> {
+    schema_version: u32,
+    command: &'a str,
+    data: &'a T,
+}
+
+/// Print `data` as `--json` output, wrapped in the versioned envelope
+/// `{"schema_version", "command", "data"}`.
+pub(crate) fn print_json<T: Serialize + ?Sized>(data: &T) -> Result<()> {
+    let envelope = JsonEnvelope {
+        schema_version: JSON_SCHEMA_VERSION,
+        command: json_command(),
+        data,
+    };
+    println!("{}", serde_json::to_string_pretty(&envelope)?);
+    Ok(())
+}
+
+/// Line-oriented record output for scripts (`--format`): no decorations,
+/// raw Unix timestamps, fields in [`FileRecord`] order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum RecordFormat {
+    /// One record per line, tab-separated; backslash, tab, newline, and CR escaped C-style
+    Tsv,
+    /// Every field NUL-terminated (7 per record), nothing escaped
+    Null,
+}
+
+/// How `query` and `package` present their records when not pretty-printing.
+/// The modes are mutually exclusive on the command line.
+#[derive(Debug, Clone, Copy)]
+pub enum RecordOutput<'a> {
+    /// Decorated listing (or plain `--json`)
+    Pretty,
+    /// `--format`
+    Format(RecordFormat),
+    /// `--export-manifest FILE`
+    ExportManifest(&'a Path),
+    /// `--owner-check`
+    OwnerCheck,
+}
+
+impl<'a> RecordOutput<'a> {
+    pub fn from_flags(format: Option<RecordFormat>, export_manifest: Option<&'a Path>, owner_check: bool) -> Self {
+        match (format, export_manifest) {
+            _ if owner_check => Self::OwnerCheck,
+            (_, Some(path)) => Self::ExportManifest(path),
+            (Some(format), None) => Self::Format(format),
+            (None, None) => Self::Pretty,
+        }
+    }
+}
+
+/// File formats for `hdas export`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    /// A JSON array of records, timestamps as Unix seconds (as with --json)
+    Json,
+    /// One compact JSON record per line
+    Jsonl,
+    /// CSV with a header row and RFC 3339 (UTC) timestamps
+    Csv,
+}
+
+/// Compile a `query --regex` pattern.
+pub fn compile_path_regex(pattern: &str) -> Result<regex::Regex> {
+    regex::Regex::new(pattern).map_err(|e| anyhow::anyhow!("Invalid --regex pattern '{}': {}", pattern, e))
+}
+
+/// `--package-regex` / `--process-regex`: regexes on the creating package and
+/// process, applied in Rust after the SQL filters.
+#[derive(Debug, Default)]
+pub struct AttributionFilter {
+    package: Option<regex::Regex>,
+    process: Option<regex::Regex>,
+}
+
+impl AttributionFilter {
+    /// Compile the patterns, naming the offending flag if one is invalid.
+    pub fn new(package: Option<&str>, process: Option<&str>) -> Result<Self> {
+        let compile = |flag: &str, pattern: Option<&str>| {
+            pattern
+                .map(|p| regex::Regex::new(p).map_err(|e| anyhow::anyhow!("Invalid {} '{}': {}", flag, p, e)))
+                .transpose()
+        };
+        Ok(Self {
+            package: compile("--package-regex", package)?,
+            process: compile("--process-regex", process)?,
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.package.is_none() && self.process.is_none()
+    }
+
+    fn matches(&self, record: &FileRecord) -> bool {
+        self.package.as_ref().is_none_or(|re| re.is_match(&record.created_by_package))
+            && self.process.as_ref().is_none_or(|re| re.is_match(&record.created_by_process))
+    }
+
+    /// A literal prefix every matching package name must start with, for
+    /// narrowing the rows fetched from the database: `^python3?-` gives
+    /// `python`. `None` when the pattern isn't anchored or uses alternation.
+    fn package_prefix(&self) -> Option<String> {
+        let pattern = self.package.as_ref()?.as_str();
+        let rest = pattern.strip_prefix('^')?;
+        if pattern.contains('|') {
+            return None;
+        }
+        let mut prefix = String::new();
+        let mut chars = rest.chars().peekable();
+        while let Some(c) = chars.next() {
+            if "\\.+*?()|[]{}^$".contains(c) {
+                break;
+            }
+            match chars.peek() {
+                // The character may be absent
+                Some('?' | '*' | '{') => break,
+                Some('+') => {
+                    prefix.push(c);
+                    break;
+                }
+                _ => prefix.push(c),
+            }
+        }
+        (!prefix.is_empty()).then_some(prefix)
+    }
+
+    /// Human-readable description of the active filters, e.g. for headers.
+    fn describe(&self) -> Vec<String> {
+        let mut what = Vec::new();
+        if let Some(re) = &self.package {
+            what.push(format!("from packages matching /{}/", re));
+        }
+        if let Some(re) = &self.process {
+            what.push(format!("via processes matching /{}/", re));
+        }
+        what
+    }
+}
+
+/// Escape a TSV field so it can't break the line or column structure.
+fn tsv_escape(field: &str) -> std::borrow::Cow<'_, str> {
+    if !field.contains(['\\', '\t', '\n', '\r']) {
+        return field.into();
+    }
+    let mut out = String::with_capacity(field.len() + 8);
+    for c in field.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out.into()
+}
+
+fn write_record(out: &mut impl std::io::Write, record: &FileRecord, format: RecordFormat) -> Result<()> {
+    let created_at = record.created_at.to_string();
+    let last_accessed_at = record.last_accessed_at.to_string();
+    let fields = [
+        record.path.as_str(),
+        &record.created_by_package,
+        &record.created_by_process,
+        &created_at,
+        &record.last_accessed_by_package,
+        &record.last_accessed_by_process,
+        &last_accessed_at,
+    ];
+    match format {
+        RecordFormat::Tsv => {
+            let line: Vec<_> = fields.iter().map(|f| tsv_escape(f)).collect();
+            writeln!(out, "{}", line.join("\t"))?;
+        }
+        RecordFormat::Null => {
+            // Paths come from the kernel and can't contain NUL, so the bytes
+            // go out untouched
+            for field in fields {
+                out.write_all(field.as_bytes())?;
+                out.write_all(b"\0")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Quote a CSV field (RFC 4180) if it contains a delimiter, quote, or line
+/// break; embedded quotes are doubled.
+fn csv_escape(field: &str) -> std::borrow::Cow<'_, str> {
+    if !field.contains([',', '"', '\n', '\r']) {
+        return field.into();
+    }
+    format!("\"{}\"", field.replace('"', "\"\"")).into()
+}
+
+/// RFC 3339 UTC timestamp for CSV export; empty when unknown (0).
+fn rfc3339(timestamp: i64) -> String {
+    if timestamp == 0 {
+        return String::new();
+    }
+    DateTime::<Utc>::from_timestamp(timestamp, 0)
+        .map(|dt| dt.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+        .unwrap_or_default()
+}
+
+/// Header row of `export --format csv`, matching [`write_csv_record`].
+const CSV_HEADER: &str = "path,created_by_package,created_by_process,created_at,\
+                          last_accessed_by_package,last_accessed_by_process,last_accessed_at";
+
+fn write_csv_record(out: &mut impl std::io::Write, record: &FileRecord) -> Result<()> {
+    let created_at = rfc3339(record.created_at);
+    let last_accessed_at = rfc3339(record.last_accessed_at);
+    let fields = [
+        record.path.as_str(),
+        &record.created_by_package,
+        &record.created_by_process,
+        &created_at,
+        &record.last_accessed_by_package,
+        &record.last_accessed_by_process,
+        &last_accessed_at,
+    ];
+    let line: Vec<_> = fields.iter().map(|f| csv_escape(f)).collect();
+    // RFC 4180 line ending, which spreadsheet imports expect
+    write!(out, "{}\r\n", line.join(","))?;
+    Ok(())
+}
+
+/// Print `records` in a `--format` output mode.
+fn print_records(records: &[FileRecord], format: RecordFormat) -> Result<()> {
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    for record in records {
+        write_record(&mut out, record, format)?;
+    }
+    std::io::Write::flush(&mut out)?;
+    Ok(())
+}
+
+static NDJSON: AtomicBool = AtomicBool::new(false);
+
+/// Stream record listings as NDJSON (from `--ndjson`, which implies `--json`).
+pub fn set_ndjson() {
+    NDJSON.store(true, Ordering::Relaxed);
+}
+
+fn ndjson() -> bool {
+    NDJSON.load(Ordering::Relaxed)
+}
+
+/// `--json` output for a record listing: the enveloped document, or with
+/// `--ndjson` one compact object per line and no envelope.
+fn print_json_records<T: Serialize>(records: &[T]) -> Result<()> {
+    if !ndjson() {
+        return print_json(records);
+    }
+    use std::io::Write;
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    for record in records {
+        serde_json::to_writer(&mut out, record)?;
+        out.write_all(b"\n")?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+static DEBUG_TIMING: AtomicBool = AtomicBool::new(false);
+
+/// Enable per-phase timing on stderr (from `--debug-timing`).
+pub fn set_debug_timing() {
+    DEBUG_TIMING.store(true, Ordering::Relaxed);
+}
+
+/// Reports how long a phase took when dropped, if `--debug-timing` is on.
+/// Goes to stderr so stdout (including JSON) stays clean.
+pub(crate) struct PhaseTimer {
+    phase: &'static str,
+    start: Option<Instant>,
+}
+
+impl PhaseTimer {
+    pub(crate) fn start(phase: &'static str) -> Self {
+        let start = DEBUG_TIMING.load(Ordering::Relaxed).then(Instant::now);
+        Self { phase, start }
+    }
+}
+
+impl Drop for PhaseTimer {
+    fn drop(&mut self) {
+        if let Some(start) = self.start {
+            eprintln!("[timing] {}: {:.1?}", self.phase, start.elapsed());
+        }
+    }
+}
+
+static TIME_FORMAT: OnceLock<Option<String>> = OnceLock::new();
+
+/// Override the display time format for this process (from `--time-format`).
+/// Must be called before the first `format_time`.
+pub fn set_time_format(format: String) {
+    let _ = TIME_FORMAT.set(Some(format));
+}
+
+static TIMEZONE: OnceLock<Option<Tz>> = OnceLock::new();
+
+/// Pin displayed times to `tz` for this process (from `--timezone`).
+/// Must be called before the first `format_time`.
+pub fn set_timezone(tz: Tz) {
+    let _ = TIMEZONE.set(Some(tz));
+}
+
+/// Parse an IANA timezone name such as "Europe/Berlin" or "UTC".
+pub fn parse_timezone(name: &str) -> Result<Tz> {
+    name.parse::<Tz>()
+        .map_err(|_| anyhow::anyhow!("Unknown timezone '{}' (expected an IANA name like Europe/Berlin or UTC)", name))
+}
+
+/// The display timezone: `--timezone`, else `timezone` in config, else
+/// `None` for system local time (which follows `TZ`). An unknown name in
+/// config warns once and falls back to local time.
+fn display_timezone() -> Option<Tz> {
+    *TIMEZONE.get_or_init(|| {
+        let name = Config::load().ok().and_then(|c| c.timezone)?;
+        match parse_timezone(&name) {
+            Ok(tz) => Some(tz),
+            Err(e) => {
+                eprintln!("Warning: {} in config; using local time", e);
+                None
+            }
+        }
+    })
+}
+
+/// Returns true if `format` is a strftime string chrono can render.
+pub fn is_valid_time_format(format: &str) -> bool {
+    !StrftimeItems::new(format).any(|item| matches!(item, Item::Error))
+}
+
+/// Named creation-time windows for `hdas query`. Each resolves to a lower
+/// bound on `created_at`; records with unknown creation time never match.
+#[derive(Debug, Clone, Copy)]
+pub enum TimeRange {
+    /// Since midnight today
+    Today,
+    /// Since midnight on the most recent Monday
+    ThisWeek,
+    /// Since midnight on the 1st of this month
+    ThisMonth,
+    /// Since the kernel booted (`btime` in /proc/stat)
+    LastBoot,
+}
+
+impl TimeRange {
+    /// Start of the range as Unix seconds. Calendar ranges use the display
+    /// timezone; on a DST gap at midnight the first valid minute is used.
+    pub fn start(self) -> Result<i64> {
+        let today = match display_timezone() {
+            Some(tz) => Utc::now().with_timezone(&tz).date_naive(),
+            None => Local::now().date_naive(),
+        };
+        let first_day = match self {
+            Self::Today => today,
+            Self::ThisWeek => today - chrono::Duration::days(today.weekday().num_days_from_monday() as i64),
+            Self::ThisMonth => today.with_day(1).unwrap_or(today),
+            Self::LastBoot => return boot_time(),
+        };
+        display_midnight(first_day)
+    }
+}
+
+/// Midnight at the start of `day` in the display timezone, as Unix seconds.
+fn display_midnight(day: chrono::NaiveDate) -> Result<i64> {
+    let start = match display_timezone() {
+        Some(tz) => midnight_in(day, &tz),
+        None => midnight_in(day, &Local),
+    };
+    start.ok_or_else(|| anyhow::anyhow!("Could not resolve midnight for {}", day))
+}
+
+/// Midnight at the start of `day` in `tz`. Where a DST change skips
+/// midnight, the day starts at the first minute that exists.
+fn midnight_in<Z: TimeZone>(day: chrono::NaiveDate, tz: &Z) -> Option<i64> {
+    (0..24 * 60).find_map(|minute| {
+        day.and_hms_opt(minute / 60, minute % 60, 0)?
+            .and_local_timezone(tz.clone())
+            .earliest()
+            .map(|dt| dt.timestamp())
+    })
+}
+
+/// Resolve `--since` / `--until` for `list` and `package`. Each accepts a
+/// date in the display timezone (`YYYY-MM-DD`; `--until` includes that whole day) or an age
+/// counted back from now (`90s`, `30m`, `24h`, `7d`, `2w`).
+pub fn parse_created_range(since: Option<&str>, until: Option<&str>) -> Result<CreatedRange> {
+    let range = CreatedRange {
+        since: since.map(|s| parse_date_arg("--since", s, false)).transpose()?,
+        until: until.map(|s| parse_date_arg("--until", s, true)).transpose()?,
+    };
+    if let (Some(start), Some(end)) = (range.since, range.until) {
+        if start >= end {
+            anyhow::bail!(
+                "--since {} is not before --until {}; nothing could match",
+                since.unwrap_or_default(),
+                until.unwrap_or_default()
+            );
+        }
+    }
+    Ok(range)
+}
+
+/// One `--since` / `--until` value as Unix seconds. A date resolves to its
+/// midnight in the display timezone, or with `end_of_day` to the following one.
+fn parse_date_arg(flag: &str, value: &str, end_of_day: bool) -> Result<i64> {
+    let invalid = || {
+        anyhow::anyhow!(
+            "Invalid {} '{}' (expected a date like 2024-05-31 or an age like 7d, 24h, 30m, 2w)",
+            flag,
+            value
+        )
+    };
+    let value = value.trim();
+
+    // Exactly YYYY-MM-DD, so 05/06/2024-style dates can't be misread
+    if value.len() == 10 && value.as_bytes()[4] == b'-' && value.as_bytes()[7] == b'-' {
+        let day = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| invalid())?;
+        let day = if end_of_day { day.succ_opt().ok_or_else(invalid)? } else { day };
+        return display_midnight(day);
+    }
+
+    let split = value.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+    let (amount, unit) = value.split_at(split);
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    let age = amount.checked_mul(seconds).ok_or_else(invalid)?;
+    Ok(Utc::now().timestamp().saturating_sub(age))
+}
 
-use crate::config::Config;
-use crate::db::FileRecord;
+/// Header fragments describing `range`, e.g. "created since Jan 05 12:00".
+fn describe_created_range(range: CreatedRange) -> Vec<String> {
+    let mut what = Vec::new();
+    if let Some(since) = range.since {
+        what.push(format!("created since {}", format_time(since)));
+    }
+    if let Some(until) = range.until {
+        what.push(format!("created before {}", format_time(until)));
+    }
+    what
+}
 
-fn use_color() -> bool {
-    std::io::stdout().is_terminal()
+/// System boot time from the `btime` line of /proc/stat.
+fn boot_time() -> Result<i64> {
+    let stat = std::fs::read_to_string("/proc/stat")?;
+    stat.lines()
+        .find_map(|line| line.strip_prefix("btime "))
+        .and_then(|v| v.trim().parse().ok())
+        .ok_or_else(|| anyhow::anyhow!("No btime in /proc/stat"))
 }
 
 fn format_time(timestamp: i64) -> String {
+    // 0 means "missing" (NULL or imported without a time), not 1970
+    if timestamp == 0 {
+        return "(unknown time)".to_string();
+    }
+
     let Some(dt) = DateTime::<Utc>::from_timestamp(timestamp, 0) else {
         return "            ".to_string();
     };
 
-    let local: DateTime<Local> = dt.into();
-    let now = Local::now();
+    match display_timezone() {
+        Some(tz) => render_time(dt.with_timezone(&tz), Utc::now().with_timezone(&tz)),
+        None => render_time(dt.with_timezone(&Local), Local::now()),
+    }
+}
+
+fn render_time<T: TimeZone>(time: DateTime<T>, now: DateTime<T>) -> String
+where
+    T::Offset: std::fmt::Display,
+{
+    let custom = TIME_FORMAT.get_or_init(|| Config::load().ok().and_then(|c| c.time_format));
+    if let Some(format) = custom.as_deref().filter(|f| is_valid_time_format(f)) {
+        return time.format(format).to_string();
+    }
 
-    if local.year() == now.year() {
-        local.format("%b %d %H:%M").to_string()
+    if time.year() == now.year() {
+        time.format("%b %d %H:%M").to_string()
     } else {
-        local.format("%b %d  %Y").to_string()
+        time.format("%b %d  %Y").to_string()
     }
 }
 
+static SIZE_UNITS: OnceLock<SizeUnits> = OnceLock::new();
+
+/// Override the size units for this process (from `--size-units`).
+/// Must be called before the first `format_size`.
+pub fn set_size_units(units: SizeUnits) {
+    let _ = SIZE_UNITS.set(units);
+}
+
 pub fn format_size(bytes: u64) -> String {
-    const KB: u64 = 1024;
-    const MB: u64 = KB * 1024;
-    const GB: u64 = MB * 1024;
-
-    if bytes >= GB {
-        format!("{:.1}G", bytes as f64 / GB as f64)
-    } else if bytes >= MB {
-        format!("{:.1}M", bytes as f64 / MB as f64)
-    } else if bytes >= KB {
-        format!("{:.1}K", bytes as f64 / KB as f64)
+    let units = *SIZE_UNITS.get_or_init(|| Config::load().map(|c| c.size_units).unwrap_or_default());
+    let (base, labels) = match units {
+        SizeUnits::Binary => (1024u64, ["KiB", "MiB", "GiB"]),
+        SizeUnits::Si => (1000u64, ["kB", "MB", "GB"]),
+    };
+    let kb = base;
+    let mb = kb * base;
+    let gb = mb * base;
+
+    if bytes >= gb {
+        format!("{:.1}{}", bytes as f64 / gb as f64, labels[2])
+    } else if bytes >= mb {
+        format!("{:.1}{}", bytes as f64 / mb as f64, labels[1])
+    } else if bytes >= kb {
+        format!("{:.1}{}", bytes as f64 / kb as f64, labels[0])
     } else {
         format!("{}B", bytes)
     }
 }
 
+/// `  12.3 KiB` after a listed path; empty when the size wasn't measured.
+fn size_suffix(record: &FileRecord) -> String {
+    match record.size_bytes {
+        Some(size) => format!("  {}", format_size(size)),
+        None => String::new(),
+    }
+}
+
 fn display_record(record: &FileRecord, show_accessor: bool) {
     let color = use_color();
     let exists_char = if Path::new(&record.path).exists() { "✓" } else { "✗" };
@@ -55,14 +609,15 @@ fn display_record(record: &FileRecord, show_accessor: bool) {
             format!("{}", exists_char.red())
         };
         println!(
-            "{} [{}] {} ({})",
+            "{} [{}] {} ({}){}",
             time.dimmed(),
             exists_colored,
             record.path,
-            record.created_by_package.cyan()
+            record.created_by_package.cyan(),
+            size_suffix(record).dimmed()
         );
     } else {
-        println!("{} [{}] {} ({})", time, exists_char, record.path, record.created_by_package);
+        println!("{} [{}] {} ({}){}", time, exists_char, record.path, record.created_by_package, size_suffix(record));
     }
 
     if show_accessor
@@ -118,22 +673,232 @@ fn maybe_prune(db: &crate::db::Database, json: bool) -> Result<usize> {
     }
 }
 
-pub fn query_file(pattern: &str, json: bool) -> Result<()> {
-    let db = crate::db::Database::new()?;
-    maybe_prune(&db, json)?;
-    let records = db.query_file(pattern)?;
+#[derive(Serialize)]
+struct CountOutput {
+    count: usize,
+}
+
+fn print_count(count: usize, json: bool) -> Result<()> {
+    if json {
+        print_json(&CountOutput { count })?;
+    } else {
+        println!("{}", count);
+    }
+    Ok(())
+}
+
+/// `query --count-only`: number of records matching `pattern`.
+pub fn count_query_file(
+    path: PathMatch,
+    range: Option<TimeRange>,
+    missing: bool,
+    filter: &AttributionFilter,
+    json: bool,
+) -> Result<()> {
+    count_query_file_with(&crate::db::Database::new()?, path, range, missing, filter, json)
+}
+
+/// [`count_query_file`] against an already-open database.
+pub fn count_query_file_with(
+    db: &crate::db::Database,
+    path: PathMatch,
+    range: Option<TimeRange>,
+    missing: bool,
+    filter: &AttributionFilter,
+    json: bool,
+) -> Result<()> {
+    let since = range.map(TimeRange::start).transpose()?;
+    if !missing {
+        maybe_prune(db, json)?;
+        if let (PathMatch::Like(pattern), true) = (path, filter.is_empty()) {
+            return print_count(db.count_query_file(pattern, since)?, json);
+        }
+    }
+    // Regexes and the existence check both need the rows themselves
+    let records = db.query_file(path, since, filter.package_prefix().as_deref())?;
+    let count = records
+        .iter()
+        .filter(|r| filter.matches(r))
+        .filter(|r| !missing || !Path::new(&r.path).exists())
+        .count();
+    print_count(count, json)
+}
+
+/// `package --count-only`: number of records created by `package`.
+pub fn count_package(package: &str, json: bool) -> Result<()> {
+    count_package_with(&crate::db::Database::new()?, package, json)
+}
+
+/// [`count_package`] against an already-open database.
+pub fn count_package_with(db: &crate::db::Database, package: &str, json: bool) -> Result<()> {
+    maybe_prune(db, json)?;
+    print_count(db.count_package(package)?, json)
+}
+
+/// `dir --count-only`: number of records under `dir`.
+pub fn count_directory(dir: &str, json: bool) -> Result<()> {
+    count_directory_with(&crate::db::Database::new()?, dir, json)
+}
+
+/// [`count_directory`] against an already-open database.
+pub fn count_directory_with(db: &crate::db::Database, dir: &str, json: bool) -> Result<()> {
+    maybe_prune(db, json)?;
+    let expanded = crate::db::expand_user_path(dir)?;
+    print_count(db.count_directory(&expanded)?, json)
+}
+
+#[derive(Serialize)]
+struct OwnerCheckEntry {
+    path: String,
+    recorded_package: String,
+    /// What the package manager says owns the path right now
+    filesystem_owners: Vec<String>,
+    /// "match", "mismatch", or "unowned" (no package claims the path)
+    status: &'static str,
+}
+
+#[derive(Serialize)]
+struct OwnerCheckOutput {
+    checked: usize,
+    matches: usize,
+    mismatches: usize,
+    unowned: usize,
+    files: Vec<OwnerCheckEntry>,
+}
+
+/// `--owner-check`: compare each record's package with the package manager's
+/// current owner of the path. One subprocess per record, so opt-in.
+fn print_owner_check(records: &[FileRecord], json: bool) -> Result<()> {
+    let pm = crate::pkgmgr::PkgMgr::detect()
+        .ok_or_else(|| anyhow::anyhow!("No supported package manager found"))?;
+
+    let mut files = Vec::with_capacity(records.len());
+    {
+        let _t = PhaseTimer::start("ownership lookups");
+        let bar = crate::progress::bar(records.len() as u64, "Querying owners");
+        for record in records {
+            bar.inc(1);
+            let owners = pm.query_owners(&record.path);
+            let status = if owners.is_empty() {
+                "unowned"
+            } else if owners.contains(&record.created_by_package) {
+                "match"
+            } else {
+                "mismatch"
+            };
+            files.push(OwnerCheckEntry {
+                path: record.path.clone(),
+                recorded_package: record.created_by_package.clone(),
+                filesystem_owners: owners,
+                status,
+            });
+        }
+        bar.finish_and_clear();
+    }
+
+    let count = |status: &str| files.iter().filter(|f| f.status == status).count();
+    let output = OwnerCheckOutput {
+        checked: files.len(),
+        matches: count("match"),
+        mismatches: count("mismatch"),
+        unowned: count("unowned"),
+        files,
+    };
 
     if json {
-        println!("{}", serde_json::to_string_pretty(&records)?);
+        print_json(&output)?;
+        return Ok(());
+    }
+
+    let color = use_color();
+    for file in &output.files {
+        let owners = file.filesystem_owners.join(", ");
+        match file.status {
+            "mismatch" => {
+                let marker = if color { "mismatch".red().bold().to_string() } else { "mismatch".to_string() };
+                println!("{:<8} {} (recorded {}, owned by {})", marker, file.path, file.recorded_package, owners);
+            }
+            "match" => println!("{:<8} {} ({})", "ok", file.path, file.recorded_package),
+            _ => println!("{:<8} {} (recorded {})", "unowned", file.path, file.recorded_package),
+        }
+    }
+
+    println!(
+        "\nChecked {} file(s) against {}: {} match, {} mismatch, {} not owned by any package",
+        output.checked,
+        pm.name(),
+        output.matches,
+        output.mismatches,
+        output.unowned
+    );
+
+    Ok(())
+}
+
+/// `query`: records whose path matches `pattern`, optionally limited to a
+/// creation-time range and, with `missing`, to files no longer on disk.
+pub fn query_file(
+    path: PathMatch,
+    range: Option<TimeRange>,
+    missing: bool,
+    filter: &AttributionFilter,
+    output: RecordOutput,
+    json: bool,
+) -> Result<()> {
+    query_file_with(&crate::db::Database::new()?, path, range, missing, filter, output, json)
+}
+
+/// [`query_file`] against an already-open database.
+pub fn query_file_with(
+    db: &crate::db::Database,
+    path: PathMatch,
+    range: Option<TimeRange>,
+    missing: bool,
+    filter: &AttributionFilter,
+    output: RecordOutput,
+    json: bool,
+) -> Result<()> {
+    // Auto-prune would delete exactly the records `missing` asks to see
+    if !missing {
+        maybe_prune(db, json)?;
+    }
+    let since = range.map(TimeRange::start).transpose()?;
+    let mut records = {
+        let _t = PhaseTimer::start("db query");
+        db.query_file(path, since, filter.package_prefix().as_deref())?
+    };
+    records.retain(|r| filter.matches(r));
+    if missing {
+        let _t = PhaseTimer::start("existence checks");
+        records.retain(|r| !Path::new(&r.path).exists());
+    }
+
+    if emit_records(&records, output, None, json)? {
         return Ok(());
     }
 
+    let mut what = Vec::new();
+    match path {
+        PathMatch::Like("") => {}
+        PathMatch::Like(pattern) => what.push(format!("matching '{}'", pattern)),
+        PathMatch::Regex(re) => what.push(format!("matching /{}/", re.as_str())),
+    }
+    if let Some(since) = since {
+        what.push(format!("created since {}", format_time(since)));
+    }
+    what.extend(filter.describe());
+    if missing {
+        what.push("no longer on disk".to_string());
+    }
+    let what = what.join(" ");
+
     if records.is_empty() {
-        println!("No records found for: {}", pattern);
+        println!("No records found {}", what);
         return Ok(());
     }
 
-    println!("Found {} file(s) matching '{}':\n", records.len(), pattern);
+    println!("Found {} file(s) {}:\n", records.len(), what);
+    let _t = PhaseTimer::start("existence checks + output");
     for record in records {
         display_record(&record, true);
     }
@@ -141,22 +906,109 @@ pub fn query_file(pattern: &str, json: bool) -> Result<()> {
     Ok(())
 }
 
-pub fn query_package(package: &str, json: bool) -> Result<()> {
-    let db = crate::db::Database::new()?;
-    maybe_prune(&db, json)?;
-    let records = db.query_package(package)?;
+/// `package --export-manifest` file; `clean` accepts it on stdin.
+#[derive(Serialize)]
+struct PackageManifest<'a> {
+    /// Unix seconds when the manifest was written
+    exported_at: i64,
+    package: Option<&'a str>,
+    files: &'a [FileRecord],
+}
 
+#[derive(Serialize)]
+struct ExportOutput {
+    manifest: String,
+    records: usize,
+}
+
+fn write_manifest(path: &Path, records: &[FileRecord], package: Option<&str>, json: bool) -> Result<()> {
+    let manifest = PackageManifest {
+        exported_at: Utc::now().timestamp(),
+        package,
+        files: records,
+    };
+    std::fs::write(path, serde_json::to_string_pretty(&manifest)? + "\n")
+        .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))?;
     if json {
-        println!("{}", serde_json::to_string_pretty(&records)?);
+        print_json(&ExportOutput { manifest: path.display().to_string(), records: records.len() })?;
+    } else {
+        match package {
+            Some(package) => println!("Exported {} record(s) for {} to {}", records.len(), package, path.display()),
+            None => println!("Exported {} record(s) to {}", records.len(), path.display()),
+        }
+    }
+    Ok(())
+}
+
+/// Print `records` in every mode except the decorated listing. Returns false
+/// when the caller should print that listing itself.
+fn emit_records(records: &[FileRecord], output: RecordOutput, package: Option<&str>, json: bool) -> Result<bool> {
+    match output {
+        RecordOutput::ExportManifest(path) => write_manifest(path, records, package, json)?,
+        RecordOutput::OwnerCheck => print_owner_check(records, json)?,
+        _ if json => print_json_records(records)?,
+        RecordOutput::Format(format) => print_records(records, format)?,
+        RecordOutput::Pretty => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// Which of a package's files `package` lists, and in what order.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PackageListing {
+    /// `--newest N`
+    pub newest: Option<usize>,
+    /// `--oldest N`
+    pub oldest: Option<usize>,
+    /// `--since` / `--until`
+    pub range: CreatedRange,
+    pub sort: RecordSort,
+}
+
+pub fn query_package(package: &str, listing: PackageListing, output: RecordOutput, json: bool) -> Result<()> {
+    query_package_with(&crate::db::Database::new()?, package, listing, output, json)
+}
+
+/// [`query_package`] against an already-open database.
+pub fn query_package_with(
+    db: &crate::db::Database,
+    package: &str,
+    listing: PackageListing,
+    output: RecordOutput,
+    json: bool,
+) -> Result<()> {
+    maybe_prune(db, json)?;
+    let range = listing.range;
+    let mut records = {
+        let _t = PhaseTimer::start("db query");
+        match (listing.newest, listing.oldest) {
+            (Some(n), _) => db.query_package_by_age(package, true, n, range)?,
+            (None, Some(n)) => db.query_package_by_age(package, false, n, range)?,
+            (None, None) => db.query_package_since(package, range)?,
+        }
+    };
+    listing.sort.apply(&mut records);
+
+    if emit_records(&records, output, Some(package), json)? {
         return Ok(());
     }
 
+    let what = describe_created_range(range).join(" and ");
     if records.is_empty() {
-        println!("No files found for package: {}", package);
+        if range.is_unbounded() {
+            println!("No files found for package: {}", package);
+        } else {
+            println!("No files found for package {} {}", package, what);
+        }
         return Ok(());
     }
 
-    println!("Files created by {} ({} total):\n", package, records.len());
+    if range.is_unbounded() {
+        println!("Files created by {} ({} total):\n", package, records.len());
+    } else {
+        println!("Files {} by {} ({} total):\n", what, package, records.len());
+    }
+    let _t = PhaseTimer::start("existence checks + output");
     for record in records {
         let exists = if Path::new(&record.path).exists() { "✓" } else { "✗" };
         let time = format_time(record.created_at);
@@ -167,161 +1019,1326 @@ pub fn query_package(package: &str, json: bool) -> Result<()> {
             } else {
                 format!("{}", exists.red())
             };
-            println!("{} [{}] {}", time.dimmed(), exists_colored, record.path);
+            println!("{} [{}] {}{}", time.dimmed(), exists_colored, record.path, size_suffix(&record).dimmed());
         } else {
-            println!("{} [{}] {}", time, exists, record.path);
+            println!("{} [{}] {}{}", time, exists, record.path, size_suffix(&record));
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct PackageFileRecord {
+    #[serde(flatten)]
+    record: FileRecord,
+    /// "created" if the package created the file, "accessed" if it only
+    /// accessed it
+    relation: &'static str,
+    /// Every role the package has for the file: "created", "accessed" (it
+    /// made the last access), or both
+    roles: Vec<&'static str>,
+}
+
+/// `package --accessed` / `--both`: files the package last accessed, optionally
+/// together with the files it created, each labeled with how it's related.
+pub fn query_package_accessed(package: &str, include_created: bool, json: bool) -> Result<()> {
+    query_package_accessed_with(&crate::db::Database::new()?, package, include_created, json)
+}
+
+/// [`query_package_accessed`] against an already-open database.
+pub fn query_package_accessed_with(db: &crate::db::Database, package: &str, include_created: bool, json: bool) -> Result<()> {
+    maybe_prune(db, json)?;
+    let records = {
+        let _t = PhaseTimer::start("db query");
+        db.query_package_accessed(package, include_created)?
+    };
+    let records: Vec<PackageFileRecord> = records
+        .into_iter()
+        .map(|record| {
+            let relation = if record.created_by_package == package { "created" } else { "accessed" };
+            let mut roles = Vec::new();
+            if record.created_by_package == package {
+                roles.push("created");
+            }
+            if record.last_accessed_by_package == package {
+                roles.push("accessed");
+            }
+            PackageFileRecord { record, relation, roles }
+        })
+        .collect();
+
+    if json {
+        print_json_records(&records)?;
+        return Ok(());
+    }
+
+    if records.is_empty() {
+        println!("No files found for package: {}", package);
+        return Ok(());
+    }
+
+    let what = if include_created { "created or accessed" } else { "accessed" };
+    println!("Files {} by {} ({} total):\n", what, package, records.len());
+    let _t = PhaseTimer::start("existence checks + output");
+    let color = use_color();
+    for entry in records {
+        let record = &entry.record;
+        let exists = Path::new(&record.path).exists();
+        let mark = if exists { "✓" } else { "✗" };
+        let time = format_time(record.last_accessed_at);
+        // Files the package only accessed show who created them
+        let creator = if entry.relation == "accessed" {
+            format!(" (created by {})", record.created_by_package)
+        } else {
+            String::new()
+        };
+
+        let roles = entry.roles.join("+");
+
+        if color {
+            let mark = if exists { format!("{}", mark.green()) } else { format!("{}", mark.red()) };
+            println!("{} [{}] {:<16} {}{}", time.dimmed(), mark, roles, record.path, creator.dimmed());
+        } else {
+            println!("{} [{}] {:<16} {}{}", time, mark, roles, record.path, creator);
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct ProcessCount {
+    process: String,
+    files: usize,
+}
+
+#[derive(Serialize)]
+struct PackageSummary {
+    package: String,
+    files: usize,
+    existing: usize,
+    processes: Vec<ProcessCount>,
+    first_created_at: Option<i64>,
+    last_created_at: Option<i64>,
+    total_size: u64,
+}
+
+pub fn package_summary(package: &str, json: bool) -> Result<()> {
+    package_summary_with(&crate::db::Database::new()?, package, json)
+}
+
+/// [`package_summary`] against an already-open database.
+pub fn package_summary_with(db: &crate::db::Database, package: &str, json: bool) -> Result<()> {
+    maybe_prune(db, json)?;
+
+    let t = PhaseTimer::start("db query");
+    let records = db.query_package(package)?;
+    let (first_created_at, last_created_at) = db.package_time_range(package)?;
+    let processes = db
+        .package_process_counts(package)?
+        .into_iter()
+        .map(|(process, files)| ProcessCount { process, files })
+        .collect::<Vec<_>>();
+    drop(t);
+
+    let t = PhaseTimer::start("existence checks + sizes");
+    let bar = crate::progress::bar(records.len() as u64, "Computing sizes");
+    let mut existing = 0;
+    let mut total_size = 0;
+    for record in &records {
+        let path = Path::new(&record.path);
+        if path.exists() {
+            existing += 1;
+            total_size += crate::cleanup::get_path_size(path);
+        }
+        bar.inc(1);
+    }
+    bar.finish_and_clear();
+    drop(t);
+
+    let summary = PackageSummary {
+        package: package.to_string(),
+        files: records.len(),
+        existing,
+        processes,
+        first_created_at,
+        last_created_at,
+        total_size,
+    };
+
+    if json {
+        print_json(&summary)?;
+        return Ok(());
+    }
+
+    if summary.files == 0 {
+        println!("No files found for package: {}", package);
+        return Ok(());
+    }
+
+    let color = use_color();
+    if color {
+        println!("{}", package.bold());
+    } else {
+        println!("{}", package);
+    }
+    println!("  Files:     {} ({} on disk)", summary.files, summary.existing);
+    println!("  Size:      {}", format_size(summary.total_size));
+    println!(
+        "  Created:   {} .. {}",
+        format_time(summary.first_created_at.unwrap_or(0)),
+        format_time(summary.last_created_at.unwrap_or(0))
+    );
+    println!("  Processes:");
+    for p in &summary.processes {
+        if color {
+            println!("    {:>6}  {}", p.files, p.process.cyan());
+        } else {
+            println!("    {:>6}  {}", p.files, p.process);
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct InstalledDiff {
+    package: String,
+    /// Tracked paths no package ships: written at runtime
+    generated: Vec<String>,
+    /// Tracked paths this package ships itself
+    package_owned: Vec<String>,
+    /// Tracked paths shipped by some other package
+    other_owned: Vec<OtherOwned>,
+}
+
+#[derive(Serialize)]
+struct OtherOwned {
+    path: String,
+    owners: Vec<String>,
+}
+
+/// `package --diff-installed`: split an installed package's tracked files into
+/// those the package manager ships and those generated at runtime.
+pub fn package_diff_installed(package: &str, json: bool) -> Result<()> {
+    package_diff_installed_with(&crate::db::Database::new()?, package, json)
+}
+
+/// [`package_diff_installed`] against an already-open database.
+pub fn package_diff_installed_with(db: &crate::db::Database, package: &str, json: bool) -> Result<()> {
+    let pm = crate::pkgmgr::PkgMgr::detect()
+        .ok_or_else(|| anyhow::anyhow!("No supported package manager found"))?;
+    let installed = pm.list_installed()?;
+    let package = pm.normalize_package(package);
+    let package = package.as_str();
+    if !installed.contains(package) {
+        anyhow::bail!(
+            "{} is not installed; use `hdas orphans` to review files from removed packages",
+            package
+        );
+    }
+
+    maybe_prune(db, json)?;
+    let records = {
+        let _t = PhaseTimer::start("db query");
+        db.query_package(package)?
+    };
+
+    let mut diff = InstalledDiff {
+        package: package.to_string(),
+        generated: vec![],
+        package_owned: vec![],
+        other_owned: vec![],
+    };
+    {
+        let _t = PhaseTimer::start("ownership lookups");
+        let bar = crate::progress::bar(records.len() as u64, "Querying owners");
+        for record in records {
+            bar.inc(1);
+            let owners = pm.query_owners(&record.path);
+            if owners.is_empty() {
+                diff.generated.push(record.path);
+            } else if owners.iter().any(|o| o == package) {
+                diff.package_owned.push(record.path);
+            } else {
+                diff.other_owned.push(OtherOwned { path: record.path, owners });
+            }
+        }
+        bar.finish_and_clear();
+    }
+
+    if json {
+        print_json(&diff)?;
+        return Ok(());
+    }
+
+    if diff.generated.is_empty() && diff.package_owned.is_empty() && diff.other_owned.is_empty() {
+        println!("No files found for package: {}", package);
+        return Ok(());
+    }
+
+    let color = use_color();
+    let heading = |title: &str, count: usize| {
+        if color {
+            println!("{} ({}):", title.bold(), count);
+        } else {
+            println!("{} ({}):", title, count);
+        }
+    };
+
+    heading("Generated at runtime", diff.generated.len());
+    for path in &diff.generated {
+        println!("  {}", path);
+    }
+    if !diff.package_owned.is_empty() {
+        println!();
+        heading(&format!("Shipped by {}", package), diff.package_owned.len());
+        for path in &diff.package_owned {
+            println!("  {}", path);
+        }
+    }
+    if !diff.other_owned.is_empty() {
+        println!();
+        heading("Shipped by other packages", diff.other_owned.len());
+        for entry in &diff.other_owned {
+            if color {
+                println!("  {} {}", entry.path, format!("({})", entry.owners.join(", ")).dimmed());
+            } else {
+                println!("  {} ({})", entry.path, entry.owners.join(", "));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct ViaParentFile {
+    path: String,
+    process: String,
+}
+
+#[derive(Serialize)]
+struct AttributionBreakdown {
+    package: String,
+    direct: usize,
+    via_parent: usize,
+    /// Records from before the monitor stored how they were attributed
+    not_recorded: usize,
+    via_parent_files: Vec<ViaParentFile>,
+}
+
+/// `package --attribution`: direct vs parent-process attribution counts, and
+/// the parent-attributed files, which are the less certain ones.
+pub fn package_attribution(package: &str, json: bool) -> Result<()> {
+    package_attribution_with(&crate::db::Database::new()?, package, json)
+}
+
+/// [`package_attribution`] against an already-open database.
+pub fn package_attribution_with(db: &crate::db::Database, package: &str, json: bool) -> Result<()> {
+    maybe_prune(db, json)?;
+    let (direct, via_parent, not_recorded) = db.package_attribution_counts(package)?;
+    let via_parent_files = db
+        .package_via_parent_files(package)?
+        .into_iter()
+        .map(|(path, process)| ViaParentFile { path, process })
+        .collect::<Vec<_>>();
+
+    let breakdown = AttributionBreakdown {
+        package: package.to_string(),
+        direct,
+        via_parent,
+        not_recorded,
+        via_parent_files,
+    };
+
+    if json {
+        print_json(&breakdown)?;
+        return Ok(());
+    }
+
+    if direct + via_parent + not_recorded == 0 {
+        println!("No files found for package: {}", package);
+        return Ok(());
+    }
+
+    let color = use_color();
+    if color {
+        println!("{}", package.bold());
+    } else {
+        println!("{}", package);
+    }
+    println!("  Direct:        {}", direct);
+    println!("  Via parent:    {}", via_parent);
+    if not_recorded > 0 {
+        println!("  Not recorded:  {}", not_recorded);
+    }
+
+    if !breakdown.via_parent_files.is_empty() {
+        println!();
+        println!("Attributed via a parent process:");
+        for file in &breakdown.via_parent_files {
+            if color {
+                println!("  {} {}", file.path, format!("(created by {})", file.process).dimmed());
+            } else {
+                println!("  {} (created by {})", file.path, file.process);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct PackageLocation {
+    package: String,
+    directory: Option<String>,
+}
+
+/// Longest common directory of `paths`, component-wise. A lone file path
+/// resolves to its parent. None if the only shared ancestor is `/`.
+fn common_directory<'a>(paths: impl Iterator<Item = &'a str>) -> Option<std::path::PathBuf> {
+    let mut common: Option<Vec<std::path::Component>> = None;
+    for path in paths {
+        let components: Vec<_> = Path::new(path).components().collect();
+        common = Some(match common {
+            None => components,
+            Some(prev) => prev
+                .into_iter()
+                .zip(components)
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a)
+                .collect(),
+        });
+    }
+    let mut dir: std::path::PathBuf = common?.into_iter().collect();
+    if !dir.is_dir() {
+        dir.pop();
+    }
+    dir.parent().is_some().then_some(dir)
+}
+
+/// `package --open`: print the common parent directory of a package's files
+/// and, when run interactively, open it with `xdg-open`.
+pub fn open_package_location(package: &str, json: bool) -> Result<()> {
+    open_package_location_with(&crate::db::Database::new()?, package, json)
+}
+
+/// [`open_package_location`] against an already-open database.
+pub fn open_package_location_with(db: &crate::db::Database, package: &str, json: bool) -> Result<()> {
+    maybe_prune(db, json)?;
+    let records = db.query_package(package)?;
+    let directory = common_directory(records.iter().map(|r| r.path.as_str()));
+
+    if json {
+        let output = PackageLocation {
+            package: package.to_string(),
+            directory: directory.map(|d| d.to_string_lossy().into_owned()),
+        };
+        print_json(&output)?;
+        return Ok(());
+    }
+
+    let Some(directory) = directory else {
+        if records.is_empty() {
+            eprintln!("No files found for package: {}", package);
+        } else {
+            eprintln!("{}'s files share no common directory", package);
+        }
+        return Ok(());
+    };
+
+    println!("{}", directory.display());
+
+    // Only launch a file manager for a human; `cd "$(hdas package x --open)"` just gets the path
+    if std::io::stdout().is_terminal() {
+        if let Err(e) = std::process::Command::new("xdg-open")
+            .arg(&directory)
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn()
+        {
+            eprintln!("Could not run xdg-open: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn query_directory(dir: &str, json: bool) -> Result<()> {
+    query_directory_with(&crate::db::Database::new()?, dir, json)
+}
+
+/// [`query_directory`] against an already-open database.
+pub fn query_directory_with(db: &crate::db::Database, dir: &str, json: bool) -> Result<()> {
+    maybe_prune(db, json)?;
+
+    let expanded = crate::db::expand_user_path(dir)?;
+
+    let records = {
+        let _t = PhaseTimer::start("db query");
+        db.query_directory(&expanded)?
+    };
+
+    if json {
+        print_json_records(&records)?;
+        return Ok(());
+    }
+
+    if records.is_empty() {
+        println!("No files found under: {}", dir);
+        return Ok(());
+    }
+
+    println!("Files under {} ({} total):\n", dir, records.len());
+    let _t = PhaseTimer::start("existence checks + output");
+    for record in records {
+        display_record(&record, true);
+    }
+
+    Ok(())
+}
+
+/// A directory in `hdas tree` output. Chains of directories with a single
+/// child and no record of their own are collapsed into one node whose name
+/// spans them (e.g. `foo/bar`).
+#[derive(Serialize)]
+struct TreeNode {
+    /// Path relative to the parent node (the full path for the root)
+    name: String,
+    path: String,
+    exists: bool,
+    /// Tracked records at or below this node
+    records: usize,
+    /// Present when this path is itself a tracked record
+    #[serde(skip_serializing_if = "Option::is_none")]
+    record: Option<FileRecord>,
+    children: Vec<TreeNode>,
+}
+
+/// Path trie the tree is built from, before chains are collapsed.
+#[derive(Default)]
+struct TreeBuilder {
+    record: Option<FileRecord>,
+    children: std::collections::BTreeMap<String, TreeBuilder>,
+}
+
+impl TreeBuilder {
+    fn insert(&mut self, rest: &str, record: FileRecord) {
+        let mut node = self;
+        for part in rest.split('/').filter(|p| !p.is_empty()) {
+            node = node.children.entry(part.to_string()).or_default();
+        }
+        node.record = Some(record);
+    }
+
+    /// Convert to a [`TreeNode`], folding single-child chains. The root
+    /// (`is_root`) only folds in directories without a record, so a tracked
+    /// path is never swallowed into the header line.
+    fn into_node(self, name: String, path: String, is_root: bool) -> TreeNode {
+        let (mut name, mut path, mut builder) = (name, path, self);
+        while builder.record.is_none()
+            && builder.children.len() == 1
+            && !(is_root && builder.children.values().any(|c| c.record.is_some()))
+        {
+            let (child_name, child) = builder.children.into_iter().next().expect("one child");
+            path = join_tree_path(&path, &child_name);
+            name = join_tree_path(&name, &child_name);
+            builder = child;
+        }
+
+        let children: Vec<TreeNode> = builder
+            .children
+            .into_iter()
+            .map(|(child_name, child)| {
+                let child_path = join_tree_path(&path, &child_name);
+                child.into_node(child_name, child_path, false)
+            })
+            .collect();
+        let records = usize::from(builder.record.is_some()) + children.iter().map(|c| c.records).sum::<usize>();
+        TreeNode {
+            exists: Path::new(&path).exists(),
+            name,
+            path,
+            records,
+            record: builder.record,
+            children,
+        }
+    }
+}
+
+fn join_tree_path(parent: &str, name: &str) -> String {
+    if parent.ends_with('/') {
+        format!("{}{}", parent, name)
+    } else {
+        format!("{}/{}", parent, name)
+    }
+}
+
+/// Nest `records` under `root` (records outside it are dropped).
+fn build_tree(root: &str, records: Vec<FileRecord>) -> TreeNode {
+    let mut trie = TreeBuilder::default();
+    for record in records {
+        let Some(rest) = crate::monitor::strip_path_prefix(&record.path, root) else {
+            continue;
+        };
+        let rest = rest.to_string();
+        trie.insert(&rest, record);
+    }
+    // Directories above the first branch fold into the root line, so a
+    // whole-database tree starts at the home dir rather than at /
+    trie.into_node(String::new(), root.to_string(), true)
+}
+
+pub fn show_tree(dir: Option<&str>, package: Option<&str>, json: bool) -> Result<()> {
+    show_tree_with(&crate::db::Database::new()?, dir, package, json)
+}
+
+/// [`show_tree`] against an already-open database.
+pub fn show_tree_with(db: &crate::db::Database, dir: Option<&str>, package: Option<&str>, json: bool) -> Result<()> {
+    maybe_prune(db, json)?;
+
+    let root = match dir {
+        Some(dir) => {
+            let expanded = crate::db::expand_user_path(dir)?;
+            let trimmed = expanded.trim_end_matches('/');
+            if trimmed.is_empty() { "/".to_string() } else { trimmed.to_string() }
+        }
+        None => "/".to_string(),
+    };
+
+    let records = {
+        let _t = PhaseTimer::start("db query");
+        match package {
+            Some(package) => db.query_package(package)?,
+            None => db.list_all()?,
+        }
+    };
+
+    let _t = PhaseTimer::start("existence checks + output");
+    let mut tree = build_tree(&root, records);
+    // Name the root as the user typed it unless a chain was folded into it
+    tree.name = match dir {
+        Some(dir) if tree.path == root => dir.to_string(),
+        _ => tree.path.clone(),
+    };
+
+    if json {
+        print_json(&tree)?;
+        return Ok(());
+    }
+
+    if tree.records == 0 {
+        match (dir, package) {
+            (Some(dir), Some(package)) => println!("No files from {} found under: {}", package, dir),
+            (Some(dir), None) => println!("No files found under: {}", dir),
+            (None, Some(package)) => println!("No files found for package: {}", package),
+            (None, None) => println!("No files cataloged yet."),
+        }
+        return Ok(());
+    }
+
+    let color = use_color();
+    let show_package = package.is_none();
+    match tree.record {
+        Some(ref record) => print_tree_record_line("", &tree, record, show_package, color),
+        None => {
+            let count = format!("({} {})", tree.records, if tree.records == 1 { "record" } else { "records" });
+            if color {
+                println!("{} {}", tree.name.bold(), count.dimmed());
+            } else {
+                println!("{} {}", tree.name, count);
+            }
+        }
+    }
+    print_tree_children(&tree, "", show_package, color);
+
+    Ok(())
+}
+
+fn print_tree_children(node: &TreeNode, prefix: &str, show_package: bool, color: bool) {
+    for (i, child) in node.children.iter().enumerate() {
+        let last = i + 1 == node.children.len();
+        let branch = format!("{}{}", prefix, if last { "└── " } else { "├── " });
+        match child.record {
+            Some(ref record) => print_tree_record_line(&branch, child, record, show_package, color),
+            None => {
+                let count = format!("({})", child.records);
+                if color {
+                    println!("{}{} {}", branch, format!("{}/", child.name).bold(), count.dimmed());
+                } else {
+                    println!("{}{}/ {}", branch, child.name, count);
+                }
+            }
+        }
+        let child_prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+        print_tree_children(child, &child_prefix, show_package, color);
+    }
+}
+
+/// One tracked record in the tree: existence marker, name, creating package
+/// (unless the tree is for a single package), size, and a count of the
+/// records below it when there are any.
+fn print_tree_record_line(branch: &str, node: &TreeNode, record: &FileRecord, show_package: bool, color: bool) {
+    let marker = if node.exists { "✓" } else { "✗" };
+    let package = if show_package { format!(" ({})", record.created_by_package) } else { String::new() };
+    let below = if node.children.is_empty() { String::new() } else { format!(" ({} below)", node.records - 1) };
+    let name = &node.name;
+    if color {
+        let marker = if node.exists { marker.green().to_string() } else { marker.red().to_string() };
+        println!(
+            "{}[{}] {}{}{}{}",
+            branch,
+            marker,
+            name,
+            package.cyan(),
+            size_suffix(record).dimmed(),
+            below.dimmed()
+        );
+    } else {
+        println!("{}[{}] {}{}{}{}", branch, marker, name, package, size_suffix(record), below);
+    }
+}
+
+/// Files one package created and another last accessed, for `contested`.
+#[derive(Serialize)]
+struct ContestedPair {
+    created_by: String,
+    accessed_by: String,
+    files: Vec<FileRecord>,
+}
+
+pub fn show_contested(json: bool) -> Result<()> {
+    show_contested_with(&crate::db::Database::new()?, json)
+}
+
+/// [`show_contested`] against an already-open database.
+pub fn show_contested_with(db: &crate::db::Database, json: bool) -> Result<()> {
+    maybe_prune(db, json)?;
+
+    let records = {
+        let _t = PhaseTimer::start("db query");
+        db.query_contested()?
+    };
+
+    // Rows arrive ordered by pair, so each pair is one run
+    let mut pairs: Vec<ContestedPair> = Vec::new();
+    for record in records {
+        match pairs.last_mut() {
+            Some(pair)
+                if pair.created_by == record.created_by_package
+                    && pair.accessed_by == record.last_accessed_by_package =>
+            {
+                pair.files.push(record)
+            }
+            _ => pairs.push(ContestedPair {
+                created_by: record.created_by_package.clone(),
+                accessed_by: record.last_accessed_by_package.clone(),
+                files: vec![record],
+            }),
+        }
+    }
+    pairs.sort_by_key(|pair| std::cmp::Reverse(pair.files.len()));
+
+    if json {
+        return print_json(&pairs);
+    }
+
+    if pairs.is_empty() {
+        println!("No files were last accessed by a package other than their creator.");
+        return Ok(());
+    }
+
+    let color = use_color();
+    let _t = PhaseTimer::start("existence checks + output");
+    for (i, pair) in pairs.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        let count = format!("({} {})", pair.files.len(), if pair.files.len() == 1 { "file" } else { "files" });
+        if color {
+            println!("{} → {} {}", pair.created_by.cyan().bold(), pair.accessed_by.cyan().bold(), count.dimmed());
+        } else {
+            println!("{} → {} {}", pair.created_by, pair.accessed_by, count);
+        }
+        for record in &pair.files {
+            display_record(record, true);
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct OrphanPackage {
+    package: String,
+    /// True for the synthetic "unknown" groups from `--include-unknown`,
+    /// which are unattributed files rather than an uninstalled package.
+    unattributed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    process: Option<String>,
+    files: Vec<OrphanFile>,
+    total: usize,
+    existing: usize,
+    deleted: usize,
+}
+
+#[derive(Serialize)]
+struct OrphanFile {
+    path: String,
+    exists: bool,
+}
+
+/// Records grouped for `orphans` output: one group per uninstalled package,
+/// plus (optionally) one unattributed group per creating process.
+struct OrphanGroup {
+    package: String,
+    process: Option<String>,
+    records: Vec<FileRecord>,
+}
+
+pub fn show_orphans(include_unknown: bool, json: bool) -> Result<()> {
+    show_orphans_with(&crate::db::Database::new()?, include_unknown, json)
+}
+
+/// [`show_orphans`] against an already-open database.
+pub fn show_orphans_with(db: &crate::db::Database, include_unknown: bool, json: bool) -> Result<()> {
+    // Auto-recheck orphan attributions before displaying
+    {
+        let _t = PhaseTimer::start("orphan recheck");
+        recheck_orphans(db)?;
+    }
+
+    let t = PhaseTimer::start("db query");
+    let mut groups = Vec::new();
+    for pkg in db.get_orphans()? {
+        let records = db.query_package(&pkg)?;
+        if !records.is_empty() {
+            groups.push(OrphanGroup { package: pkg, process: None, records });
+        }
+    }
+    if include_unknown {
+        let mut by_process: std::collections::BTreeMap<String, Vec<FileRecord>> = std::collections::BTreeMap::new();
+        for record in db.query_package("unknown")? {
+            by_process.entry(record.created_by_process.clone()).or_default().push(record);
+        }
+        for (process, records) in by_process {
+            groups.push(OrphanGroup { package: "unknown".to_string(), process: Some(process), records });
+        }
+    }
+    drop(t);
+
+    if groups.is_empty() {
+        if json {
+            print_json(&[] as &[OrphanPackage])?;
+        } else {
+            println!("No orphaned files found!");
+        }
+        return Ok(());
+    }
+
+    let _t = PhaseTimer::start("existence checks + output");
+    if json {
+        let output: Vec<OrphanPackage> = groups.into_iter().map(|group| {
+            let files: Vec<OrphanFile> = group.records.iter().map(|r| {
+                let exists = Path::new(&r.path).exists();
+                OrphanFile { path: r.path.clone(), exists }
+            }).collect();
+            let existing = files.iter().filter(|f| f.exists).count();
+            let deleted = files.len() - existing;
+            OrphanPackage {
+                total: files.len(),
+                existing,
+                deleted,
+                unattributed: group.process.is_some(),
+                package: group.package,
+                process: group.process,
+                files,
+            }
+        }).collect();
+        print_json(&output)?;
+        return Ok(());
+    }
+
+    let color = use_color();
+    println!("Files from uninstalled packages:\n");
+    for group in groups {
+        let records = group.records;
+        let existing_count = records.iter().filter(|r| Path::new(&r.path).exists()).count();
+        let deleted_count = records.len() - existing_count;
+
+        let label = match &group.process {
+            Some(process) => format!("{} (unattributed, created by {})", group.package, process),
+            None => group.package,
+        };
+        if color {
+            print!("{} ({} file(s)", label.yellow(), records.len());
+        } else {
+            print!("{} ({} file(s)", label, records.len());
+        }
+        if deleted_count > 0 {
+            print!(", {} already deleted", deleted_count);
+        }
+        println!("):");
+
+        for record in records {
+            let exists = Path::new(&record.path).exists();
+            if exists {
+                println!("  {}", record.path);
+            } else {
+                println!("  {} (deleted)", record.path);
+            }
+        }
+        println!();
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct UnknownProcessGroup {
+    process: String,
+    count: usize,
+    files: Vec<FileRecord>,
+}
+
+/// Dump every record to `output` (stdout if `None`) as JSON, JSON Lines, or
+/// CSV. Rows are streamed from the database, so large catalogs aren't held
+/// in memory.
+pub fn export(format: ExportFormat, output: Option<&Path>) -> Result<()> {
+    export_with(&crate::db::Database::new()?, format, output)
+}
+
+/// [`export`] against an already-open database.
+pub fn export_with(db: &crate::db::Database, format: ExportFormat, output: Option<&Path>) -> Result<()> {
+    use std::io::Write;
+    maybe_prune(db, false)?;
+
+    let sink: Box<dyn Write> = match output {
+        Some(path) => Box::new(
+            std::fs::File::create(path)
+                .map_err(|e| anyhow::anyhow!("Could not create {}: {}", path.display(), e))?,
+        ),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    let mut out = std::io::BufWriter::new(sink);
+
+    let count = match format {
+        ExportFormat::Json => {
+            write!(out, "[")?;
+            let mut first = true;
+            let count = db.for_each_record(None, RecordSort::Accessed, |record| {
+                let item = serde_json::to_string_pretty(&record)?;
+                write!(out, "{}\n  {}", if first { "" } else { "," }, item.replace('\n', "\n  "))?;
+                first = false;
+                Ok(())
+            })?;
+            writeln!(out, "{}]", if count == 0 { "" } else { "\n" })?;
+            count
+        }
+        ExportFormat::Jsonl => db.for_each_record(None, RecordSort::Accessed, |record| {
+            serde_json::to_writer(&mut out, &record)?;
+            out.write_all(b"\n")?;
+            Ok(())
+        })?,
+        ExportFormat::Csv => {
+            write!(out, "{}\r\n", CSV_HEADER)?;
+            db.for_each_record(None, RecordSort::Accessed, |record| write_csv_record(&mut out, &record))?
+        }
+    };
+    out.flush()?;
+
+    if let Some(path) = output {
+        println!("Exported {} record(s) to {}", count, path.display());
+    }
+    Ok(())
+}
+
+/// Split CSV text into rows of fields, each with the line it starts on.
+/// Handles RFC 4180 quoting: quoted fields may contain commas, doubled
+/// quotes, and line breaks.
+fn parse_csv(text: &str) -> Result<Vec<(usize, Vec<String>)>> {
+    let mut rows = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let (mut line, mut row_start) = (1, 1);
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                c => {
+                    if c == '\n' {
+                        line += 1;
+                    }
+                    field.push(c);
+                }
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => in_quotes = true,
+            ',' => fields.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                fields.push(std::mem::take(&mut field));
+                rows.push((row_start, std::mem::take(&mut fields)));
+                line += 1;
+                row_start = line;
+            }
+            c => field.push(c),
         }
     }
-
-    Ok(())
+    if in_quotes {
+        anyhow::bail!("line {}: quoted field is never closed", row_start);
+    }
+    if !field.is_empty() || !fields.is_empty() {
+        fields.push(field);
+        rows.push((row_start, fields));
+    }
+    Ok(rows)
 }
 
-pub fn query_directory(dir: &str, json: bool) -> Result<()> {
-    let db = crate::db::Database::new()?;
-    maybe_prune(&db, json)?;
-
-    let home = crate::db::get_user_home();
-    let expanded = if dir.starts_with('/') {
-        dir.to_string()
-    } else if let Some(rest) = dir.strip_prefix("~/") {
-        home.join(rest).to_string_lossy().into_owned()
-    } else if dir == "~" {
-        home.to_string_lossy().into_owned()
-    } else {
-        home.join(dir).to_string_lossy().into_owned()
-    };
+/// Records from an `export --format csv` file. Rows that don't parse are
+/// described in `problems` by line number.
+fn read_csv_export(text: &str, problems: &mut Vec<String>) -> Result<Vec<FileRecord>> {
+    let mut rows = parse_csv(text)?.into_iter();
+    let header: Vec<&str> = CSV_HEADER.split(',').collect();
+    match rows.next() {
+        Some((_, fields)) if fields == header => {}
+        _ => anyhow::bail!("Not an `hdas export --format csv` file: the first line should be\n  {}", CSV_HEADER),
+    }
 
-    let records = db.query_directory(&expanded)?;
+    // Empty cells are unknown timestamps, as written by export
+    let timestamp = |line: usize, column: &str, value: &str, problems: &mut Vec<String>| -> i64 {
+        if value.is_empty() {
+            return 0;
+        }
+        match DateTime::parse_from_rfc3339(value) {
+            Ok(dt) => dt.timestamp(),
+            Err(_) => {
+                problems.push(format!("line {}: {} '{}' is not an RFC 3339 timestamp", line, column, value));
+                0
+            }
+        }
+    };
 
-    if json {
-        println!("{}", serde_json::to_string_pretty(&records)?);
-        return Ok(());
+    let mut records = Vec::new();
+    for (line, fields) in rows {
+        let found = fields.len();
+        let Ok([path, created_by_package, created_by_process, created_at, last_accessed_by_package, last_accessed_by_process, last_accessed_at]) =
+            <[String; 7]>::try_from(fields)
+        else {
+            problems.push(format!("line {}: expected {} fields, found {}", line, header.len(), found));
+            continue;
+        };
+        let created_at = timestamp(line, "created_at", &created_at, problems);
+        let last_accessed_at = timestamp(line, "last_accessed_at", &last_accessed_at, problems);
+        records.push(FileRecord {
+            path,
+            created_by_package,
+            created_by_process,
+            created_at,
+            last_accessed_by_package,
+            last_accessed_by_process,
+            last_accessed_at,
+            // Not part of the CSV columns; measured on the next access
+            size_bytes: None,
+        });
     }
+    Ok(records)
+}
 
-    if records.is_empty() {
-        println!("No files found under: {}", dir);
-        return Ok(());
-    }
+#[derive(Serialize)]
+struct ImportSummary<'a> {
+    file: &'a Path,
+    inserted: usize,
+    updated: usize,
+    skipped: usize,
+}
 
-    println!("Files under {} ({} total):\n", dir, records.len());
-    for record in records {
-        display_record(&record, true);
+/// Merge an `hdas export` file into the database. `format` defaults to the
+/// file's extension. The whole file is checked first: if any row doesn't
+/// parse, nothing is imported and the bad lines are listed.
+pub fn import(file: &Path, format: Option<ExportFormat>, json: bool) -> Result<()> {
+    let format = match format {
+        Some(format) => format,
+        None => match file.extension().and_then(|e| e.to_str()) {
+            Some("csv") => ExportFormat::Csv,
+            Some("jsonl" | "ndjson") => ExportFormat::Jsonl,
+            Some("json") => ExportFormat::Json,
+            _ => anyhow::bail!(
+                "Can't tell the format of {} from its extension; pass --format json, jsonl, or csv",
+                file.display()
+            ),
+        },
+    };
+    let text = std::fs::read_to_string(file)
+        .map_err(|e| anyhow::anyhow!("Could not read {}: {}", file.display(), e))?;
+
+    let mut problems = Vec::new();
+    let records = match format {
+        ExportFormat::Json => match serde_json::from_str::<Vec<FileRecord>>(&text) {
+            Ok(records) => records,
+            Err(e) => {
+                problems.push(format!("line {}: {}", e.line(), e));
+                Vec::new()
+            }
+        },
+        ExportFormat::Jsonl => text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .filter_map(|(i, line)| match serde_json::from_str::<FileRecord>(line) {
+                Ok(record) => Some(record),
+                Err(e) => {
+                    problems.push(format!("line {}: {}", i + 1, e));
+                    None
+                }
+            })
+            .collect(),
+        ExportFormat::Csv => read_csv_export(&text, &mut problems)?,
+    };
+
+    if !problems.is_empty() {
+        const SHOWN: usize = 20;
+        let mut message = format!(
+            "Refusing to import {}: {} problem(s) found, nothing was imported",
+            file.display(),
+            problems.len()
+        );
+        for problem in problems.iter().take(SHOWN) {
+            message.push_str("\n  ");
+            message.push_str(problem);
+        }
+        if problems.len() > SHOWN {
+            message.push_str(&format!("\n  ... and {} more", problems.len() - SHOWN));
+        }
+        anyhow::bail!(message);
     }
 
+    let db = crate::db::Database::new()?;
+    let (inserted, updated, skipped) = db.import_records(&records)?;
+    let summary = ImportSummary { file, inserted, updated, skipped };
+    if json {
+        return print_json(&summary);
+    }
+    println!(
+        "Imported {} record(s) from {}: {} inserted, {} updated, {} skipped (already up to date)",
+        records.len(),
+        file.display(),
+        inserted,
+        updated,
+        skipped
+    );
     Ok(())
 }
 
-#[derive(Serialize)]
-struct OrphanPackage {
-    package: String,
-    files: Vec<OrphanFile>,
-    total: usize,
-    existing: usize,
-    deleted: usize,
+/// `list --created-by PKG`: the same as `package PKG`, except that `unknown`
+/// is grouped by creating process with a hint for tuning the config.
+pub fn list_created_by(
+    package: &str,
+    format: Option<RecordFormat>,
+    range: CreatedRange,
+    sort: RecordSort,
+    json: bool,
+) -> Result<()> {
+    list_created_by_with(&crate::db::Database::new()?, package, format, range, sort, json)
 }
 
-#[derive(Serialize)]
-struct OrphanFile {
-    path: String,
-    exists: bool,
-}
+/// [`list_created_by`] against an already-open database.
+pub fn list_created_by_with(
+    db: &crate::db::Database,
+    package: &str,
+    format: Option<RecordFormat>,
+    range: CreatedRange,
+    sort: RecordSort,
+    json: bool,
+) -> Result<()> {
+    if package != "unknown" {
+        let listing = PackageListing { range, sort, ..Default::default() };
+        return query_package_with(db, package, listing, RecordOutput::from_flags(format, None, false), json);
+    }
 
-pub fn show_orphans(json: bool) -> Result<()> {
-    let db = crate::db::Database::new()?;
-    // Auto-recheck orphan attributions before displaying
-    recheck_orphans(&db)?;
-    let orphans = db.get_orphans()?;
+    maybe_prune(db, json)?;
+    let mut records = {
+        let _t = PhaseTimer::start("db query");
+        db.query_package_since(package, range)?
+    };
+    sort.apply(&mut records);
+    if let (Some(format), false) = (format, json) {
+        return print_records(&records, format);
+    }
 
-    if orphans.is_empty() {
-        if json {
-            println!("[]");
-        } else {
-            println!("No orphaned files found!");
-        }
-        return Ok(());
+    let mut by_process: std::collections::BTreeMap<String, Vec<FileRecord>> = std::collections::BTreeMap::new();
+    for record in records {
+        by_process.entry(record.created_by_process.clone()).or_default().push(record);
     }
+    // Biggest gaps first
+    let mut groups: Vec<UnknownProcessGroup> = by_process
+        .into_iter()
+        .map(|(process, files)| UnknownProcessGroup { process, count: files.len(), files })
+        .collect();
+    groups.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.process.cmp(&b.process)));
 
     if json {
-        let mut output = Vec::new();
-        for pkg in orphans {
-            let records = db.query_package(&pkg)?;
-            if !records.is_empty() {
-                let files: Vec<OrphanFile> = records.iter().map(|r| {
-                    let exists = Path::new(&r.path).exists();
-                    OrphanFile { path: r.path.clone(), exists }
-                }).collect();
-                let existing = files.iter().filter(|f| f.exists).count();
-                let deleted = files.len() - existing;
-                output.push(OrphanPackage {
-                    total: files.len(),
-                    existing,
-                    deleted,
-                    package: pkg,
-                    files,
-                });
-            }
+        print_json_records(&groups)?;
+        return Ok(());
+    }
+
+    if groups.is_empty() {
+        if range.is_unbounded() {
+            println!("No unattributed files: every record has a package.");
+        } else {
+            println!("No unattributed files {}.", describe_created_range(range).join(" and "));
         }
-        println!("{}", serde_json::to_string_pretty(&output)?);
         return Ok(());
     }
 
+    let total: usize = groups.iter().map(|g| g.count).sum();
     let color = use_color();
-    println!("Files from uninstalled packages:\n");
-    for pkg in orphans {
-        let records = db.query_package(&pkg)?;
-        if !records.is_empty() {
-            let existing_count = records.iter().filter(|r| Path::new(&r.path).exists()).count();
-            let deleted_count = records.len() - existing_count;
-
-            if color {
-                print!("{} ({} file(s)", pkg.yellow(), records.len());
+    println!("Unattributed files ({} total, {} process(es)):\n", total, groups.len());
+    let _t = PhaseTimer::start("existence checks + output");
+    for group in &groups {
+        if color {
+            println!("{} ({} file(s)):", group.process.yellow(), group.count);
+        } else {
+            println!("{} ({} file(s)):", group.process, group.count);
+        }
+        for record in &group.files {
+            if Path::new(&record.path).exists() {
+                println!("  {}", record.path);
             } else {
-                print!("{} ({} file(s)", pkg, records.len());
-            }
-            if deleted_count > 0 {
-                print!(", {} already deleted", deleted_count);
+                println!("  {} (deleted)", record.path);
             }
-            println!("):");
-
-            for record in records {
-                let exists = Path::new(&record.path).exists();
-                if exists {
-                    println!("  {}", record.path);
-                } else {
-                    println!("  {} (deleted)", record.path);
-                }
-            }
-            println!();
         }
+        println!();
     }
+    println!(
+        "Hint: add processes that shouldn't own files (editors, file managers) to ignored_processes, \
+         or investigate why their executables aren't owned by a package."
+    );
 
     Ok(())
 }
 
-pub fn list_all(json: bool) -> Result<()> {
-    let db = crate::db::Database::new()?;
-    maybe_prune(&db, json)?;
-    let records = db.list_all()?;
+pub fn list_all(
+    format: Option<RecordFormat>,
+    filter: &AttributionFilter,
+    range: CreatedRange,
+    sort: RecordSort,
+    json: bool,
+) -> Result<()> {
+    list_all_with(&crate::db::Database::new()?, format, filter, range, sort, json)
+}
 
+/// [`list_all`] against an already-open database.
+pub fn list_all_with(
+    db: &crate::db::Database,
+    format: Option<RecordFormat>,
+    filter: &AttributionFilter,
+    range: CreatedRange,
+    sort: RecordSort,
+    json: bool,
+) -> Result<()> {
+    maybe_prune(db, json)?;
+
+    let prefix = filter.package_prefix();
+    let prefix = prefix.as_deref();
+
+    // Stream rows straight to stdout so large catalogs print immediately
+    // and don't need to fit in memory.
+    if json && ndjson() {
+        use std::io::Write;
+        let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+        db.for_each_record_since(prefix, range, sort, |record| {
+            if filter.matches(&record) {
+                serde_json::to_writer(&mut out, &record)?;
+                out.write_all(b"\n")?;
+            }
+            Ok(())
+        })?;
+        out.flush()?;
+        return Ok(());
+    }
     if json {
-        println!("{}", serde_json::to_string_pretty(&records)?);
+        use std::io::Write;
+        // Same envelope as `print_json`, written by hand around the stream
+        let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+        write!(
+            out,
+            "{{\n  \"schema_version\": {},\n  \"command\": {},\n  \"data\": [",
+            JSON_SCHEMA_VERSION,
+            serde_json::to_string(json_command())?
+        )?;
+        let mut first = true;
+        db.for_each_record_since(prefix, range, sort, |record| {
+            if !filter.matches(&record) {
+                return Ok(());
+            }
+            let item = serde_json::to_string_pretty(&record)?;
+            write!(out, "{}\n    {}", if first { "" } else { "," }, item.replace('\n', "\n    "))?;
+            first = false;
+            Ok(())
+        })?;
+        writeln!(out, "{}]\n}}", if first { "" } else { "\n  " })?;
+        return Ok(());
+    }
+    if let Some(format) = format {
+        let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+        db.for_each_record_since(prefix, range, sort, |record| {
+            if filter.matches(&record) {
+                write_record(&mut out, &record, format)?;
+            }
+            Ok(())
+        })?;
+        std::io::Write::flush(&mut out)?;
         return Ok(());
     }
 
-    if records.is_empty() {
+    let total = db.count_files()?;
+    if total == 0 {
         println!("No files cataloged yet. Run 'sudo hdas monitor' to start tracking.");
         return Ok(());
     }
 
-    println!("Cataloged files ({} total):\n", records.len());
-    for record in records {
-        display_record(&record, true);
+    if filter.is_empty() && range.is_unbounded() {
+        println!("Cataloged files ({} total):\n", total);
+        db.for_each_record(None, sort, |record| {
+            display_record(&record, true);
+            Ok(())
+        })?;
+        return Ok(());
+    }
+
+    // The match count isn't known until the end, so it goes in a footer
+    let mut what = filter.describe();
+    what.extend(describe_created_range(range));
+    let what = what.join(" ");
+    println!("Cataloged files {}:\n", what);
+    let mut matched = 0;
+    db.for_each_record_since(prefix, range, sort, |record| {
+        if filter.matches(&record) {
+            display_record(&record, true);
+            matched += 1;
+        }
+        Ok(())
+    })?;
+    if matched == 0 {
+        println!("No records found {}", what);
+    } else {
+        println!("\n{} of {} file(s) matched", matched, total);
     }
 
     Ok(())
 }
 
-
 pub fn show_config() -> Result<()> {
     let path = Config::path();
 
@@ -340,25 +2357,120 @@ pub fn show_config() -> Result<()> {
     Ok(())
 }
 
-pub fn edit_config() -> Result<()> {
+/// Open the config in $EDITOR. A copy of the config is saved to
+/// `config.toml.bak` first when it parses. If the edited file fails to parse,
+/// the editor is reopened on request; otherwise the backup is restored (after
+/// a prompt, unless `force`). Once it parses, fixable problems are offered for
+/// `config validate --fix`. With `validate_only` the editor isn't opened and
+/// only those post-edit checks run against the file as it is.
+pub fn edit_config(force: bool, validate_only: bool) -> Result<()> {
     Config::ensure_exists()?;
 
     let path = Config::path();
+    let backup = path.with_extension("toml.bak");
     let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
 
-    println!("Opening {} with {}...", path.display(), editor);
+    // Under sudo, hand the copies to the real user like the config itself
+    let copy_owned = |from: &Path, to: &Path| -> std::io::Result<()> {
+        std::fs::copy(from, to)?;
+        if let (_, Some(uid), Some(gid)) = crate::db::get_user_info() {
+            let _ = std::os::unix::fs::chown(to, Some(uid), Some(gid));
+        }
+        Ok(())
+    };
+
+    if validate_only {
+        let config = Config::load()?;
+        return offer_config_fixes(config);
+    }
+
+    let has_backup = Config::load().is_ok() && copy_owned(&path, &backup).is_ok();
+
+    loop {
+        println!("Opening {} with {}...", path.display(), editor);
+
+        let status = std::process::Command::new(&editor)
+            .arg(&path)
+            .status()?;
 
-    let status = std::process::Command::new(&editor)
-        .arg(&path)
-        .status()?;
+        if !status.success() {
+            return Ok(());
+        }
 
-    if status.success() {
         match Config::load() {
-            Ok(_) => println!("Configuration updated successfully."),
-            Err(e) => eprintln!("Warning: Config file has errors: {}", e),
+            Ok(config) => {
+                println!("Configuration updated successfully.");
+                return offer_config_fixes(config);
+            }
+            Err(e) => {
+                eprintln!("Warning: Config file has errors: {}", e);
+                if force || !ask_yes_no("Reopen the editor to fix it?", true)? {
+                    break;
+                }
+            }
+        }
+    }
+
+    if !has_backup {
+        return Ok(());
+    }
+
+    if force || ask_yes_no("Restore the previous configuration?", false)? {
+        let broken = path.with_extension("toml.rejected");
+        copy_owned(&path, &broken)?;
+        std::fs::copy(&backup, &path)?;
+        println!("Restored previous configuration; your edit was saved to {}", broken.display());
+    } else {
+        println!("Kept the edited file. The previous version is at {}", backup.display());
+    }
+
+    Ok(())
+}
+
+/// Ask a yes/no question on stdin; an empty answer picks `default`.
+fn ask_yes_no(question: &str, default: bool) -> Result<bool> {
+    use std::io::Write;
+    print!("{} {}: ", question, if default { "[Y/n]" } else { "[y/N]" });
+    std::io::stdout().flush()?;
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input)?;
+    Ok(match input.trim().to_lowercase().as_str() {
+        "" => default,
+        answer => matches!(answer, "y" | "yes"),
+    })
+}
+
+/// After a successful edit: list what `config validate --fix` would change
+/// and apply it on confirmation, then report any problems left over.
+fn offer_config_fixes(mut config: Config) -> Result<()> {
+    let mut fixed = config.clone();
+    let fixes = fixed.fix_problems();
+    if !fixes.is_empty() {
+        println!("\n{} fixable problem(s):", fixes.len());
+        for fix in &fixes {
+            println!("  {}", fix);
+        }
+        if ask_yes_no("Apply these fixes? (rewrites the file without its comments)", false)? {
+            fixed.save()?;
+            config = fixed;
+            println!("Applied {} fix(es).", fixes.len());
         }
     }
 
+    let (errors, warnings) = config_problems(&config);
+    if errors.is_empty() && warnings.is_empty() {
+        if fixes.is_empty() {
+            println!("No problems found.");
+        }
+        return Ok(());
+    }
+    println!("\nRemaining problems (see `hdas config validate`):");
+    for err in &errors {
+        println!("  error: {}", err);
+    }
+    for warn in &warnings {
+        println!("  warning: {}", warn);
+    }
     Ok(())
 }
 
@@ -383,13 +2495,77 @@ pub fn init_config() -> Result<()> {
 #[derive(Serialize)]
 struct ValidationOutput {
     valid: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    fixed: Vec<String>,
     errors: Vec<String>,
     warnings: Vec<String>,
 }
 
-pub fn validate_config(json: bool) -> Result<()> {
-    let config = Config::load()?;
-    let home = crate::db::get_user_home();
+/// Check the config for problems. With `fix`, the mechanical ones
+/// (duplicates, excessive depths) are corrected and the file is rewritten
+/// before checking.
+pub fn validate_config(fix: bool, json: bool) -> Result<()> {
+    let mut config = Config::load()?;
+    let fixed = if fix { config.fix_problems() } else { Vec::new() };
+    if !fixed.is_empty() {
+        config.save()?;
+    }
+    let (errors, warnings) = config_problems(&config);
+    let valid = errors.is_empty();
+
+    if json {
+        let output = ValidationOutput { valid, fixed, errors, warnings };
+        print_json(&output)?;
+        return Ok(());
+    }
+
+    let color = use_color();
+
+    for fix in &fixed {
+        if color {
+            println!("{} {}", "fixed:".green().bold(), fix);
+        } else {
+            println!("fixed: {}", fix);
+        }
+    }
+
+    if errors.is_empty() && warnings.is_empty() {
+        if color {
+            println!("{}", "Configuration is valid.".green());
+        } else {
+            println!("Configuration is valid.");
+        }
+        return Ok(());
+    }
+
+    for err in &errors {
+        if color {
+            println!("{} {}", "error:".red().bold(), err);
+        } else {
+            println!("error: {}", err);
+        }
+    }
+
+    for warn in &warnings {
+        if color {
+            println!("{} {}", "warning:".yellow().bold(), warn);
+        } else {
+            println!("warning: {}", warn);
+        }
+    }
+
+    if valid {
+        println!("\nConfiguration is valid (with warnings).");
+    } else {
+        println!("\nConfiguration has errors.");
+    }
+
+    Ok(())
+}
+
+/// Errors and warnings for a loaded config, shared by `config validate` and `doctor`.
+fn config_problems(config: &Config) -> (Vec<String>, Vec<String>) {
+    let home = crate::db::analysis_home();
 
     let mut errors: Vec<String> = Vec::new();
     let mut warnings: Vec<String> = Vec::new();
@@ -406,6 +2582,28 @@ pub fn validate_config(json: bool) -> Result<()> {
         }
     }
 
+    // Symlinked monitored dirs work, but the kernel-side filter only passes
+    // paths under /etc or a .cache/.config/.local component
+    for (link, target) in config.symlinked_monitored_dirs(&home) {
+        let target_str = target.to_string_lossy();
+        let kernel_visible = target_str.starts_with("/etc/")
+            || [".cache", ".config", ".local"]
+                .iter()
+                .any(|d| target_str.contains(&format!("/{}/", d)) || target_str.ends_with(&format!("/{}", d)));
+        if kernel_visible {
+            warnings.push(format!(
+                "Monitored directory {} is a symlink to {}; files opened through either path are recorded under {}",
+                link.display(), target.display(), link.display()
+            ));
+        } else {
+            warnings.push(format!(
+                "Monitored directory {} is a symlink to {}; only opens through {} are seen, since the target \
+                 isn't under /etc or a .cache/.config/.local directory",
+                link.display(), target.display(), link.display()
+            ));
+        }
+    }
+
     // Check duplicate monitored dirs
     let mut seen_dirs = std::collections::HashSet::new();
     for dir in &config.monitored_dirs {
@@ -414,6 +2612,18 @@ pub fn validate_config(json: bool) -> Result<()> {
         }
     }
 
+    // Check ignored path globs
+    for pattern in &config.ignored_paths {
+        if let Err(e) = glob::Pattern::new(pattern) {
+            errors.push(format!("Invalid ignored_paths pattern '{}': {}", pattern, e));
+        } else if pattern.ends_with('/') {
+            warnings.push(format!(
+                "ignored_paths pattern '{}' ends with '/' and never matches — use '{}**' to skip everything inside",
+                pattern, pattern
+            ));
+        }
+    }
+
     // Check duplicate ignored processes
     let mut seen_procs = std::collections::HashSet::new();
     for proc in &config.ignored_processes {
@@ -433,17 +2643,50 @@ pub fn validate_config(json: bool) -> Result<()> {
     }
 
     // Check tracking depth
-    if config.tracking_depth > 5 {
+    if config.tracking_depth > MAX_USUAL_DEPTH {
         warnings.push(format!(
             "Global tracking_depth={} is unusually high (most users want 1-3)",
             config.tracking_depth
         ));
     }
 
+    // Check for depth 0 on high-churn dirs
+    for dir in config.depth_zero_high_churn_dirs() {
+        warnings.push(format!(
+            "Depth 0 on '{}' records every file individually and can catalog millions of entries — consider depth 1 or 2",
+            dir
+        ));
+    }
+
+    // Check timezone
+    if let Some(ref name) = config.timezone {
+        if let Err(e) = parse_timezone(name) {
+            errors.push(e.to_string());
+        }
+    }
+
+    // Check time format
+    if let Some(ref format) = config.time_format {
+        if !is_valid_time_format(format) {
+            errors.push(format!("Invalid time_format '{}' (not a valid strftime string)", format));
+        }
+    }
+
+    // The database is a file; the directory holding it is created on open
+    if let Some(ref path) = config.db_path {
+        let resolved = crate::db::expand_db_path(path);
+        if resolved.is_dir() {
+            errors.push(format!(
+                "db_path '{}' is a directory; give a file path such as {}",
+                path, resolved.join("attributions.db").display()
+            ));
+        }
+    }
+
     // Check per-dir depths
     for dir in &config.monitored_dirs {
         if let Some(depth) = dir.depth {
-            if depth > 5 {
+            if depth > MAX_USUAL_DEPTH {
                 warnings.push(format!(
                     "Per-directory depth={} for '{}' is unusually high",
                     depth, dir.path
@@ -452,45 +2695,128 @@ pub fn validate_config(json: bool) -> Result<()> {
         }
     }
 
-    let valid = errors.is_empty();
+    (errors, warnings)
+}
 
-    if json {
-        let output = ValidationOutput { valid, errors, warnings };
-        println!("{}", serde_json::to_string_pretty(&output)?);
-        return Ok(());
+#[derive(Serialize)]
+struct ConfigFieldDiff {
+    field: String,
+    /// List fields: entries present only in the current config
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    added: Vec<serde_json::Value>,
+    /// List fields: default entries missing from the current config
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    removed: Vec<serde_json::Value>,
+    /// Scalar fields: the default and current values
+    #[serde(skip_serializing_if = "Option::is_none")]
+    default: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    current: Option<serde_json::Value>,
+}
+
+/// Compare one config field. Lists are compared as sets of entries so that
+/// reordering isn't reported; everything else is compared by value.
+fn diff_config_field(field: &str, default: &serde_json::Value, current: &serde_json::Value) -> Option<ConfigFieldDiff> {
+    use serde_json::Value;
+    if default == current {
+        return None;
+    }
+    let mut diff = ConfigFieldDiff {
+        field: field.to_string(),
+        added: vec![],
+        removed: vec![],
+        default: None,
+        current: None,
+    };
+    match (default, current) {
+        (Value::Array(default), Value::Array(current)) => {
+            diff.added = current.iter().filter(|v| !default.contains(v)).cloned().collect();
+            diff.removed = default.iter().filter(|v| !current.contains(v)).cloned().collect();
+            if diff.added.is_empty() && diff.removed.is_empty() {
+                return None;
+            }
+        }
+        _ => {
+            diff.default = Some(default.clone());
+            diff.current = Some(current.clone());
+        }
     }
+    Some(diff)
+}
 
-    let color = use_color();
+fn display_config_value(value: &serde_json::Value) -> String {
+    use serde_json::Value;
+    match value {
+        Value::Null => "(unset)".to_string(),
+        Value::String(s) => s.clone(),
+        // Monitored dir with a per-dir depth
+        Value::Object(map) if map.contains_key("path") => match map.get("depth") {
+            Some(depth) => format!("{} (depth={})", display_config_value(&map["path"]), depth),
+            None => display_config_value(&map["path"]),
+        },
+        other => other.to_string(),
+    }
+}
 
-    if errors.is_empty() && warnings.is_empty() {
-        if color {
-            println!("{}", "Configuration is valid.".green());
-        } else {
-            println!("Configuration is valid.");
-        }
+/// `config diff`: fields of the current config that differ from the defaults.
+pub fn config_diff(json: bool) -> Result<()> {
+    let current = serde_json::to_value(Config::load()?)?;
+    let default = serde_json::to_value(Config::default())?;
+    let empty = serde_json::Map::new();
+    let current = current.as_object().unwrap_or(&empty);
+    let default = default.as_object().unwrap_or(&empty);
+
+    // Optional fields are omitted when unset, so walk the union of keys
+    let mut fields: Vec<&String> = default.keys().chain(current.keys()).collect();
+    fields.sort();
+    fields.dedup();
+
+    let null = serde_json::Value::Null;
+    let diffs: Vec<ConfigFieldDiff> = fields
+        .into_iter()
+        .filter_map(|field| {
+            diff_config_field(
+                field,
+                default.get(field).unwrap_or(&null),
+                current.get(field).unwrap_or(&null),
+            )
+        })
+        .collect();
+
+    if json {
+        print_json(&diffs)?;
         return Ok(());
     }
 
-    for err in &errors {
-        if color {
-            println!("{} {}", "error:".red().bold(), err);
-        } else {
-            println!("error: {}", err);
-        }
+    if diffs.is_empty() {
+        println!("Configuration matches the defaults.");
+        return Ok(());
     }
 
-    for warn in &warnings {
+    let color = use_color();
+    for diff in &diffs {
         if color {
-            println!("{} {}", "warning:".yellow().bold(), warn);
+            println!("{}:", diff.field.bold());
         } else {
-            println!("warning: {}", warn);
+            println!("{}:", diff.field);
+        }
+        if let (Some(default), Some(current)) = (&diff.default, &diff.current) {
+            println!("  {} -> {}", display_config_value(default), display_config_value(current));
+        }
+        for value in &diff.added {
+            if color {
+                println!("  {} {}", "+".green(), display_config_value(value));
+            } else {
+                println!("  + {}", display_config_value(value));
+            }
+        }
+        for value in &diff.removed {
+            if color {
+                println!("  {} {}", "-".red(), display_config_value(value));
+            } else {
+                println!("  - {}", display_config_value(value));
+            }
         }
-    }
-
-    if valid {
-        println!("\nConfiguration is valid (with warnings).");
-    } else {
-        println!("\nConfiguration has errors.");
     }
 
     Ok(())
@@ -514,8 +2840,194 @@ struct StatusOutput {
     auto_prune: bool,
 }
 
+#[derive(Serialize)]
+struct PackageStat {
+    package: String,
+    files: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    existing: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total_size: Option<u64>,
+}
+
+/// Sizes of `paths`, in order and one per path (None if missing), computed
+/// on all available cores since walking large cache dirs dominates the cost.
+fn path_sizes(paths: &[String]) -> Vec<Option<u64>> {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk = paths.len().div_ceil(threads).max(1);
+    let bar = crate::progress::bar(paths.len() as u64, "Computing sizes");
+    let sizes = std::thread::scope(|scope| {
+        let handles: Vec<_> = paths
+            .chunks(chunk)
+            .map(|chunk| {
+                let bar = bar.clone();
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|p| {
+                            let path = Path::new(p);
+                            let size = path.exists().then(|| crate::cleanup::get_path_size(path));
+                            bar.inc(1);
+                            size
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        // A lost chunk would shift every later size onto the wrong path
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+            .collect()
+    });
+    bar.finish_and_clear();
+    sizes
+}
+
+/// Per-package leaderboard: by record count, or with `by_size` by the disk
+/// space their existing files use (slow: stats every tracked path).
+pub fn show_stats(by_size: bool, top: Option<usize>, json: bool) -> Result<()> {
+    show_stats_with(&crate::db::Database::new()?, by_size, top, json)
+}
+
+/// [`show_stats`] against an already-open database.
+pub fn show_stats_with(db: &crate::db::Database, by_size: bool, top: Option<usize>, json: bool) -> Result<()> {
+    maybe_prune(db, json)?;
+
+    let mut stats: Vec<PackageStat> = if by_size {
+        let mut packages = Vec::new();
+        let mut paths = Vec::new();
+        {
+            let _t = PhaseTimer::start("db query");
+            db.for_each_record(None, RecordSort::Accessed, |record| {
+                packages.push(record.created_by_package);
+                paths.push(record.path);
+                Ok(())
+            })?;
+        }
+        let sizes = {
+            let _t = PhaseTimer::start("existence checks + sizes");
+            path_sizes(&paths)
+        };
+
+        let mut by_package: std::collections::HashMap<String, PackageStat> = std::collections::HashMap::new();
+        for (package, size) in packages.into_iter().zip(sizes) {
+            let stat = by_package.entry(package.clone()).or_insert_with(|| PackageStat {
+                package,
+                files: 0,
+                existing: Some(0),
+                total_size: Some(0),
+            });
+            stat.files += 1;
+            if let Some(size) = size {
+                *stat.existing.get_or_insert(0) += 1;
+                *stat.total_size.get_or_insert(0) += size;
+            }
+        }
+        let mut stats: Vec<_> = by_package.into_values().collect();
+        stats.sort_by(|a, b| b.total_size.cmp(&a.total_size).then_with(|| a.package.cmp(&b.package)));
+        stats
+    } else {
+        let _t = PhaseTimer::start("db query");
+        db.package_file_counts()?
+            .into_iter()
+            .map(|(package, files)| PackageStat { package, files, existing: None, total_size: None })
+            .collect()
+    };
+    if let Some(top) = top {
+        stats.truncate(top);
+    }
+
+    if json {
+        print_json(&stats)?;
+        return Ok(());
+    }
+
+    if stats.is_empty() {
+        println!("No files cataloged yet. Run 'sudo hdas monitor' to start tracking.");
+        return Ok(());
+    }
+
+    let color = use_color();
+    if by_size {
+        println!("{:>9}  {:>7}  PACKAGE", "SIZE", "FILES");
+    } else {
+        println!("{:>7}  PACKAGE", "FILES");
+    }
+    for stat in &stats {
+        let package = if color { stat.package.cyan().to_string() } else { stat.package.clone() };
+        match stat.total_size {
+            Some(size) => println!(
+                "{:>9}  {:>7}  {}",
+                format_size(size),
+                format!("{}/{}", stat.existing.unwrap_or(0), stat.files),
+                package
+            ),
+            None => println!("{:>7}  {}", stat.files, package),
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct TopPackage {
+    package: String,
+    files: usize,
+    /// Sum of the sizes recorded when the files were cataloged
+    total_size: u64,
+}
+
+/// Package leaderboard from the database alone: record count and recorded
+/// size per package, without touching the filesystem (unlike
+/// `stats --packages-by-size`). `limit` 0 shows every package.
+pub fn show_top(limit: usize, by_size: bool, json: bool) -> Result<()> {
+    show_top_with(&crate::db::Database::new()?, limit, by_size, json)
+}
+
+/// [`show_top`] against an already-open database.
+pub fn show_top_with(db: &crate::db::Database, limit: usize, by_size: bool, json: bool) -> Result<()> {
+    maybe_prune(db, json)?;
+
+    let mut packages: Vec<TopPackage> = {
+        let _t = PhaseTimer::start("db query");
+        db.package_stats()?
+            .into_iter()
+            .map(|(package, files, total_size)| TopPackage { package, files, total_size })
+            .collect()
+    };
+    if by_size {
+        packages.sort_by(|a, b| b.total_size.cmp(&a.total_size).then_with(|| a.package.cmp(&b.package)));
+    }
+    if limit > 0 {
+        packages.truncate(limit);
+    }
+
+    if json {
+        return print_json(&packages);
+    }
+
+    if packages.is_empty() {
+        println!("No files cataloged yet. Run 'sudo hdas monitor' to start tracking.");
+        return Ok(());
+    }
+
+    let color = use_color();
+    println!("{:>7}  {:>9}  PACKAGE", "FILES", "SIZE");
+    for top in &packages {
+        let package = if color { top.package.cyan().to_string() } else { top.package.clone() };
+        println!("{:>7}  {:>9}  {}", top.files, format_size(top.total_size), package);
+    }
+
+    Ok(())
+}
+
 pub fn show_status(json: bool) -> Result<()> {
-    let db = crate::db::Database::new()?;
+    show_status_with(&crate::db::Database::new()?, json)
+}
+
+/// [`show_status`] against an already-open database.
+pub fn show_status_with(db: &crate::db::Database, json: bool) -> Result<()> {
     let (files, packages, db_path_str) = db.get_stats()?;
     let last_event = db.get_last_event_time()?;
     let config_path = Config::path();
@@ -567,7 +3079,7 @@ pub fn show_status(json: bool) -> Result<()> {
             ignored_processes_count: config.ignored_processes.len(),
             auto_prune: config.auto_prune,
         };
-        println!("{}", serde_json::to_string_pretty(&output)?);
+        print_json(&output)?;
         return Ok(());
     }
 
@@ -626,6 +3138,288 @@ pub fn show_status(json: bool) -> Result<()> {
     Ok(())
 }
 
+#[derive(Serialize)]
+struct VersionOutput {
+    version: String,
+    schema_version: i32,
+    package_manager: Option<String>,
+    kernel: Option<String>,
+    btf_available: bool,
+}
+
+pub fn show_version(json: bool) -> Result<()> {
+    show_version_with(&crate::db::Database::new()?, json)
+}
+
+/// [`show_version`] against an already-open database.
+pub fn show_version_with(db: &crate::db::Database, json: bool) -> Result<()> {
+    let schema_version = db.schema_version()?;
+    let package_manager = crate::pkgmgr::PkgMgr::detect().map(|pm| pm.name().to_string());
+    let kernel = std::fs::read_to_string("/proc/sys/kernel/osrelease")
+        .ok()
+        .map(|s| s.trim().to_string());
+    let btf_available = Path::new("/sys/kernel/btf/vmlinux").exists();
+
+    if json {
+        let output = VersionOutput {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            schema_version,
+            package_manager,
+            kernel,
+            btf_available,
+        };
+        print_json(&output)?;
+        return Ok(());
+    }
+
+    println!("hdas {}", env!("CARGO_PKG_VERSION"));
+    println!("Schema version: {}", schema_version);
+    println!("Package manager: {}", package_manager.as_deref().unwrap_or("(none detected)"));
+    println!("Kernel: {}", kernel.as_deref().unwrap_or("(unknown)"));
+    println!("BTF: {}", if btf_available { "available" } else { "not available" });
+
+    Ok(())
+}
+
+/// Report the database schema version and run any pending migrations.
+pub fn migrate_db(dry_run: bool, json: bool) -> Result<()> {
+    let summary = crate::db::Database::run_migrations(dry_run)?;
+
+    if json {
+        print_json(&summary)?;
+        return Ok(());
+    }
+
+    println!("Database: {}", summary.path.display());
+    println!("Schema version: {} (this hdas: {})", summary.from_version, crate::db::SCHEMA_VERSION);
+
+    if summary.steps.is_empty() {
+        println!("Already up to date; nothing to migrate.");
+        return Ok(());
+    }
+
+    let verb = if dry_run { "Would apply" } else { "Applied" };
+    println!("{} {} migration(s):", verb, summary.steps.len());
+    for step in &summary.steps {
+        println!("  {} -> {}: {}", step.version - 1, step.version, step.description);
+    }
+    if dry_run {
+        println!("(dry run - database not modified)");
+    } else {
+        println!("Migrated from version {} to {}.", summary.from_version, summary.to_version);
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum CheckStatus {
+    Ok,
+    Warn,
+    Fail,
+}
+
+#[derive(Serialize)]
+struct DoctorCheck {
+    name: &'static str,
+    status: CheckStatus,
+    detail: String,
+}
+
+#[derive(Serialize)]
+struct DoctorOutput {
+    healthy: bool,
+    checks: Vec<DoctorCheck>,
+}
+
+/// Where distributions and `make install` put the service template.
+const SERVICE_UNIT_DIRS: &[&str] = &[
+    "/etc/systemd/system",
+    "/usr/lib/systemd/system",
+    "/lib/systemd/system",
+];
+
+/// Where tracefs may be mounted.
+const TRACEFS_DIRS: &[&str] = &["/sys/kernel/tracing", "/sys/kernel/debug/tracing"];
+
+/// Tracepoints the BPF programs attach to.
+const SYSCALL_TRACEPOINTS: &[&str] = &[
+    "sys_enter_openat",
+    "sys_exit_openat",
+    "sys_enter_unlinkat",
+    "sys_exit_unlinkat",
+    "sys_enter_renameat2",
+    "sys_exit_renameat2",
+];
+
+/// Run every environment check and print a checklist. Returns `false` when a
+/// critical check failed so the caller can exit non-zero.
+pub fn doctor(json: bool) -> Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut checks = Vec::new();
+    let mut check = |name: &'static str, status: CheckStatus, detail: String| {
+        checks.push(DoctorCheck { name, status, detail });
+    };
+
+    // Database: open (creates and migrates if needed), then probe write access
+    // and ownership directly since the monitor writes as root on the user's behalf
+    let (_, uid, _) = crate::db::get_user_info();
+    let expected_uid = uid.unwrap_or_else(|| nix::unistd::Uid::effective().as_raw());
+    match crate::db::db_path() {
+        Ok(db_path) => match crate::db::Database::new().and_then(|db| db.schema_version()) {
+            Ok(version) => {
+                check("database", CheckStatus::Ok, format!("{} (schema {})", db_path.display(), version));
+                match std::fs::OpenOptions::new().write(true).open(&db_path) {
+                    Ok(_) => check("database writable", CheckStatus::Ok, "yes".to_string()),
+                    Err(e) => check("database writable", CheckStatus::Fail, e.to_string()),
+                }
+                match db_path.metadata() {
+                    Ok(meta) if meta.uid() == expected_uid => {
+                        check("database owner", CheckStatus::Ok, format!("uid {}", meta.uid()));
+                    }
+                    Ok(meta) => check(
+                        "database owner",
+                        CheckStatus::Fail,
+                        format!("owned by uid {}, expected {} (fix with chown)", meta.uid(), expected_uid),
+                    ),
+                    Err(e) => check("database owner", CheckStatus::Fail, e.to_string()),
+                }
+            }
+            Err(e) => check("database", CheckStatus::Fail, format!("{}: {}", db_path.display(), e)),
+        },
+        Err(e) => check("database", CheckStatus::Fail, e.to_string()),
+    }
+
+    // Config: parse, then the same checks as `config validate`
+    match Config::load() {
+        Ok(config) => {
+            let (errors, warnings) = config_problems(&config);
+            if let Some(err) = errors.first() {
+                check("config", CheckStatus::Fail, format!("{} error(s), first: {}", errors.len(), err));
+            } else if let Some(warn) = warnings.first() {
+                check("config", CheckStatus::Warn, format!("{} warning(s), first: {}", warnings.len(), warn));
+            } else {
+                check("config", CheckStatus::Ok, Config::path().display().to_string());
+            }
+        }
+        Err(e) => check("config", CheckStatus::Fail, e.to_string()),
+    }
+
+    // Package manager, plus one real ownership lookup against a file every
+    // distribution ships from a package (coreutils)
+    match crate::pkgmgr::PkgMgr::detect() {
+        Some(pm) => {
+            check("package manager", CheckStatus::Ok, pm.name().to_string());
+            let sample = "/usr/bin/env";
+            match pm.query_owner(sample) {
+                Some(owner) => check("owner lookup", CheckStatus::Ok, format!("{} -> {}", sample, owner)),
+                None => check(
+                    "owner lookup",
+                    CheckStatus::Warn,
+                    format!("{} returned no owner for {}", pm.name(), sample),
+                ),
+            }
+        }
+        None => check("package manager", CheckStatus::Fail, "none detected".to_string()),
+    }
+
+    // BPF prerequisites, checked without loading anything
+    match std::fs::read_to_string("/proc/sys/kernel/osrelease") {
+        Ok(release) => check("kernel", CheckStatus::Ok, release.trim().to_string()),
+        Err(e) => check("kernel", CheckStatus::Warn, format!("unknown ({})", e)),
+    }
+    if Path::new("/sys/kernel/btf/vmlinux").exists() {
+        check("BTF", CheckStatus::Ok, "available".to_string());
+    } else {
+        check("BTF", CheckStatus::Warn, "/sys/kernel/btf/vmlinux not found".to_string());
+    }
+    let tracepoint_lookups = SYSCALL_TRACEPOINTS.iter().map(|name| {
+        let found = TRACEFS_DIRS
+            .iter()
+            .map(|dir| Path::new(dir).join("events/syscalls").join(name).try_exists())
+            .collect::<Vec<_>>();
+        (*name, found)
+    });
+    let mut missing = Vec::new();
+    let mut uninspectable = false;
+    for (name, found) in tracepoint_lookups {
+        if !found.iter().any(|r| matches!(r, Ok(true))) {
+            uninspectable |= found.iter().any(|r| r.is_err());
+            missing.push(name);
+        }
+    }
+    if missing.is_empty() {
+        check("syscall tracepoints", CheckStatus::Ok, SYSCALL_TRACEPOINTS.join(", ") + " present");
+    } else if uninspectable {
+        // tracefs is usually root-only
+        check("syscall tracepoints", CheckStatus::Warn, "cannot inspect tracefs (run as root)".to_string());
+    } else {
+        check("syscall tracepoints", CheckStatus::Fail, format!("{} not found in tracefs", missing.join(", ")));
+    }
+
+    // systemd service: template installed, and the instance for this user active
+    let unit = SERVICE_UNIT_DIRS
+        .iter()
+        .map(|dir| Path::new(dir).join("hdas@.service"))
+        .find(|p| p.exists());
+    match unit {
+        Some(unit) => check("service unit", CheckStatus::Ok, unit.display().to_string()),
+        None => check("service unit", CheckStatus::Warn, "hdas@.service not installed".to_string()),
+    }
+    let user = nix::unistd::User::from_uid(nix::unistd::Uid::from_raw(expected_uid))
+        .ok()
+        .flatten()
+        .map(|u| u.name)
+        .unwrap_or_default();
+    let instance = format!("hdas@{}", user);
+    match std::process::Command::new("systemctl")
+        .args(["is-active", &instance])
+        .stderr(std::process::Stdio::null())
+        .output()
+    {
+        Ok(out) => {
+            let mut state = String::from_utf8_lossy(&out.stdout).trim().to_string();
+            if state.is_empty() {
+                state = "unknown (is systemd running?)".to_string();
+            }
+            let status = if out.status.success() { CheckStatus::Ok } else { CheckStatus::Warn };
+            check("service", status, format!("{} is {}", instance, state));
+        }
+        Err(_) => check("service", CheckStatus::Warn, "systemctl not available".to_string()),
+    }
+
+    let healthy = checks.iter().all(|c| c.status != CheckStatus::Fail);
+
+    if json {
+        print_json(&DoctorOutput { healthy, checks })?;
+        return Ok(healthy);
+    }
+
+    let color = use_color();
+    for c in &checks {
+        let mark = match (c.status, color) {
+            (CheckStatus::Ok, true) => "✓".green().to_string(),
+            (CheckStatus::Warn, true) => "!".yellow().to_string(),
+            (CheckStatus::Fail, true) => "✗".red().to_string(),
+            (CheckStatus::Ok, false) => "✓".to_string(),
+            (CheckStatus::Warn, false) => "!".to_string(),
+            (CheckStatus::Fail, false) => "✗".to_string(),
+        };
+        println!("{} {:<18} {}", mark, c.name, c.detail);
+    }
+
+    println!();
+    if healthy {
+        println!("No critical problems found.");
+    } else {
+        println!("Critical problems found.");
+    }
+
+    Ok(healthy)
+}
+
 #[derive(Serialize)]
 struct ExplainOutput {
     input_path: String,
@@ -634,22 +3428,25 @@ struct ExplainOutput {
     monitored: bool,
     matched_dir: Option<String>,
     depth_used: Option<u32>,
+    /// Every monitored dir that matches, in priority order (only with `--all`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    candidates: Option<Vec<ExplainCandidate>>,
+}
+
+#[derive(Serialize)]
+struct ExplainCandidate {
+    dir: String,
+    depth: u32,
+    tracked_path: Option<String>,
+    winner: bool,
 }
 
-pub fn explain_path(path: &str, json: bool) -> Result<()> {
+pub fn explain_path(path: &str, all: bool, json: bool) -> Result<()> {
     let config = Config::load()?;
-    let home = crate::db::get_user_home();
+    let home = crate::db::analysis_home();
 
     // Expand the path
-    let expanded = if path.starts_with('/') {
-        path.to_string()
-    } else if let Some(rest) = path.strip_prefix("~/") {
-        home.join(rest).to_string_lossy().into_owned()
-    } else if path == "~" {
-        home.to_string_lossy().into_owned()
-    } else {
-        home.join(path).to_string_lossy().into_owned()
-    };
+    let expanded = crate::db::expand_user_path(path)?;
 
     // Normalize: ensure paths like ~/.config get a trailing slash so they match
     let expanded_norm = if !expanded.ends_with('/') && std::path::Path::new(&expanded).is_dir() {
@@ -665,8 +3462,30 @@ pub fn explain_path(path: &str, json: bool) -> Result<()> {
         config.tracking_depth,
     );
 
-    // Figure out which dir matched and what depth was used
-    let (matched_dir, depth_used) = find_matching_dir(&expanded_norm, &home, &config);
+    // Figure out which dirs match and what depth each uses; the first one wins
+    let matches = find_matching_dirs(&expanded_norm, &home, &config);
+    let (matched_dir, depth_used) = match matches.first() {
+        Some((dir, depth)) => (Some(dir.path.clone()), Some(*depth)),
+        None => (None, None),
+    };
+    let candidates = all.then(|| {
+        matches
+            .iter()
+            .enumerate()
+            .map(|(i, (dir, depth))| ExplainCandidate {
+                dir: dir.path.clone(),
+                depth: *depth,
+                // Tracked path as if this were the only monitored dir
+                tracked_path: crate::monitor::get_tracked_path(
+                    &expanded_norm,
+                    &home,
+                    std::slice::from_ref(*dir),
+                    config.tracking_depth,
+                ),
+                winner: i == 0,
+            })
+            .collect::<Vec<_>>()
+    });
 
     if json {
         let output = ExplainOutput {
@@ -676,8 +3495,9 @@ pub fn explain_path(path: &str, json: bool) -> Result<()> {
             monitored: tracked.is_some(),
             matched_dir,
             depth_used,
+            candidates,
         };
-        println!("{}", serde_json::to_string_pretty(&output)?);
+        print_json(&output)?;
         return Ok(());
     }
 
@@ -709,23 +3529,41 @@ pub fn explain_path(path: &str, json: bool) -> Result<()> {
         }
     }
 
+    if let Some(candidates) = candidates {
+        if !candidates.is_empty() {
+            println!("\nCandidates (in priority order):");
+        }
+        for c in &candidates {
+            let marker = if c.winner { "*" } else { " " };
+            let tracked = c.tracked_path.as_deref().unwrap_or("-");
+            if color && c.winner {
+                println!("  {} {} (depth {}) -> {}", marker.green(), c.dir.bold(), c.depth, tracked);
+            } else {
+                println!("  {} {} (depth {}) -> {}", marker, c.dir, c.depth, tracked);
+            }
+        }
+    }
+
     Ok(())
 }
 
-fn find_matching_dir(
+/// Every monitored dir that covers `expanded`, with the depth it applies, in
+/// the order `get_tracked_path` tries them (absolute dirs first). The first
+/// entry is the one that wins.
+fn find_matching_dirs<'a>(
     expanded: &str,
     home: &std::path::Path,
-    config: &Config,
-) -> (Option<String>, Option<u32>) {
+    config: &'a Config,
+) -> Vec<(&'a crate::config::MonitoredDir, u32)> {
     let home_str = home.to_string_lossy();
+    let mut matches = Vec::new();
 
     // Check absolute dirs first
     for dir in &config.monitored_dirs {
         if dir.path.starts_with('/') {
             let base = dir.path.trim_end_matches('/');
             if expanded.starts_with(base) && (expanded.len() == base.len() || expanded[base.len()..].starts_with('/')) {
-                let depth = dir.depth.unwrap_or(config.tracking_depth);
-                return (Some(dir.path.clone()), Some(depth));
+                matches.push((dir, dir.depth.unwrap_or(config.tracking_depth)));
             }
         }
     }
@@ -740,18 +3578,28 @@ fn find_matching_dir(
             let dir_name = dir.path.trim_start_matches('.');
             let prefix = format!(".{}/", dir_name);
             if relative.starts_with(&prefix) {
-                let depth = dir.depth.unwrap_or(config.tracking_depth);
-                return (Some(dir.path.clone()), Some(depth));
+                matches.push((dir, dir.depth.unwrap_or(config.tracking_depth)));
             }
         }
     }
 
-    (None, None)
+    matches
+}
+
+#[derive(Serialize)]
+struct Reassignment {
+    path: String,
+    old_package: String,
+    new_package: String,
+    /// Other packages that also claim the path; non-empty means the
+    /// attribution is ambiguous.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    other_owners: Vec<String>,
 }
 
 /// Re-check orphan files against the package manager and fix misattributions.
 /// Returns (reassigned, removed) counts.
-fn recheck_orphans(db: &crate::db::Database) -> Result<(Vec<(String, String, String)>, usize)> {
+fn recheck_orphans(db: &crate::db::Database) -> Result<(Vec<Reassignment>, usize)> {
     let pm = crate::pkgmgr::PkgMgr::detect()
         .ok_or_else(|| anyhow::anyhow!("No supported package manager found"))?;
 
@@ -762,14 +3610,20 @@ fn recheck_orphans(db: &crate::db::Database) -> Result<(Vec<(String, String, Str
 
     let records = db.get_files_for_packages(&orphans)?;
 
-    let mut reassigned: Vec<(String, String, String)> = Vec::new();
+    let mut reassigned: Vec<Reassignment> = Vec::new();
     let mut removed = 0usize;
 
     for record in &records {
-        if let Some(owner) = pm.query_owner(&record.path) {
+        let mut owners = pm.query_owners(&record.path).into_iter();
+        if let Some(owner) = owners.next() {
             if owner != record.created_by_package {
                 db.reassign_file(&record.path, &owner)?;
-                reassigned.push((record.path.clone(), record.created_by_package.clone(), owner));
+                reassigned.push(Reassignment {
+                    path: record.path.clone(),
+                    old_package: record.created_by_package.clone(),
+                    new_package: owner,
+                    other_owners: owners.collect(),
+                });
             }
         } else if !Path::new(&record.path).exists() {
             db.delete_file_records(&[record.path.clone()])?;
@@ -778,13 +3632,102 @@ fn recheck_orphans(db: &crate::db::Database) -> Result<(Vec<(String, String, Str
             // Path still exists but no installed package claims it.
             // Relabel as "unknown" so it no longer appears as an orphan.
             db.reassign_file(&record.path, "unknown")?;
-            reassigned.push((record.path.clone(), record.created_by_package.clone(), "unknown".to_string()));
+            reassigned.push(Reassignment {
+                path: record.path.clone(),
+                old_package: record.created_by_package.clone(),
+                new_package: "unknown".to_string(),
+                other_owners: vec![],
+            });
         }
     }
 
     Ok((reassigned, removed))
 }
 
+#[derive(Serialize)]
+struct VerifyResult {
+    dry_run: bool,
+    /// Unattributed records whose path still exists, each asked about
+    checked: usize,
+    /// Unattributed records whose path is gone, skipped
+    missing: usize,
+    reattributed: Vec<Reassignment>,
+}
+
+/// `verify`: ask the package manager who owns each unattributed ("unknown")
+/// record's path and make that package its creator. Paths that no longer
+/// exist are skipped; they're for `prune`.
+pub fn verify(dry_run: bool, json: bool) -> Result<()> {
+    verify_with(&crate::db::Database::new()?, dry_run, json)
+}
+
+/// [`verify`] against an already-open database.
+pub fn verify_with(db: &crate::db::Database, dry_run: bool, json: bool) -> Result<()> {
+    let pm = crate::pkgmgr::PkgMgr::detect()
+        .ok_or_else(|| anyhow::anyhow!("No supported package manager found"))?;
+    let paths = db.list_unknown()?;
+
+    let (mut checked, mut missing) = (0, 0);
+    let mut reattributed = Vec::new();
+    let bar = crate::progress::bar(paths.len() as u64, "Querying owners");
+    for path in paths {
+        bar.inc(1);
+        if !Path::new(&path).exists() {
+            missing += 1;
+            continue;
+        }
+        checked += 1;
+        let mut owners = pm.query_owners(&path).into_iter();
+        let Some(owner) = owners.next() else {
+            continue;
+        };
+        if !dry_run && !db.update_creator(&path, &owner)? {
+            continue;
+        }
+        reattributed.push(Reassignment {
+            path,
+            old_package: "unknown".to_string(),
+            new_package: owner,
+            other_owners: owners.collect(),
+        });
+    }
+    bar.finish_and_clear();
+
+    if json {
+        return print_json(&VerifyResult { dry_run, checked, missing, reattributed });
+    }
+
+    if checked + missing == 0 {
+        println!("No unattributed records to verify.");
+        return Ok(());
+    }
+
+    let color = use_color();
+    let verb = if dry_run { "Would re-attribute" } else { "Re-attributed" };
+    println!("{} {} of {} unattributed record(s){}", verb, reattributed.len(), checked,
+        if reattributed.is_empty() { "." } else { ":" });
+    for r in &reattributed {
+        let ambiguity = if r.other_owners.is_empty() {
+            String::new()
+        } else {
+            format!(" (ambiguous: also owned by {})", r.other_owners.join(", "))
+        };
+        if color {
+            println!("  {} -> {}{}", r.path, r.new_package.green(), ambiguity.yellow());
+        } else {
+            println!("  {} -> {}{}", r.path, r.new_package, ambiguity);
+        }
+    }
+    if missing > 0 {
+        println!("Skipped {} record(s) whose files no longer exist (`hdas prune` removes them).", missing);
+    }
+    if dry_run {
+        println!("(dry run - database not modified)");
+    }
+
+    Ok(())
+}
+
 pub fn forget_package_cmd(package: &str) -> Result<()> {
     let db = crate::db::Database::new()?;
     let removed = db.forget_package(package)?;
@@ -799,6 +3742,127 @@ pub fn forget_package_cmd(package: &str) -> Result<()> {
     Ok(())
 }
 
+/// Whether a `forget` argument names a path rather than a package: package
+/// names never contain '/', so anything with one (or starting with `~`) is
+/// a path.
+pub fn is_forget_path(target: &str) -> bool {
+    target.contains('/') || target.starts_with('~')
+}
+
+#[derive(Serialize)]
+struct ForgetPathResult {
+    pattern: String,
+    forgotten: Vec<String>,
+    records_removed: usize,
+}
+
+/// `forget PATH`: drop the record for one path, leaving the file alone. With
+/// `glob`, `path` is a shell-style pattern (`*` and `?` stay within one path
+/// component); the matching records are listed and confirmed (unless
+/// `force`) before they're dropped.
+pub fn forget_path_cmd(path: &str, glob: bool, force: bool, json: bool) -> Result<()> {
+    let db = crate::db::Database::new()?;
+    let expanded = crate::db::expand_user_path(path)?;
+    let expanded = match expanded.trim_end_matches('/') {
+        "" => "/".to_string(),
+        trimmed => trimmed.to_string(),
+    };
+
+    let matched: Vec<FileRecord> = if glob {
+        let pattern = glob::Pattern::new(&expanded)
+            .map_err(|e| anyhow::anyhow!("Invalid glob '{}': {}", path, e))?;
+        let options = glob::MatchOptions { require_literal_separator: true, ..Default::default() };
+        // Only records under the pattern's literal leading directory can match
+        let literal = &expanded[..expanded.find(['*', '?', '[']).unwrap_or(expanded.len())];
+        let base = &literal[..literal.rfind('/').unwrap_or(0)];
+        db.query_directory(base)?
+            .into_iter()
+            .filter(|r| pattern.matches_with(&r.path, options))
+            .collect()
+    } else {
+        db.get_record(&expanded)?.into_iter().collect()
+    };
+
+    if matched.is_empty() {
+        if json {
+            return print_json(&ForgetPathResult { pattern: expanded, forgotten: vec![], records_removed: 0 });
+        }
+        println!("No records found matching: {}", path);
+        return Ok(());
+    }
+
+    if glob && !json {
+        println!("Will forget {} record(s) (files are not deleted):\n", matched.len());
+        for record in &matched {
+            display_record(record, true);
+        }
+        println!();
+        if !force && !crate::cleanup::confirm_prompt(false, None)? {
+            println!("Aborted.");
+            return Ok(());
+        }
+    }
+
+    let forgotten: Vec<String> = matched.into_iter().map(|r| r.path).collect();
+    let records_removed = db.delete_file_records(&forgotten)?;
+    if json {
+        return print_json(&ForgetPathResult { pattern: expanded, forgotten, records_removed });
+    }
+    match forgotten.as_slice() {
+        [only] if !glob => println!("Forgot the record for {}. The file was not deleted.", only),
+        _ => println!("Removed {} record(s). Files were not deleted.", records_removed),
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct MergeResult {
+    old: String,
+    new: String,
+    dry_run: bool,
+    created_records: usize,
+    accessed_records: usize,
+    rows_updated: usize,
+}
+
+/// `merge-package`: fold a renamed or replaced package's records into its
+/// successor so they stop showing up as orphans.
+pub fn merge_package_cmd(old: &str, new: &str, dry_run: bool, json: bool) -> Result<()> {
+    if old == new {
+        anyhow::bail!("Cannot merge {} into itself", old);
+    }
+    let db = crate::db::Database::new()?;
+    let (created_records, accessed_records, rows_updated) = db.merge_package(old, new, dry_run)?;
+
+    if json {
+        print_json(&MergeResult {
+            old: old.to_string(),
+            new: new.to_string(),
+            dry_run,
+            created_records,
+            accessed_records,
+            rows_updated,
+        })?;
+        return Ok(());
+    }
+
+    if rows_updated == 0 {
+        println!("No records found for package: {}", old);
+        return Ok(());
+    }
+
+    let verb = if dry_run { "Would update" } else { "Updated" };
+    let rows = if use_color() { rows_updated.to_string().green().to_string() } else { rows_updated.to_string() };
+    println!(
+        "{} {} record(s): {} created by {}, {} last accessed by {} -> {}",
+        verb, rows, created_records, old, accessed_records, old, new
+    );
+    if dry_run {
+        println!("(dry run - database not modified)");
+    }
+    Ok(())
+}
+
 pub fn ignore_package_cmd(package: &str) -> Result<()> {
     let mut config = crate::config::Config::load()?;
     let color = use_color();
@@ -824,16 +3888,7 @@ pub fn ignore_package_cmd(package: &str) -> Result<()> {
 }
 
 pub fn exclude_path_cmd(path: &str) -> Result<()> {
-    let home = crate::db::get_user_home();
-    let expanded = if path.starts_with('/') {
-        path.to_string()
-    } else if let Some(rest) = path.strip_prefix("~/") {
-        home.join(rest).to_string_lossy().into_owned()
-    } else if path == "~" {
-        home.to_string_lossy().into_owned()
-    } else {
-        home.join(path).to_string_lossy().into_owned()
-    };
+    let expanded = crate::db::expand_user_path(path)?;
 
     let mut config = crate::config::Config::load()?;
     let color = use_color();
@@ -864,20 +3919,11 @@ pub fn recheck(json: bool) -> Result<()> {
     let color = use_color() && !json;
 
     if json {
-        #[derive(Serialize)]
-        struct Reassignment {
-            path: String,
-            old_package: String,
-            new_package: String,
-        }
-        let entries: Vec<Reassignment> = reassigned.iter().map(|(p, old, new)| Reassignment {
-            path: p.clone(), old_package: old.clone(), new_package: new.clone(),
-        }).collect();
-        println!("{}", serde_json::to_string_pretty(&serde_json::json!({
-            "reassigned": entries.len(),
+        print_json(&serde_json::json!({
+            "reassigned": reassigned.len(),
             "removed": removed,
-            "details": entries,
-        }))?);
+            "details": reassigned,
+        }))?;
         return Ok(());
     }
 
@@ -888,15 +3934,21 @@ pub fn recheck(json: bool) -> Result<()> {
 
     if !reassigned.is_empty() {
         println!("Reassigned {} file(s):", reassigned.len());
-        for (path, old_pkg, new_pkg) in &reassigned {
+        for r in &reassigned {
+            let ambiguity = if r.other_owners.is_empty() {
+                String::new()
+            } else {
+                format!(" (ambiguous: also owned by {})", r.other_owners.join(", "))
+            };
             if color {
-                println!("  {} {} -> {}",
-                    path,
-                    old_pkg.red(),
-                    new_pkg.green(),
+                println!("  {} {} -> {}{}",
+                    r.path,
+                    r.old_package.red(),
+                    r.new_package.green(),
+                    ambiguity.yellow(),
                 );
             } else {
-                println!("  {} {} -> {}", path, old_pkg, new_pkg);
+                println!("  {} {} -> {}{}", r.path, r.old_package, r.new_package, ambiguity);
             }
         }
     }
@@ -907,3 +3959,21 @@ pub fn recheck(json: bool) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn midnight_follows_the_given_timezone() {
+        let day = chrono::NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        assert_eq!(midnight_in(day, &Utc), Some(1_714_521_600));
+        let tokyo: Tz = "Asia/Tokyo".parse().unwrap();
+        assert_eq!(midnight_in(day, &tokyo), Some(1_714_521_600 - 9 * 3600));
+
+        // São Paulo skipped 00:00-01:00 on 2018-11-04; the day starts at 01:00 -02
+        let sao_paulo: Tz = "America/Sao_Paulo".parse().unwrap();
+        let gap = chrono::NaiveDate::from_ymd_opt(2018, 11, 4).unwrap();
+        assert_eq!(midnight_in(gap, &sao_paulo), Some(1_541_300_400));
+    }
+}